@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::utils;
+
+const MANIFEST_NAME: &str = ".halogen-manifest";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// Hash of the formatter and the rendered code, before formatting
+    pub input: u64,
+    /// Hash of the bytes actually written to disk
+    pub output: u64,
+}
+
+/// List of files produced by a previous generation run.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = BTreeMap::new();
+        for line in data.lines() {
+            let mut parts = line.splitn(3, ' ');
+            let (Some(input), Some(output), Some(path)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(io::Error::other(format!("malformed manifest line: {line}")));
+            };
+
+            let parse = |hash| {
+                u64::from_str_radix(hash, 16)
+                    .map_err(|_| io::Error::other(format!("malformed manifest hash: {hash}")))
+            };
+
+            // Stale entries get removed, never let them point outside the root
+            let path = PathBuf::from(path);
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(io::Error::other(format!(
+                    "invalid manifest path: {}",
+                    path.display()
+                )));
+            }
+
+            entries.insert(
+                path,
+                Entry {
+                    input: parse(input)?,
+                    output: parse(output)?,
+                },
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn dump(&self, path: &Path) -> io::Result<()> {
+        let mut data = Vec::new();
+        for (path, entry) in &self.entries {
+            writeln!(
+                data,
+                "{:016x} {:016x} {}",
+                entry.input,
+                entry.output,
+                path.display()
+            )?;
+        }
+
+        utils::write_if_changed(path, &data)?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Entry> {
+        self.entries.get(path).copied()
+    }
+
+    pub fn insert(&mut self, path: PathBuf, entry: Entry) {
+        self.entries.insert(path, entry);
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.entries.keys().map(PathBuf::as_path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Write changed files to disk
+    Write,
+    /// Only compare against the files on disk, without writing anything
+    Check,
+}
+
+/// A generated file which differs from the one on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub path: PathBuf,
+    pub kind: MismatchKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// The file does not exist on disk
+    Missing,
+    /// The file on disk has different content
+    Changed { expected: Vec<u8>, actual: Vec<u8> },
+    /// The file was generated by a previous run but is not generated anymore
+    Stale,
+}
+
+/// Writes generated files below a root directory, skipping the ones whose
+/// content did not change since the last run and removing stale ones.
+pub struct Emitter {
+    root: PathBuf,
+    mode: Mode,
+    old: Manifest,
+    new: Mutex<Manifest>,
+    mismatches: Mutex<Vec<Mismatch>>,
+}
+
+impl Emitter {
+    pub fn new(root: impl AsRef<Path>, mode: Mode) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let old = Manifest::load(&root.join(MANIFEST_NAME))?;
+
+        Ok(Self {
+            root,
+            mode,
+            old,
+            new: Mutex::default(),
+            mismatches: Mutex::default(),
+        })
+    }
+
+    /// Emit a file, the path is relative to the root. `format` is only
+    /// invoked if the rendered data or the `formatter` identifying it differ
+    /// from the previous run.
+    pub fn emit<F>(
+        &self,
+        path: impl AsRef<Path>,
+        data: Vec<u8>,
+        formatter: &str,
+        format: F,
+    ) -> io::Result<()>
+    where
+        F: FnOnce(Vec<u8>) -> io::Result<Vec<u8>>,
+    {
+        let path = path.as_ref();
+        let full_path = self.root.join(path);
+        let input = utils::hash_extend(utils::hash(formatter.as_bytes()), &data);
+
+        if self.mode == Mode::Check {
+            // Always go through the whole pipeline, the manifest could lie
+            let expected = format(data)?;
+            self.record(
+                path,
+                Entry {
+                    input,
+                    output: utils::hash(&expected),
+                },
+            );
+
+            let kind = match fs::read(&full_path) {
+                Ok(actual) if actual == expected => None,
+                Ok(actual) => Some(MismatchKind::Changed { expected, actual }),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Some(MismatchKind::Missing),
+                Err(err) => return Err(err),
+            };
+
+            if let Some(kind) = kind {
+                self.mismatches.lock().unwrap().push(Mismatch {
+                    path: path.to_path_buf(),
+                    kind,
+                });
+            }
+
+            return Ok(());
+        }
+
+        if let Some(entry) = self.old.get(path)
+            && entry.input == input
+            && utils::hash_file(&full_path)? == Some(entry.output)
+        {
+            self.record(path, entry);
+            return Ok(());
+        }
+
+        let data = format(data)?;
+        let entry = Entry {
+            input,
+            output: utils::hash(&data),
+        };
+
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        utils::write_if_changed(&full_path, &data)?;
+        self.record(path, entry);
+
+        Ok(())
+    }
+
+    /// Remove files generated by the previous run but not by this one, then
+    /// store the new manifest. In check mode nothing is touched, and the
+    /// files which are not up to date are returned instead.
+    pub fn finish(self) -> io::Result<Vec<Mismatch>> {
+        let new = self.new.into_inner().unwrap();
+        let mut mismatches = self.mismatches.into_inner().unwrap();
+
+        for path in self.old.paths() {
+            if new.get(path).is_some() {
+                continue;
+            }
+
+            if self.mode == Mode::Check {
+                if self.root.join(path).exists() {
+                    mismatches.push(Mismatch {
+                        path: path.to_path_buf(),
+                        kind: MismatchKind::Stale,
+                    });
+                }
+
+                continue;
+            }
+
+            log::debug!("removing stale file {}", path.display());
+            match fs::remove_file(self.root.join(path)) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if self.mode == Mode::Write {
+            new.dump(&self.root.join(MANIFEST_NAME))?;
+        }
+
+        mismatches.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(mismatches)
+    }
+
+    fn record(&self, path: &Path, entry: Entry) {
+        self.new.lock().unwrap().insert(path.to_path_buf(), entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("halogen-emit-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn upper(data: Vec<u8>) -> io::Result<Vec<u8>> {
+        Ok(data.to_ascii_uppercase())
+    }
+
+    fn unreachable(_: Vec<u8>) -> io::Result<Vec<u8>> {
+        panic!("unchanged file formatted again")
+    }
+
+    #[test]
+    fn write_rewrite_stale() {
+        let root = temp_dir("cycle");
+
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "upper", upper).unwrap();
+        emitter.emit("b.rs", b"b".to_vec(), "upper", upper).unwrap();
+        emitter.finish().unwrap();
+
+        assert_eq!(fs::read(root.join("a.rs")).unwrap(), b"A");
+        assert_eq!(fs::read(root.join("b.rs")).unwrap(), b"B");
+
+        let manifest = Manifest::load(&root.join(MANIFEST_NAME)).unwrap();
+        assert_eq!(
+            manifest.paths().collect::<Vec<_>>(),
+            [Path::new("a.rs"), Path::new("b.rs")]
+        );
+
+        // Same input and formatter, the formatter is skipped
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter
+            .emit("a.rs", b"a".to_vec(), "upper", unreachable)
+            .unwrap();
+        emitter
+            .emit("b.rs", b"b".to_vec(), "upper", unreachable)
+            .unwrap();
+        emitter.finish().unwrap();
+
+        // A different formatter runs again, b.rs is no longer generated
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "none", Ok).unwrap();
+        emitter.finish().unwrap();
+
+        assert_eq!(fs::read(root.join("a.rs")).unwrap(), b"a");
+        assert!(!root.join("b.rs").exists());
+
+        let manifest = Manifest::load(&root.join(MANIFEST_NAME)).unwrap();
+        assert_eq!(manifest.paths().collect::<Vec<_>>(), [Path::new("a.rs")]);
+    }
+
+    #[test]
+    fn rewrite_modified_output() {
+        let root = temp_dir("modified");
+
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "upper", upper).unwrap();
+        emitter.finish().unwrap();
+
+        fs::write(root.join("a.rs"), b"edited").unwrap();
+
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "upper", upper).unwrap();
+        emitter.finish().unwrap();
+
+        assert_eq!(fs::read(root.join("a.rs")).unwrap(), b"A");
+    }
+
+    #[test]
+    fn manifest_rejects_paths_outside_root() {
+        let root = temp_dir("outside");
+        let path = root.join(MANIFEST_NAME);
+
+        for entry in ["/etc/passwd", "../a.rs", "chips/../../a.rs"] {
+            fs::write(&path, format!("{:016x} {:016x} {entry}\n", 0, 0)).unwrap();
+
+            let err = Manifest::load(&path).unwrap_err();
+            assert_eq!(err.to_string(), format!("invalid manifest path: {entry}"));
+        }
+
+        fs::write(&path, format!("{:016x} {:016x} chips/a.rs\n", 0, 0)).unwrap();
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(
+            manifest.paths().collect::<Vec<_>>(),
+            [Path::new("chips/a.rs")]
+        );
+    }
+
+    #[test]
+    fn check_mode() {
+        let root = temp_dir("check");
+
+        let emitter = Emitter::new(&root, Mode::Write).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "upper", upper).unwrap();
+        emitter.emit("b.rs", b"b".to_vec(), "upper", upper).unwrap();
+        emitter.emit("c.rs", b"c".to_vec(), "upper", upper).unwrap();
+        assert_eq!(emitter.finish().unwrap(), []);
+
+        let manifest = fs::read(root.join(MANIFEST_NAME)).unwrap();
+        fs::remove_file(root.join("b.rs")).unwrap();
+
+        // a.rs changed, b.rs is missing and c.rs is stale, the formatter
+        // always runs since the manifest could be out of date
+        let emitter = Emitter::new(&root, Mode::Check).unwrap();
+        emitter.emit("a.rs", b"x".to_vec(), "upper", upper).unwrap();
+        emitter.emit("b.rs", b"b".to_vec(), "upper", upper).unwrap();
+        let mismatches = emitter.finish().unwrap();
+
+        assert_eq!(
+            mismatches,
+            [
+                Mismatch {
+                    path: "a.rs".into(),
+                    kind: MismatchKind::Changed {
+                        expected: b"X".to_vec(),
+                        actual: b"A".to_vec(),
+                    },
+                },
+                Mismatch {
+                    path: "b.rs".into(),
+                    kind: MismatchKind::Missing,
+                },
+                Mismatch {
+                    path: "c.rs".into(),
+                    kind: MismatchKind::Stale,
+                },
+            ]
+        );
+
+        // Nothing is touched on disk
+        assert_eq!(fs::read(root.join("a.rs")).unwrap(), b"A");
+        assert!(!root.join("b.rs").exists());
+        assert_eq!(fs::read(root.join("c.rs")).unwrap(), b"C");
+        assert_eq!(fs::read(root.join(MANIFEST_NAME)).unwrap(), manifest);
+
+        // Matching files report nothing, b.rs is not stale since it is gone
+        let emitter = Emitter::new(&root, Mode::Check).unwrap();
+        emitter.emit("a.rs", b"a".to_vec(), "upper", upper).unwrap();
+        emitter.emit("c.rs", b"c".to_vec(), "upper", upper).unwrap();
+        assert_eq!(emitter.finish().unwrap(), []);
+    }
+}
@@ -1,3 +1,4 @@
+mod emit;
 mod utils;
 
 #[cfg(feature = "rust")]
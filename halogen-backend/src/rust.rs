@@ -1,17 +1,24 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{ChildStdin, Command, Stdio};
+use std::sync::OnceLock;
 
 use halogen_ir::ir;
+use halogen_ir::visit::{self, VisitorMut};
 use heck::*;
 use tera::Tera;
 
+use crate::emit::{Emitter, Mode};
 use crate::utils;
 use crate::utils::rayon_prelude::*;
 
+pub use crate::emit::{Mismatch, MismatchKind};
+
+pub mod bit_band;
+pub mod codegen;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Utils {
     Super,
@@ -30,18 +37,39 @@ fn to_tera_utils(utils: Utils) -> &'static str {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Format {
     Rustfmt,
+    Prettyplease,
     None,
 }
 
+/// Which generator produces chip and module files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+    /// Render the Tera templates
+    Tera,
+    /// Build the code directly with `quote`, see [`codegen`]
+    Quote,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GenMultiChipSettings<'a> {
     pub utils: Utils,
     pub format: Format,
+    pub generator: Generator,
     pub core_path: Option<&'a str>,
     pub gen_chips: bool,
     pub gen_list: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenCrateSettings<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub edition: &'a str,
+    pub format: Format,
+    pub generator: Generator,
+    pub core_path: Option<&'a str>,
+}
+
 pub struct GenCtx {
     tera: Tera,
 }
@@ -63,17 +91,116 @@ impl GenCtx {
         root: impl AsRef<Path>,
         settings: GenMultiChipSettings<'_>,
     ) -> io::Result<()> {
-        let root = root.as_ref();
-        let chips_path = root.join("chips");
-        let modules_path = root.join("modules");
+        let emitter = Emitter::new(root, Mode::Write)?;
+        self.emit_multi_chip(&emitter, multi, Path::new(""), settings)?;
+        emitter.finish()?;
+
+        Ok(())
+    }
+
+    /// Like [`Self::gen_multi_chip`], but only compare the generated code
+    /// against the one on disk, returning the files which are not up to date.
+    pub fn check_multi_chip(
+        &self,
+        multi: &ir::MultiChip,
+        root: impl AsRef<Path>,
+        settings: GenMultiChipSettings<'_>,
+    ) -> io::Result<Vec<Mismatch>> {
+        let emitter = Emitter::new(root, Mode::Check)?;
+        self.emit_multi_chip(&emitter, multi, Path::new(""), settings)?;
+        emitter.finish()
+    }
+
+    pub fn gen_crate(
+        &self,
+        multi: &ir::MultiChip,
+        root: impl AsRef<Path>,
+        settings: GenCrateSettings<'_>,
+    ) -> io::Result<()> {
+        let emitter = Emitter::new(root, Mode::Write)?;
+        self.emit_crate(&emitter, multi, settings)?;
+        emitter.finish()?;
+
+        Ok(())
+    }
+
+    /// Like [`Self::gen_crate`], but only compare the generated code against
+    /// the one on disk, returning the files which are not up to date.
+    pub fn check_crate(
+        &self,
+        multi: &ir::MultiChip,
+        root: impl AsRef<Path>,
+        settings: GenCrateSettings<'_>,
+    ) -> io::Result<Vec<Mismatch>> {
+        let emitter = Emitter::new(root, Mode::Check)?;
+        self.emit_crate(&emitter, multi, settings)?;
+        emitter.finish()
+    }
+
+    fn emit_crate(
+        &self,
+        emitter: &Emitter,
+        multi: &ir::MultiChip,
+        settings: GenCrateSettings<'_>,
+    ) -> io::Result<()> {
+        let src_path = Path::new("src");
+
+        // Remove trailing / in core_path
+        let core_path = settings.core_path.map(|path| path.trim_end_matches("/"));
+
+        // Sources are generated as usual, lib.rs takes the place of chips.rs
+        self.emit_multi_chip(
+            emitter,
+            multi,
+            src_path,
+            GenMultiChipSettings {
+                utils: Utils::None,
+                format: settings.format,
+                generator: settings.generator,
+                core_path,
+                gen_chips: false,
+                gen_list: true,
+            },
+        )?;
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("chips", &multi.chips);
+        ctx.insert("root", ".");
+        ctx.insert("core_path", &core_path);
+        ctx.insert("utils", to_tera_utils(Utils::None));
+        ctx.insert("name", settings.name);
+        ctx.insert("version", settings.version);
+        ctx.insert("edition", settings.edition);
+
+        let files = [
+            ("crate_lib.tera", src_path.join("lib.rs"), settings.format),
+            ("utils.rs", src_path.join("utils.rs"), settings.format),
+            ("build.rs", "build.rs".into(), settings.format),
+            ("cargo.tera", "Cargo.toml".into(), Format::None),
+            ("readme.tera", "README.md".into(), Format::None),
+        ];
+
+        for (file, path, format) in files {
+            self.emit(emitter, path, file, settings.name, &ctx, format)?;
+        }
+
+        Ok(())
+    }
+
+    fn emit_multi_chip(
+        &self,
+        emitter: &Emitter,
+        multi: &ir::MultiChip,
+        base: &Path,
+        settings: GenMultiChipSettings<'_>,
+    ) -> io::Result<()> {
+        let chips_path = base.join("chips");
+        let modules_path = base.join("modules");
 
         // Remove trailing / in core_path
         let core_path = settings.core_path.map(|path| path.trim_end_matches("/"));
 
-        // First create necessary directories
-        utils::create_dir_if_not_exist(&root)?;
-        utils::create_dir_if_not_exist(&chips_path)?;
-        utils::create_dir_if_not_exist(&modules_path)?;
+        let bit_band_modules = bit_band_modules(multi);
 
         let (res1, (res2, (res3, res4))) = utils::maybe_par_multi_join! {
             || {
@@ -81,10 +208,8 @@ impl GenCtx {
                     let mut ctx = tera::Context::new();
                     ctx.insert("chips", &multi.chips);
 
-                    let path = root.join("list.rs");
-                    let out = io::BufWriter::new(fs::File::create(path)?);
-
-                    render_with_fmt(&self.tera, "list.tera", &ctx, settings.format, out)
+                    let path = base.join("list.rs");
+                    self.emit(emitter, path, "list.tera", "chips list", &ctx, settings.format)
                 } else {
                     Ok(())
                 }
@@ -97,10 +222,8 @@ impl GenCtx {
                     ctx.insert("core_path", &core_path);
                     ctx.insert("utils", to_tera_utils(settings.utils));
 
-                    let path = root.join("chips.rs");
-                    let out = io::BufWriter::new(fs::File::create(path)?);
-
-                    render_with_fmt(&self.tera, "chips.tera", &ctx, settings.format, out)
+                    let path = base.join("chips.rs");
+                    self.emit(emitter, path, "chips.tera", "chips", &ctx, settings.format)
                 } else {
                     Ok(())
                 }
@@ -108,12 +231,24 @@ impl GenCtx {
             || {
                 utils::into_maybe_par_iter(&multi.chips).try_for_each(
                     |chip| -> io::Result<()> {
-                        let name = escape_keyword(chip.name.to_snake_case().into());
+                        let path = chips_path.join(chip_file_name(chip));
+                        let format = settings.format;
+                        let aliases = bit_band::aliases(multi, chip)?;
 
-                        let path = chips_path.join(format!("{name}.rs"));
-                        let out = io::BufWriter::new(fs::File::create(path)?);
-
-                        self.gen_chip(chip, Some(".."), Utils::Super, settings.format, out)
+                        match settings.generator {
+                            Generator::Tera => {
+                                let ctx = chip_ctx(chip, Some(".."), Utils::Super, &aliases);
+                                self.emit(emitter, path, "chip.tera", &chip.name, &ctx, format)
+                            }
+                            Generator::Quote => {
+                                let data =
+                                    codegen::chip(chip, Some(".."), Utils::Super, &aliases)?;
+                                let data = data.to_string().into_bytes();
+                                emitter.emit(path, data, &formatter_id(format), |data| {
+                                    format_code(data, format, "codegen", &chip.name)
+                                })
+                            }
+                        }
                     },
                 )
             },
@@ -129,9 +264,23 @@ impl GenCtx {
                         let name = escape_keyword(name.to_snake_case().into());
 
                         let path = modules_path.join(format!("{name}.rs"));
-                        let out = io::BufWriter::new(fs::File::create(path)?);
+                        let format = settings.format;
+                        let bit_band = bit_band_modules
+                            .contains(&(module.name.as_str(), module.version.as_deref()));
 
-                        self.gen_module(module, Utils::Super, settings.format, out)
+                        match settings.generator {
+                            Generator::Tera => {
+                                let ctx = module_ctx(module, Utils::Super, bit_band);
+                                self.emit(emitter, path, "module.tera", &name, &ctx, format)
+                            }
+                            Generator::Quote => {
+                                let data = codegen::module(module, Utils::Super, bit_band)?;
+                                let data = data.to_string().into_bytes();
+                                emitter.emit(path, data, &formatter_id(format), |data| {
+                                    format_code(data, format, "codegen", &name)
+                                })
+                            }
+                        }
                     },
                 )
             }
@@ -145,6 +294,21 @@ impl GenCtx {
         Ok(())
     }
 
+    fn emit(
+        &self,
+        emitter: &Emitter,
+        path: PathBuf,
+        file: &str,
+        item: &str,
+        ctx: &tera::Context,
+        format: Format,
+    ) -> io::Result<()> {
+        let data = render(&self.tera, file, item, ctx)?;
+        emitter.emit(path, data, &formatter_id(format), |data| {
+            format_code(data, format, file, item)
+        })
+    }
+
     pub fn gen_chip(
         &self,
         chip: &ir::Chip,
@@ -153,12 +317,54 @@ impl GenCtx {
         format: Format,
         out: impl Write,
     ) -> io::Result<()> {
+        let ctx = chip_ctx(chip, root, utils, &[]);
+        render_with_fmt(&self.tera, "chip.tera", &chip.name, &ctx, format, out)
+    }
+
+    /// Like [`Self::gen_chip`], but also generate the bit-band aliases of the
+    /// chip peripherals, which need the modules of `multi`.
+    pub fn gen_chip_in(
+        &self,
+        multi: &ir::MultiChip,
+        chip: &ir::Chip,
+        root: Option<&str>,
+        utils: Utils,
+        format: Format,
+        out: impl Write,
+    ) -> io::Result<()> {
+        let aliases = bit_band::aliases(multi, chip)?;
+        let ctx = chip_ctx(chip, root, utils, &aliases);
+        render_with_fmt(&self.tera, "chip.tera", &chip.name, &ctx, format, out)
+    }
+
+    /// Generate a self-contained file for a single chip, inlining only the
+    /// modules items actually reachable from its peripherals.
+    pub fn gen_single_chip(
+        &self,
+        multi: &ir::MultiChip,
+        chip: &ir::Chip,
+        utils: Utils,
+        format: Format,
+        out: impl Write,
+    ) -> io::Result<()> {
+        let modules = reachable_modules(multi, chip)?;
+        let aliases = bit_band::aliases(multi, chip)?;
+
         let mut ctx = tera::Context::new();
         ctx.insert("chip", chip);
-        ctx.insert("root", &root);
+        ctx.insert("modules", &modules);
         ctx.insert("utils", to_tera_utils(utils));
+        ctx.insert("bit_band", &has_bit_band(chip));
+        ctx.insert("aliases", &aliases);
 
-        render_with_fmt(&self.tera, "chip.tera", &ctx, format, out)
+        render_with_fmt(
+            &self.tera,
+            "single_chip.tera",
+            &chip.name,
+            &ctx,
+            format,
+            out,
+        )
     }
 
     pub fn gen_module(
@@ -168,11 +374,241 @@ impl GenCtx {
         format: Format,
         out: impl Write,
     ) -> io::Result<()> {
-        let mut ctx = tera::Context::new();
-        ctx.insert("module", module);
-        ctx.insert("utils", to_tera_utils(utils));
+        let ctx = module_ctx(module, utils, false);
+        render_with_fmt(&self.tera, "module.tera", &module.name, &ctx, format, out)
+    }
 
-        render_with_fmt(&self.tera, "module.tera", &ctx, format, out)
+    /// Like [`Self::gen_module`], but also generate the bit-band helpers of
+    /// single bit fields if the module is used by a chip of `multi` with
+    /// bit-band regions.
+    pub fn gen_module_in(
+        &self,
+        multi: &ir::MultiChip,
+        module: &ir::Module,
+        utils: Utils,
+        format: Format,
+        out: impl Write,
+    ) -> io::Result<()> {
+        let bit_band =
+            bit_band_modules(multi).contains(&(module.name.as_str(), module.version.as_deref()));
+        let ctx = module_ctx(module, utils, bit_band);
+        render_with_fmt(&self.tera, "module.tera", &module.name, &ctx, format, out)
+    }
+}
+
+fn chip_ctx(
+    chip: &ir::Chip,
+    root: Option<&str>,
+    utils: Utils,
+    aliases: &[bit_band::Peripheral],
+) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    ctx.insert("chip", chip);
+    ctx.insert("root", &root);
+    ctx.insert("utils", to_tera_utils(utils));
+    ctx.insert("aliases", aliases);
+    ctx
+}
+
+fn module_ctx(module: &ir::Module, utils: Utils, bit_band: bool) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    ctx.insert("module", module);
+    ctx.insert("utils", to_tera_utils(utils));
+    ctx.insert("bit_band", &bit_band);
+    ctx
+}
+
+fn has_bit_band(chip: &ir::Chip) -> bool {
+    chip.cm_ext.as_ref().is_some_and(|cm_ext| cm_ext.bit_band)
+}
+
+/// Modules imported by at least one chip with bit-band regions, their files
+/// are shared with the other chips importing them.
+fn bit_band_modules(multi: &ir::MultiChip) -> HashSet<(&str, Option<&str>)> {
+    multi
+        .chips
+        .iter()
+        .filter(|chip| has_bit_band(chip))
+        .flat_map(|chip| &chip.imports)
+        .map(|import| (import.name.as_str(), import.version.as_deref()))
+        .collect()
+}
+
+/// Collect the modules imported by a chip, stripped of every block, bitfield
+/// and enum not reachable from the chip peripherals.
+fn reachable_modules(multi: &ir::MultiChip, chip: &ir::Chip) -> io::Result<Vec<ir::Module>> {
+    let mut out = Vec::new();
+    for import in &chip.imports {
+        let module = find_module(multi, chip, import)?;
+
+        let mut blocks = chip
+            .peripherals
+            .iter()
+            .filter(|peripheral| peripheral.module == import.name)
+            .map(|peripheral| peripheral.block_name.as_str())
+            .collect::<Vec<_>>();
+
+        let mut bitfields = chip
+            .cm_ext
+            .iter()
+            .flat_map(|cm_ext| &cm_ext.cm_regs)
+            .filter(|cm_reg| cm_reg.module == import.name)
+            .map(|cm_reg| cm_reg.bitfield_name.as_str())
+            .collect::<HashSet<_>>();
+        bitfields.extend(
+            chip.riscv_ext
+                .iter()
+                .flat_map(|riscv_ext| &riscv_ext.csrs)
+                .filter(|csr| csr.module == import.name)
+                .map(|csr| csr.bitfield_name.as_str()),
+        );
+
+        let mut enums = HashSet::new();
+        let mut visited = HashSet::new();
+
+        // Walk blocks transitively, sub-blocks can reference other blocks
+        while let Some(name) = blocks.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+
+            let Some(block) = module.blocks.iter().find(|block| block.name == name) else {
+                continue;
+            };
+
+            for field in &block.fields {
+                match &field.inner {
+                    ir::block::FieldInner::Block(inner) => blocks.push(&inner.block_name),
+                    ir::block::FieldInner::Bitfield(inner) => {
+                        bitfields.insert(&inner.bitfield_name);
+                    }
+                    ir::block::FieldInner::Simple(inner) => {
+                        enums.extend(inner.enum_name.as_deref());
+                    }
+                }
+            }
+        }
+
+        for bitfield in &module.bitfields {
+            if bitfields.contains(bitfield.name.as_str()) {
+                enums.extend(
+                    bitfield
+                        .fields
+                        .iter()
+                        .filter_map(|field| field.enum_name.as_deref()),
+                );
+            }
+        }
+
+        out.push(ir::Module {
+            name: module.name.clone(),
+            version: module.version.clone(),
+            description: module.description.clone(),
+            blocks: module
+                .blocks
+                .iter()
+                .filter(|block| visited.contains(block.name.as_str()))
+                .cloned()
+                .collect(),
+            bitfields: module
+                .bitfields
+                .iter()
+                .filter(|bitfield| bitfields.contains(bitfield.name.as_str()))
+                .cloned()
+                .collect(),
+            enums: module
+                .enums
+                .iter()
+                .filter(|enum_name| enums.contains(enum_name.name.as_str()))
+                .cloned()
+                .collect(),
+            skipped: Vec::new(),
+            source: module.source.clone(),
+        });
+    }
+
+    Ok(out)
+}
+
+fn find_module<'a>(
+    multi: &'a ir::MultiChip,
+    chip: &ir::Chip,
+    import: &ir::chip::Import,
+) -> io::Result<&'a ir::Module> {
+    multi
+        .modules
+        .iter()
+        .find(|module| module.name == import.name && module.version == import.version)
+        .ok_or_else(|| {
+            io::Error::other(format!(
+                "module {} imported by chip {} not found",
+                import.name, chip.name
+            ))
+        })
+}
+
+/// Name of the file generated for a chip, the snake case name also used by
+/// the `target_chip` cfg of chips.rs.
+pub fn chip_file_name(chip: &ir::Chip) -> String {
+    format!("{}.rs", escape_keyword(chip.name.to_snake_case().into()))
+}
+
+/// Append the source of every item to its description, so it shows up in the
+/// documentation of the generated code.
+pub fn add_source_docs(multi: &mut ir::MultiChip) {
+    struct SourceDocs;
+
+    impl VisitorMut for SourceDocs {
+        fn visit_peripheral_mut(
+            &mut self,
+            _path: &visit::Path,
+            peripheral: &mut ir::chip::Peripheral,
+        ) {
+            source_doc(&mut peripheral.description, &peripheral.source);
+        }
+
+        fn visit_module_mut(&mut self, path: &visit::Path, module: &mut ir::Module) {
+            source_doc(&mut module.description, &module.source);
+            visit::walk_module_mut(self, path, module);
+        }
+
+        fn visit_block_mut(&mut self, path: &visit::Path, block: &mut ir::Block) {
+            source_doc(&mut block.description, &block.source);
+            visit::walk_block_mut(self, path, block);
+        }
+
+        fn visit_block_field_mut(&mut self, _path: &visit::Path, field: &mut ir::block::Field) {
+            source_doc(&mut field.description, &field.source);
+        }
+
+        fn visit_bitfield_mut(&mut self, path: &visit::Path, bitfield: &mut ir::Bitfield) {
+            source_doc(&mut bitfield.description, &bitfield.source);
+            visit::walk_bitfield_mut(self, path, bitfield);
+        }
+
+        fn visit_bitfield_field_mut(
+            &mut self,
+            _path: &visit::Path,
+            field: &mut ir::bitfield::Field,
+        ) {
+            source_doc(&mut field.description, &field.source);
+        }
+
+        fn visit_enum_mut(&mut self, _path: &visit::Path, enum_name: &mut ir::Enum) {
+            source_doc(&mut enum_name.description, &enum_name.source);
+        }
+    }
+
+    SourceDocs.visit_multi_chip_mut(multi);
+}
+
+fn source_doc(description: &mut Option<String>, source: &Option<ir::Source>) {
+    if let Some(source) = source {
+        let doc = format!("Source: {source}");
+        *description = Some(match description.take() {
+            Some(description) => format!("{description}\n\n{doc}"),
+            None => doc,
+        });
     }
 }
 
@@ -229,36 +665,103 @@ fn tera() -> Tera {
         ),
         ("enum.tera", include_str!("rust/templates/enum.tera")),
         ("cm_reg.tera", include_str!("rust/templates/cm_reg.tera")),
+        ("csr.tera", include_str!("rust/templates/csr.tera")),
+        (
+            "bit_band.tera",
+            include_str!("rust/templates/bit_band.tera"),
+        ),
         ("chips.tera", include_str!("rust/templates/chips.tera")),
         ("list.tera", include_str!("rust/templates/list.tera")),
+        (
+            "single_chip.tera",
+            include_str!("rust/templates/single_chip.tera"),
+        ),
+        ("build.rs", include_str!("rust/templates/build.rs")),
+        (
+            "crate_lib.tera",
+            include_str!("rust/templates/crate_lib.tera"),
+        ),
+        ("cargo.tera", include_str!("rust/templates/cargo.tera")),
+        ("readme.tera", include_str!("rust/templates/readme.tera")),
     ])
     .expect("Failed to compile tera templates");
 
     tera
 }
 
+/// Render a template and format it, `item` names the IR item the template
+/// was rendered for, and is only used to report errors.
 fn render_with_fmt(
     tera: &Tera,
     file: &str,
+    item: &str,
     ctx: &tera::Context,
     format: Format,
-    out: impl Write,
+    mut out: impl Write,
 ) -> io::Result<()> {
-    // TODO: Better handle Tera errors
+    let data = render(tera, file, item, ctx)?;
+    out.write_all(&format_code(data, format, file, item)?)
+}
+
+fn render(tera: &Tera, file: &str, item: &str, ctx: &tera::Context) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    tera.render_to(file, ctx, &mut out)
+        .map_err(|err| utils::tera_error(file, item, err))?;
+    Ok(out)
+}
+
+fn format_code(data: Vec<u8>, format: Format, file: &str, item: &str) -> io::Result<Vec<u8>> {
+    let res = match format {
+        Format::Rustfmt => {
+            let mut out = Vec::new();
+            run_with_rustfmt(|stdin| stdin.write_all(&data), &mut out).map(|_| out)
+        }
+        Format::Prettyplease => run_with_prettyplease(data),
+        Format::None => Ok(data),
+    };
+
+    res.map_err(|err| io::Error::new(err.kind(), format!("{file} for {item}: {err}")))
+}
+
+fn run_with_prettyplease(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let code = String::from_utf8(data)
+        .map_err(|_| io::Error::other("template outputted non unicode characters"))?;
+
+    let file = syn::parse_file(&code).map_err(|err| {
+        let start = err.span().start();
+        io::Error::other(format!(
+            "generated code does not parse at {}:{}: {err}",
+            start.line,
+            start.column + 1
+        ))
+    })?;
+
+    Ok(prettyplease::unparse(&file).into_bytes())
+}
+
+/// Identifies the formatter in the manifest of generated files, so that
+/// switching formatters or upgrading rustfmt formats the files again.
+fn formatter_id(format: Format) -> Cow<'static, str> {
     match format {
-        Format::Rustfmt => run_with_rustfmt(
-            |out| {
-                tera.render_to(file, ctx, out)
-                    .map_err(utils::unwrap_tera_error)?;
-                Ok(())
-            },
-            out,
-        ),
-        Format::None => {
-            tera.render_to(file, ctx, out)
-                .map_err(utils::unwrap_tera_error)?;
-            Ok(())
+        Format::Rustfmt => {
+            static ID: OnceLock<String> = OnceLock::new();
+
+            let id = ID.get_or_init(|| {
+                let rustfmt = std::env::var_os("RUSTFMT").unwrap_or_else(|| From::from("rustfmt"));
+                let version = Command::new(&rustfmt)
+                    .arg("--version")
+                    .output()
+                    .ok()
+                    .and_then(|output| String::from_utf8(output.stdout).ok())
+                    .unwrap_or_default();
+
+                format!("rustfmt {} {}", rustfmt.to_string_lossy(), version.trim())
+            });
+
+            Cow::Borrowed(id)
         }
+        Format::Prettyplease => Cow::Borrowed("prettyplease"),
+        Format::None => Cow::Borrowed("none"),
     }
 }
 
@@ -269,7 +772,7 @@ where
     let rustfmt = std::env::var_os("RUSTFMT").unwrap_or_else(|| From::from("rustfmt"));
 
     let mut child = Command::new(rustfmt)
-        .args(&[
+        .args([
             "--emit",
             "stdout",
             "--color",
@@ -301,3 +804,368 @@ where
     out.write_all(&output.stdout)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn multi() -> ir::MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "STM32F0",
+                "peripherals": [
+                    { "name": "GPIOA", "module": "gpio", "address": 0x4800_0000u32, "block_name": "Gpio" },
+                ],
+                "imports": [{ "name": "gpio", "version": "v2" }],
+            }],
+            "modules": [
+                {
+                    "name": "gpio",
+                    "version": "v1",
+                    "blocks": [{ "name": "Gpio", "fields": [] }],
+                    "bitfields": [],
+                    "enums": [],
+                },
+                {
+                    "name": "gpio",
+                    "version": "v2",
+                    "blocks": [
+                        {
+                            "name": "Gpio",
+                            "fields": [
+                                { "name": "moder", "byte_offset": 0, "access": "rw", "bitfield_name": "Moder" },
+                                { "name": "bank", "byte_offset": 4, "block_name": "Bank" },
+                            ],
+                        },
+                        {
+                            "name": "Bank",
+                            "fields": [
+                                { "name": "odr", "byte_offset": 0, "access": "rw", "bit_size": 32, "enum_name": "Odr" },
+                            ],
+                        },
+                        { "name": "Unused", "fields": [] },
+                    ],
+                    "bitfields": [
+                        {
+                            "name": "Moder",
+                            "bit_size": 32,
+                            "fields": [{ "name": "mode", "bit_offset": 0, "bit_size": 2, "enum_name": "Mode" }],
+                        },
+                        { "name": "Unused", "bit_size": 32, "fields": [] },
+                    ],
+                    "enums": [
+                        { "name": "Mode", "bit_size": 2, "variants": [{ "name": "Input", "value": 0 }] },
+                        { "name": "Odr", "bit_size": 32, "variants": [] },
+                        { "name": "Unused", "bit_size": 1, "variants": [] },
+                    ],
+                },
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reachable_modules_follow_import_version() {
+        let multi = multi();
+        let modules = reachable_modules(&multi, &multi.chips[0]).unwrap();
+
+        assert_eq!(modules.len(), 1);
+        let module = &modules[0];
+        assert_eq!(module.version.as_deref(), Some("v2"));
+
+        let names = |items: Vec<&String>| items.into_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            names(module.blocks.iter().map(|block| &block.name).collect()),
+            ["Gpio", "Bank"]
+        );
+        assert_eq!(
+            names(
+                module
+                    .bitfields
+                    .iter()
+                    .map(|bitfield| &bitfield.name)
+                    .collect()
+            ),
+            ["Moder"]
+        );
+        assert_eq!(
+            names(
+                module
+                    .enums
+                    .iter()
+                    .map(|enum_name| &enum_name.name)
+                    .collect()
+            ),
+            ["Mode", "Odr"]
+        );
+    }
+
+    #[test]
+    fn reachable_modules_missing_import() {
+        let mut multi = multi();
+        multi.chips[0].imports[0].version = Some("v3".into());
+
+        let err = reachable_modules(&multi, &multi.chips[0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "module gpio imported by chip STM32F0 not found"
+        );
+    }
+
+    #[test]
+    fn single_chip_is_self_contained() {
+        let multi = multi();
+        let mut out = Vec::new();
+        GenCtx::new()
+            .gen_single_chip(
+                &multi,
+                &multi.chips[0],
+                Utils::Embed,
+                Format::None,
+                &mut out,
+            )
+            .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("pub mod gpio"));
+        assert!(!out.contains("Unused"));
+    }
+
+    #[test]
+    fn chip_file_name_escapes_keywords() {
+        let chip = |name: &str| ir::Chip {
+            name: name.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(chip_file_name(&chip("STM32F030")), "stm32f030.rs");
+        assert_eq!(chip_file_name(&chip("Self")), "self_.rs");
+    }
+
+    #[test]
+    fn render_error_names_template_and_item() {
+        let ctx = GenCtx::new();
+        let err = render(&ctx.tera, "chip.tera", "STM32F0", &tera::Context::new()).unwrap_err();
+
+        let msg = err.to_string();
+        assert!(msg.starts_with("chip.tera for STM32F0: "), "{msg}");
+        assert!(msg.contains("chip"), "{msg}");
+    }
+
+    #[test]
+    fn prettyplease_format() {
+        let out = format_code(b"fn  main(){}".to_vec(), Format::Prettyplease, "a", "b").unwrap();
+        assert_eq!(out, b"fn main() {}\n");
+
+        let err = format_code(
+            b"fn main() {\n    let = 1;\n}".to_vec(),
+            Format::Prettyplease,
+            "chip.tera",
+            "STM32F0",
+        )
+        .unwrap_err();
+
+        let msg = err.to_string();
+        assert!(
+            msg.starts_with("chip.tera for STM32F0: generated code does not parse at 2:"),
+            "{msg}"
+        );
+    }
+
+    fn tim() -> ir::MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "STM32F103",
+                "description": "Cortex-M3 chip",
+                "peripherals": [
+                    { "name": "TIM2", "description": "Timer 2", "module": "tim", "address": 0x4000_0000u32, "block_name": "Tim" },
+                    { "name": "TIM3", "module": "tim", "address": 0x4000_0400u32, "block_name": "Tim" },
+                    { "name": "TIM9", "module": "tim", "address": 0x5000_0000u32, "block_name": "Tim" },
+                ],
+                "imports": [{ "name": "tim", "version": "v1" }],
+                "stm32_ext": { "cm_name": "CM3" },
+                "cm_ext": {
+                    "cm_regs": [
+                        { "name": "CONTROL", "module": "tim", "reg_name": "control", "access": "rw", "bitfield_name": "Cr1" },
+                        { "name": "PRIMASK", "module": "tim", "reg_name": "primask", "access": "ro", "bitfield_name": "Sr" },
+                    ],
+                    "bit_band": true,
+                },
+                "riscv_ext": {
+                    "csrs": [
+                        { "name": "MSTATUS", "description": "Status", "module": "tim", "number": 0x300, "access": "rw", "bitfield_name": "Cr1" },
+                        { "name": "MHARTID", "module": "tim", "number": 0xF14, "access": "ro", "bitfield_name": "Sr" },
+                        { "name": "MSCRATCH", "module": "tim", "number": 0x340, "access": "wo", "bitfield_name": "Sr" },
+                    ],
+                },
+            }],
+            "modules": [{
+                "name": "tim",
+                "version": "v1",
+                "description": "General purpose timer",
+                "blocks": [
+                    {
+                        "name": "Tim",
+                        "description": "Timer registers",
+                        "fields": [
+                            { "name": "CR1", "description": "Control", "byte_offset": 0x0, "access": "rw", "bitfield_name": "Cr1" },
+                            { "name": "SR", "byte_offset": 0x10, "access": "ro", "bitfield_name": "Sr" },
+                            { "name": "CNT", "byte_offset": 0x24, "access": "rw", "bit_size": 16, "enum_name": "Dir" },
+                            { "name": "DR", "byte_offset": 0x28, "access": "wo", "bit_size": 8 },
+                            { "name": "CH", "byte_offset": 0x34, "array": { "len": 4, "stride": 8 }, "block_name": "TimCh" },
+                        ],
+                    },
+                    {
+                        "name": "TimCh",
+                        "fields": [
+                            { "name": "CCR", "byte_offset": 0x0, "access": "rw", "bitfield_name": "Sr" },
+                            { "name": "CCMR", "byte_offset": 0x4, "access": "rw", "bit_size": 32 },
+                        ],
+                    },
+                ],
+                "bitfields": [
+                    {
+                        "name": "Cr1",
+                        "bit_size": 32,
+                        "default": 4,
+                        "fields": [
+                            { "name": "CEN", "description": "Enable", "bit_offset": 0, "bit_size": 1 },
+                            { "name": "DIR", "bit_offset": 4, "bit_size": 1, "enum_name": "Dir" },
+                            { "name": "CMS", "bit_offset": 5, "bit_size": 2 },
+                        ],
+                    },
+                    {
+                        "name": "Sr",
+                        "bit_size": 16,
+                        "fields": [
+                            { "name": "CCIF", "bit_offset": 1, "bit_size": 1, "array": { "len": 4, "stride": 1 } },
+                        ],
+                    },
+                ],
+                "enums": [{
+                    "name": "Dir",
+                    "description": "Counting direction",
+                    "bit_size": 1,
+                    "variants": [
+                        { "name": "UP", "description": "Upcounter", "value": 0 },
+                        { "name": "DOWN", "value": 1 },
+                    ],
+                }],
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn assert_same_code(tera: io::Result<Vec<u8>>, quote: io::Result<proc_macro2::TokenStream>) {
+        let tera = format_code(tera.unwrap(), Format::Prettyplease, "tera", "tim").unwrap();
+        let quote = quote.unwrap().to_string().into_bytes();
+        let quote = format_code(quote, Format::Prettyplease, "quote", "tim").unwrap();
+
+        assert_eq!(String::from_utf8(tera), String::from_utf8(quote));
+    }
+
+    #[test]
+    fn generators_agree() {
+        let ctx = GenCtx::new();
+        let multi = tim();
+        let (chip, module) = (&multi.chips[0], &multi.modules[0]);
+        let aliases = bit_band::aliases(&multi, chip).unwrap();
+
+        for utils in [Utils::Super, Utils::Embed, Utils::None] {
+            for root in [Some("."), Some("..")] {
+                for aliases in [&[][..], &aliases] {
+                    assert_same_code(
+                        render(
+                            &ctx.tera,
+                            "chip.tera",
+                            &chip.name,
+                            &chip_ctx(chip, root, utils, aliases),
+                        ),
+                        codegen::chip(chip, root, utils, aliases),
+                    );
+                }
+            }
+
+            for bit_band in [false, true] {
+                assert_same_code(
+                    render(
+                        &ctx.tera,
+                        "module.tera",
+                        &module.name,
+                        &module_ctx(module, utils, bit_band),
+                    ),
+                    codegen::module(module, utils, bit_band),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bit_band_aliases() {
+        let mut multi = tim();
+        let aliases = bit_band::aliases(&multi, &multi.chips[0]).unwrap();
+
+        // TIM9 is outside of the bit-band regions
+        let names = aliases
+            .iter()
+            .map(|peripheral| peripheral.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["TIM2", "TIM3"]);
+
+        // SR and DR are not read-write
+        let registers = &aliases[1].registers;
+        let summary = registers
+            .iter()
+            .map(|register| {
+                let indices = register
+                    .indices
+                    .iter()
+                    .map(|index| (index.name.as_str(), index.len, index.stride))
+                    .collect::<Vec<_>>();
+                (register.name.as_str(), register.alias, indices)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("CR1", 0x4200_8000, vec![]),
+                ("CNT", 0x4200_8480, vec![]),
+                ("CH_CCR", 0x4200_8680, vec![("idx", 4, 0x100)]),
+                ("CH_CCMR", 0x4200_8700, vec![("idx", 4, 0x100)]),
+            ]
+        );
+
+        assert_eq!(registers[0].bitfield_name.as_deref(), Some("Cr1"));
+        assert_eq!(registers[1].bitfield_name, None);
+        assert_eq!(registers[1].bit_size, 16);
+
+        // Chips without bit-band regions get no aliases
+        multi.chips[0].cm_ext.as_mut().unwrap().bit_band = false;
+        assert!(
+            bit_band::aliases(&multi, &multi.chips[0])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn bit_band_helpers_follow_chips() {
+        let ctx = GenCtx::new();
+        let mut multi = tim();
+        let module = multi.modules[0].clone();
+
+        let gen_module_in = |multi: &ir::MultiChip| {
+            let mut out = Vec::new();
+            ctx.gen_module_in(multi, &module, Utils::Super, Format::None, &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(gen_module_in(&multi).contains("set_cen_atomic"));
+
+        multi.chips[0].cm_ext.as_mut().unwrap().bit_band = false;
+        assert!(!gen_module_in(&multi).contains("set_cen_atomic"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Bit-band aliases of the registers of Cortex-M3/M4 peripherals. The aliases
+//! are computed here from the peripheral addresses and field offsets, so that
+//! the generated code only writes to constant addresses.
+
+use std::io;
+
+use halogen_ir::ir;
+use serde::Serialize;
+
+use super::find_module;
+
+/// Start of the SRAM and peripheral bit-band regions, and of their aliases.
+/// Every bit of the regions is mapped to a word of the alias.
+const REGIONS: [(u64, u64); 2] = [(0x2000_0000, 0x2200_0000), (0x4000_0000, 0x4200_0000)];
+
+/// Size of each bit-band region.
+const REGION_SIZE: u64 = 0x10_0000;
+
+/// The aliases of the registers of a peripheral.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Peripheral {
+    pub name: String,
+    pub registers: Vec<Register>,
+}
+
+/// The alias of a register, or of every element of the arrays it is nested
+/// in. The generated `utils::BitBand` is typed after the register contents,
+/// so that the per-field helpers of its bitfield are available.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Register {
+    /// Names of the fields leading to the register, joined by `_`
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Module defining the bitfield of the register
+    pub module: String,
+    /// Bitfield of the register, none for simple registers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitfield_name: Option<String>,
+    pub bit_size: u32,
+    /// Alias of bit 0 of the first element
+    pub alias: u64,
+    /// Arrays the register is nested in, outermost first
+    pub indices: Vec<Index>,
+}
+
+/// An array the register is nested in, indexed by a parameter of the
+/// generated function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Index {
+    pub name: String,
+    pub len: u64,
+    /// Distance between the aliases of two elements
+    pub stride: u64,
+}
+
+/// Collect the aliases of the peripherals of a chip, none unless it has
+/// bit-band regions. Only read-write registers are considered, both bitfield
+/// and simple ones, and registers outside of the regions are skipped.
+pub fn aliases(multi: &ir::MultiChip, chip: &ir::Chip) -> io::Result<Vec<Peripheral>> {
+    if !chip.cm_ext.as_ref().is_some_and(|cm_ext| cm_ext.bit_band) {
+        return Ok(Vec::new());
+    }
+
+    let mut peripherals = chip.peripherals.iter().collect::<Vec<_>>();
+    peripherals.sort_by_key(|peripheral| peripheral.address);
+
+    let mut out = Vec::new();
+    for peripheral in peripherals {
+        let Some(region) = region(peripheral.address) else {
+            continue;
+        };
+
+        let import = chip
+            .imports
+            .iter()
+            .find(|import| import.name == peripheral.module)
+            .ok_or_else(|| {
+                io::Error::other(format!(
+                    "module {} used by peripheral {} of chip {} not imported",
+                    peripheral.module, peripheral.name, chip.name
+                ))
+            })?;
+
+        let mut walker = Walker {
+            module: find_module(multi, chip, import)?,
+            region,
+            names: Vec::new(),
+            arrays: Vec::new(),
+            blocks: Vec::new(),
+            registers: Vec::new(),
+        };
+        walker.block(&peripheral.block_name, peripheral.address)?;
+
+        if !walker.registers.is_empty() {
+            out.push(Peripheral {
+                name: peripheral.name.clone(),
+                registers: walker.registers,
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+/// The bit-band region containing an address, with the start of its alias.
+fn region(addr: u64) -> Option<(u64, u64)> {
+    REGIONS
+        .into_iter()
+        .find(|&(start, _)| addr >= start && addr - start < REGION_SIZE)
+}
+
+struct Walker<'a> {
+    module: &'a ir::Module,
+    region: (u64, u64),
+    names: Vec<&'a str>,
+    /// Length and alias stride of the arrays walked through
+    arrays: Vec<(u64, u64)>,
+    /// Blocks walked through, to stop on cycles
+    blocks: Vec<&'a str>,
+    registers: Vec<Register>,
+}
+
+impl<'a> Walker<'a> {
+    fn block(&mut self, name: &'a str, addr: u64) -> io::Result<()> {
+        let module = self.module;
+        let block = module
+            .blocks
+            .iter()
+            .find(|block| block.name == name)
+            .ok_or_else(|| {
+                io::Error::other(format!("unknown block {name} in module {}", module.name))
+            })?;
+
+        if self.blocks.contains(&name) {
+            return Ok(());
+        }
+        self.blocks.push(name);
+
+        let mut fields = block.fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|field| field.byte_offset);
+
+        for field in fields {
+            self.names.push(&field.name);
+            if let Some(array) = &field.array {
+                self.arrays.push((array.len, array.stride * 32));
+            }
+
+            let addr = addr + field.byte_offset;
+            match &field.inner {
+                ir::block::FieldInner::Block(inner) => self.block(&inner.block_name, addr)?,
+                ir::block::FieldInner::Bitfield(inner) if inner.access == ir::Access::ReadWrite => {
+                    let bitfield = module
+                        .bitfields
+                        .iter()
+                        .find(|bitfield| bitfield.name == inner.bitfield_name)
+                        .ok_or_else(|| {
+                            io::Error::other(format!(
+                                "unknown bitfield {} in module {}",
+                                inner.bitfield_name, module.name
+                            ))
+                        })?;
+
+                    self.register(field, Some(&bitfield.name), bitfield.bit_size, addr);
+                }
+                ir::block::FieldInner::Simple(inner) if inner.access == ir::Access::ReadWrite => {
+                    self.register(field, None, inner.bit_size, addr);
+                }
+                _ => {}
+            }
+
+            if field.array.is_some() {
+                self.arrays.pop();
+            }
+            self.names.pop();
+        }
+
+        self.blocks.pop();
+        Ok(())
+    }
+
+    fn register(
+        &mut self,
+        field: &ir::block::Field,
+        bitfield_name: Option<&str>,
+        bit_size: u32,
+        addr: u64,
+    ) {
+        let (start, alias_start) = self.region;
+
+        // Every element must fit in the region, with all of its bits
+        let alias = alias_start + (addr - start) * 32;
+        let last = self
+            .arrays
+            .iter()
+            .map(|&(len, stride)| len.saturating_sub(1) * stride)
+            .sum::<u64>();
+        if alias + last + u64::from(bit_size) * 4 > alias_start + REGION_SIZE * 32 {
+            return;
+        }
+
+        let indices = self
+            .arrays
+            .iter()
+            .enumerate()
+            .map(|(i, &(len, stride))| Index {
+                name: if self.arrays.len() == 1 {
+                    "idx".into()
+                } else {
+                    format!("idx{i}")
+                },
+                len,
+                stride,
+            })
+            .collect();
+
+        self.registers.push(Register {
+            name: self.names.join("_"),
+            description: field.description.clone(),
+            module: self.module.name.clone(),
+            bitfield_name: bitfield_name.map(Into::into),
+            bit_size,
+            alias,
+            indices,
+        });
+    }
+}
@@ -0,0 +1,719 @@
+//! Rust generator built directly on top of `quote`, walking the IR instead of
+//! going through the Tera templates. It produces the same API as the
+//! templates, item by item.
+
+use std::io;
+
+use halogen_ir::ir;
+use heck::*;
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+use super::{Utils, bit_band, escape_keyword};
+
+/// Generate the content of a chip file, with the bit-band `aliases` of its
+/// peripherals, see [`bit_band::aliases`].
+pub fn chip(
+    chip: &ir::Chip,
+    root: Option<&str>,
+    utils: Utils,
+    aliases: &[bit_band::Peripheral],
+) -> io::Result<TokenStream> {
+    let utils = gen_utils(utils)?;
+
+    let mut imports = chip.imports.iter().collect::<Vec<_>>();
+    imports.sort_by(|a, b| a.name.cmp(&b.name));
+    let imports = imports
+        .into_iter()
+        .map(|import| gen_module_import(import, root))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut peripherals = chip.peripherals.iter().collect::<Vec<_>>();
+    peripherals.sort_by_key(|peripheral| peripheral.address);
+    let peripherals = peripherals
+        .into_iter()
+        .map(gen_peripheral)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut cm_regs = chip
+        .cm_ext
+        .iter()
+        .flat_map(|cm_ext| &cm_ext.cm_regs)
+        .collect::<Vec<_>>();
+    cm_regs.sort_by(|a, b| a.name.cmp(&b.name));
+    let cm_regs = cm_regs
+        .into_iter()
+        .map(gen_cm_reg)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut csrs = chip
+        .riscv_ext
+        .iter()
+        .flat_map(|riscv_ext| &riscv_ext.csrs)
+        .collect::<Vec<_>>();
+    csrs.sort_by(|a, b| a.name.cmp(&b.name));
+    let csrs = csrs
+        .into_iter()
+        .map(gen_csr)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let aliases = if aliases.is_empty() {
+        None
+    } else {
+        Some(gen_bit_band(aliases)?)
+    };
+
+    Ok(quote! {
+        #utils
+        #(#imports)*
+        #(#peripherals)*
+        #(#cm_regs)*
+        #(#csrs)*
+        #aliases
+    })
+}
+
+/// Generate the content of a module file, `bit_band` adds the atomic helpers
+/// of single bit fields.
+pub fn module(module: &ir::Module, utils: Utils, bit_band: bool) -> io::Result<TokenStream> {
+    let doc = module
+        .description
+        .as_ref()
+        .map(|doc| quote! { #![doc = #doc] });
+    let utils = gen_utils(utils)?;
+
+    let mut blocks = module.blocks.iter().collect::<Vec<_>>();
+    blocks.sort_by(|a, b| a.name.cmp(&b.name));
+    let blocks = blocks
+        .into_iter()
+        .map(gen_block)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut bitfields = module.bitfields.iter().collect::<Vec<_>>();
+    bitfields.sort_by(|a, b| a.name.cmp(&b.name));
+    let bitfields = bitfields
+        .into_iter()
+        .map(|bitfield| gen_bitfield(bitfield, bit_band))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut enums = module.enums.iter().collect::<Vec<_>>();
+    enums.sort_by(|a, b| a.name.cmp(&b.name));
+    let enums = enums
+        .into_iter()
+        .map(gen_enum)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        #doc
+        #utils
+        #(#blocks)*
+        #(#bitfields)*
+        #(#enums)*
+    })
+}
+
+fn gen_utils(utils: Utils) -> io::Result<TokenStream> {
+    match utils {
+        Utils::Super => Ok(quote! {
+            #[allow(unused_imports)]
+            use super::utils;
+        }),
+        Utils::Embed => {
+            let content = include_str!("templates/utils.rs")
+                .parse::<TokenStream>()
+                .map_err(|err| io::Error::other(format!("failed to parse utils: {err}")))?;
+
+            Ok(quote! { pub mod utils { #content } })
+        }
+        Utils::None => Ok(TokenStream::new()),
+    }
+}
+
+fn gen_module_import(import: &ir::chip::Import, root: Option<&str>) -> io::Result<TokenStream> {
+    let file = match &import.version {
+        Some(version) => mod_name(&format!("{}_{}", import.name, version)),
+        None => mod_name(&import.name),
+    };
+
+    let path = format!("{}/modules/{}.rs", root.unwrap_or("."), file);
+    let name = ident(&mod_name(&import.name))?;
+
+    Ok(quote! {
+        #[path = #path]
+        pub mod #name;
+    })
+}
+
+fn gen_peripheral(peripheral: &ir::chip::Peripheral) -> io::Result<TokenStream> {
+    let doc = gen_doc(&peripheral.description);
+    let name = ident(&const_name(&peripheral.name))?;
+    let mod_name = ident(&mod_name(&peripheral.module))?;
+    let block = ident(&type_name(&peripheral.block_name))?;
+    let address = hex(peripheral.address);
+
+    Ok(quote! {
+        #doc
+        pub const #name: #mod_name::#block = unsafe {
+            <#mod_name::#block>::from_addr(#address)
+        };
+    })
+}
+
+fn gen_cm_reg(cm_reg: &ir::chip::cm_ext::CmReg) -> io::Result<TokenStream> {
+    let doc = gen_doc(&cm_reg.description);
+    let name = ident(&mod_name(&cm_reg.name))?;
+    let mod_name = ident(&mod_name(&cm_reg.module))?;
+    let bits = ident(&type_name(&format!("{}Bits", cm_reg.bitfield_name)))?;
+
+    let read = matches!(cm_reg.access, ir::Access::Read | ir::Access::ReadWrite).then(|| {
+        let asm = format!("mrs {{}}, {}", cm_reg.reg_name);
+        quote! {
+            pub unsafe fn read() -> #mod_name::#bits {
+                let value: u32;
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        out(reg) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                    <#mod_name::#bits>::from_bits_unchecked(value)
+                }
+            }
+        }
+    });
+
+    let write = matches!(cm_reg.access, ir::Access::Write | ir::Access::ReadWrite).then(|| {
+        let asm = format!("msr {}, {{}}", cm_reg.reg_name);
+        quote! {
+            pub unsafe fn write(value: #mod_name::#bits) {
+                let value = value.to_bits();
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        in(reg) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #doc
+        pub mod #name {
+            #[allow(unused_imports)]
+            use super::*;
+            #read
+            #write
+        }
+    })
+}
+
+fn gen_csr(csr: &ir::chip::riscv_ext::Csr) -> io::Result<TokenStream> {
+    let doc = gen_doc(&csr.description);
+    let name = ident(&mod_name(&csr.name))?;
+    let mod_name = ident(&mod_name(&csr.module))?;
+    let bits = ident(&type_name(&format!("{}Bits", csr.bitfield_name)))?;
+    let number = format!("{:#x}", csr.number);
+
+    let read = matches!(csr.access, ir::Access::Read | ir::Access::ReadWrite).then(|| {
+        let asm = format!("csrr {{}}, {number}");
+        quote! {
+            pub unsafe fn read() -> #mod_name::#bits {
+                let value: u32;
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        out(reg) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                    <#mod_name::#bits>::from_bits_unchecked(value)
+                }
+            }
+        }
+    });
+
+    // Writes, plus the atomic set and clear when the register is read-write
+    let mut writes = vec![];
+    if matches!(csr.access, ir::Access::Write | ir::Access::ReadWrite) {
+        writes.push(("write", "csrw"));
+    }
+    if matches!(csr.access, ir::Access::ReadWrite) {
+        writes.extend([("set", "csrs"), ("clear", "csrc")]);
+    }
+    let writes = writes.into_iter().map(|(func, instr)| {
+        let func = Ident::new(func, Span::call_site());
+        let asm = format!("{instr} {number}, {{}}");
+        quote! {
+            pub unsafe fn #func(value: #mod_name::#bits) {
+                let value = value.to_bits();
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        in(reg) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #doc
+        pub mod #name {
+            #[allow(unused_imports)]
+            use super::*;
+            #read
+            #(#writes)*
+        }
+    })
+}
+
+fn gen_bit_band(peripherals: &[bit_band::Peripheral]) -> io::Result<TokenStream> {
+    let peripherals = peripherals
+        .iter()
+        .map(|peripheral| {
+            let name = ident(&mod_name(&peripheral.name))?;
+            let registers = peripheral
+                .registers
+                .iter()
+                .map(gen_bit_band_register)
+                .collect::<io::Result<Vec<_>>>()?;
+
+            Ok(quote! {
+                pub mod #name {
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    #(#registers)*
+                }
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        /// Bit-band aliases of the read-write registers of the peripherals, to set
+        /// and clear their bits without a read-modify-write.
+        pub mod bit_band {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#peripherals)*
+        }
+    })
+}
+
+fn gen_bit_band_register(register: &bit_band::Register) -> io::Result<TokenStream> {
+    let doc = gen_doc(&register.description);
+    let ty = match &register.bitfield_name {
+        Some(bitfield_name) => {
+            let module = ident(&mod_name(&register.module))?;
+            let bitfield = ident(&type_name(&format!("{bitfield_name}Bits")))?;
+            quote! { #module::#bitfield }
+        }
+        None => gen_type(register.bit_size)?,
+    };
+
+    let alias = hex(register.alias);
+    if register.indices.is_empty() {
+        let name = ident(&const_name(&register.name))?;
+        return Ok(quote! {
+            #doc
+            pub const #name: utils::BitBand<#ty> = unsafe {
+                utils::BitBand::from_addr(#alias)
+            };
+        });
+    }
+
+    let name = ident(&field_name(&register.name))?;
+    let indices = register
+        .indices
+        .iter()
+        .map(|index| ident(&index.name))
+        .collect::<io::Result<Vec<_>>>()?;
+    let lens = register
+        .indices
+        .iter()
+        .map(|index| Literal::u64_unsuffixed(index.len));
+    let strides = register.indices.iter().map(|index| hex(index.stride));
+
+    Ok(quote! {
+        #[inline(always)]
+        #doc
+        pub const fn #name(#(#indices: usize),*) -> utils::BitBand<#ty> {
+            #(assert!(#indices < #lens);)*
+            unsafe {
+                utils::BitBand::from_addr(#alias #(+ #indices * #strides)*)
+            }
+        }
+    })
+}
+
+fn gen_block(block: &ir::Block) -> io::Result<TokenStream> {
+    let doc = gen_doc(&block.description);
+    let name = ident(&type_name(&block.name))?;
+
+    let mut fields = block.fields.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|field| field.byte_offset);
+    let fields = fields
+        .into_iter()
+        .map(gen_block_field)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #doc
+        pub struct #name {
+            ptr: *mut u8
+        }
+
+        impl #name {
+            #[inline(always)]
+            pub const unsafe fn from_addr(addr: usize) -> Self {
+                unsafe {
+                    Self::from_ptr(addr as _)
+                }
+            }
+
+            #[inline(always)]
+            pub const unsafe fn from_ptr(ptr: *mut u8) -> Self {
+                Self { ptr }
+            }
+
+            #[inline(always)]
+            pub const fn as_ptr(&self) -> *mut u8 {
+                self.ptr
+            }
+
+            #(#fields)*
+        }
+    })
+}
+
+fn gen_block_field(field: &ir::block::Field) -> io::Result<TokenStream> {
+    use ir::block::FieldInner;
+
+    let doc = gen_doc(&field.description);
+    let name = ident(&field_name(&field.name))?;
+
+    let ty = match &field.inner {
+        FieldInner::Block(inner) => {
+            let block = ident(&type_name(&inner.block_name))?;
+            quote! { #block }
+        }
+        FieldInner::Bitfield(inner) => {
+            let access = gen_access(&inner.access);
+            let bits = ident(&type_name(&format!("{}Bits", inner.bitfield_name)))?;
+            quote! { utils::Reg<#bits, #access> }
+        }
+        FieldInner::Simple(inner) => {
+            let access = gen_access(&inner.access);
+            let ty = gen_type(inner.bit_size)?;
+            quote! { utils::Reg<#ty, #access> }
+        }
+    };
+
+    let base = hex(field.byte_offset);
+    let (idx, check, offset) = match &field.array {
+        Some(array) => {
+            let len = Literal::u64_unsuffixed(array.len);
+            let stride = hex(array.stride);
+            (
+                quote! { , idx: usize },
+                quote! { assert!(idx < #len); },
+                quote! { #base + idx * #stride },
+            )
+        }
+        None => (quote! {}, quote! {}, quote! { #base }),
+    };
+
+    Ok(quote! {
+        #[inline(always)]
+        #doc
+        pub const fn #name(&self #idx) -> #ty {
+            #check
+            unsafe {
+                let ptr = self.ptr.add(#offset);
+                <#ty>::from_ptr(ptr)
+            }
+        }
+    })
+}
+
+fn gen_bitfield(bitfield: &ir::Bitfield, bit_band: bool) -> io::Result<TokenStream> {
+    let doc = gen_doc(&bitfield.description);
+    let name = ident(&type_name(&format!("{}Bits", bitfield.name)))?;
+    let ty = gen_type(bitfield.bit_size)?;
+    let default = hex(bitfield.default);
+
+    let mut fields = bitfield.fields.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|field| field.bit_offset);
+    let fields = fields
+        .into_iter()
+        .map(|field| gen_bitfield_field(field, &ty))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut bits = bitfield
+        .fields
+        .iter()
+        .filter(|field| field.bit_size == 1)
+        .collect::<Vec<_>>();
+    bits.sort_by_key(|field| field.bit_offset);
+    let atomics = if bit_band && !bits.is_empty() {
+        let bits = bits
+            .into_iter()
+            .map(gen_bitfield_atomic)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Some(quote! {
+            impl utils::BitBand<#name> {
+                #(#bits)*
+            }
+        })
+    } else {
+        None
+    };
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        #doc
+        pub struct #name {
+            bits: #ty
+        }
+
+        impl Default for #name {
+            fn default() -> Self {
+                unsafe {
+                    Self::from_bits_unchecked(#default)
+                }
+            }
+        }
+
+        impl #name {
+            #[inline(always)]
+            pub const unsafe fn from_bits_unchecked(bits: #ty) -> Self {
+                Self { bits }
+            }
+
+            #[inline(always)]
+            pub const fn to_bits(self) -> #ty {
+                self.bits
+            }
+
+            #(#fields)*
+        }
+
+        #atomics
+    })
+}
+
+/// Set and clear a single bit field through the bit-band alias.
+fn gen_bitfield_atomic(field: &ir::bitfield::Field) -> io::Result<TokenStream> {
+    let doc = gen_doc(&field.description);
+    let name = field_name(&field.name);
+    let base = hex(field.bit_offset as u64);
+
+    let (idx, check, offset) = match &field.array {
+        Some(array) => {
+            let len = Literal::u64_unsuffixed(array.len);
+            let stride = hex(array.stride);
+            (
+                quote! { , idx: usize },
+                quote! { assert!(idx < #len); },
+                quote! { #base + idx * #stride },
+            )
+        }
+        None => (quote! {}, quote! {}, quote! { #base }),
+    };
+
+    ["set", "clear"]
+        .into_iter()
+        .map(|action| {
+            let func = ident(&format!("{action}_{name}_atomic"))?;
+            let bit = Ident::new(&format!("{action}_bit_atomic"), Span::call_site());
+            Ok(quote! {
+                #[inline(always)]
+                #doc
+                pub unsafe fn #func(&self #idx) {
+                    #check
+                    unsafe {
+                        self.#bit(#offset)
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+fn gen_bitfield_field(field: &ir::bitfield::Field, ty: &TokenStream) -> io::Result<TokenStream> {
+    let doc = gen_doc(&field.description);
+    let name = field_name(&field.name);
+    let setter = ident(&format!("set_{name}"))?;
+    let getter = ident(&name)?;
+
+    let enum_name = field
+        .enum_name
+        .as_ref()
+        .map(|enum_name| ident(&type_name(&format!("{enum_name}Val"))))
+        .transpose()?;
+
+    let field_ty = match &enum_name {
+        Some(enum_name) => quote! { #enum_name },
+        None if field.bit_size == 1 => quote! { bool },
+        None => gen_type(field.bit_size)?,
+    };
+
+    let mask = hex(mask(field.bit_size));
+    let base = hex(field.bit_offset as u64);
+
+    let (set_idx, get_idx, check, offset) = match &field.array {
+        Some(array) => {
+            let len = Literal::u64_unsuffixed(array.len);
+            let stride = hex(array.stride);
+            (
+                quote! { idx: usize, },
+                quote! { , idx: usize },
+                quote! { assert!(idx < #len); },
+                quote! { (#base + idx * #stride) },
+            )
+        }
+        None => (quote! {}, quote! {}, quote! {}, quote! { #base }),
+    };
+
+    let set = if enum_name.is_some() {
+        quote! { self.bits |= (val.to_bits() as #ty & #mask) << #offset; }
+    } else if field.bit_size == 1 {
+        quote! { self.bits |= if val { 1 << #offset } else { 0 }; }
+    } else {
+        quote! { self.bits |= (val as #ty & #mask) << #offset; }
+    };
+
+    let get = if let Some(enum_name) = &enum_name {
+        quote! {
+            let val = ((self.bits >> #offset) & #mask) as _;
+            unsafe {
+                #enum_name::from_bits_unchecked(val)
+            }
+        }
+    } else if field.bit_size == 1 {
+        quote! { ((self.bits >> #offset) & #mask) != 0 }
+    } else {
+        quote! { ((self.bits >> #offset) & #mask) as _ }
+    };
+
+    Ok(quote! {
+        #[inline(always)]
+        #doc
+        pub const fn #setter(mut self, #set_idx val: #field_ty) -> Self {
+            #check
+            self.bits &= !(#mask << #offset);
+            #set
+            self
+        }
+
+        #[inline(always)]
+        #doc
+        pub const fn #getter(self #get_idx) -> #field_ty {
+            #check
+            #get
+        }
+    })
+}
+
+fn gen_enum(enum_name: &ir::Enum) -> io::Result<TokenStream> {
+    let doc = gen_doc(&enum_name.description);
+    let name = ident(&type_name(&format!("{}Val", enum_name.name)))?;
+    let ty = gen_type(enum_name.bit_size)?;
+
+    let mut variants = enum_name.variants.iter().collect::<Vec<_>>();
+    variants.sort_by_key(|variant| variant.value);
+    let variants = variants
+        .into_iter()
+        .map(|variant| {
+            let doc = gen_doc(&variant.description);
+            let name = ident(&type_name(&variant.name))?;
+            let value = hex(variant.value);
+
+            Ok(quote! {
+                #doc
+                #name = #value,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #doc
+        #[repr(#ty)]
+        pub enum #name {
+            #(#variants)*
+        }
+
+        impl #name {
+            #[inline(always)]
+            pub const unsafe fn from_bits_unchecked(bits: #ty) -> Self {
+                unsafe {
+                    ::core::mem::transmute(bits)
+                }
+            }
+
+            #[inline(always)]
+            pub const fn to_bits(self) -> #ty {
+                self as #ty
+            }
+        }
+    })
+}
+
+fn gen_doc(description: &Option<String>) -> Option<TokenStream> {
+    description.as_ref().map(|doc| quote! { #[doc = #doc] })
+}
+
+fn gen_type(size: u32) -> io::Result<TokenStream> {
+    match size {
+        0..=8 => Ok(quote! { u8 }),
+        9..=16 => Ok(quote! { u16 }),
+        17..=32 => Ok(quote! { u32 }),
+        33..=64 => Ok(quote! { u64 }),
+        _ => Err(io::Error::other("cannot gen type with size > 64")),
+    }
+}
+
+fn gen_access(access: &ir::Access) -> TokenStream {
+    match access {
+        ir::Access::ReadWrite => quote! { utils::RW },
+        ir::Access::Read => quote! { utils::RO },
+        ir::Access::Write => quote! { utils::WO },
+    }
+}
+
+fn hex(value: u64) -> Literal {
+    format!("{value:#x}").parse().unwrap()
+}
+
+fn mask(size: u32) -> u64 {
+    1u64.checked_shl(size).map_or(u64::MAX, |bit| bit - 1)
+}
+
+fn ident(name: &str) -> io::Result<Ident> {
+    syn::parse_str::<Ident>(name)
+        .map(|ident| Ident::new(&ident.to_string(), Span::call_site()))
+        .map_err(|_| io::Error::other(format!("{name} is not a valid identifier")))
+}
+
+fn field_name(raw: &str) -> String {
+    escape_keyword(raw.to_snake_case().into()).into_owned()
+}
+
+fn mod_name(raw: &str) -> String {
+    escape_keyword(raw.to_snake_case().into()).into_owned()
+}
+
+fn const_name(raw: &str) -> String {
+    escape_keyword(raw.to_shouty_snake_case().into()).into_owned()
+}
+
+fn type_name(raw: &str) -> String {
+    escape_keyword(raw.to_upper_camel_case().into()).into_owned()
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+include!("src/list.rs");
+
+fn main() {
+    let selected = CHIPS_LIST
+        .iter()
+        .copied()
+        .filter(|chip| {
+            let feature = format!("CARGO_FEATURE_{}", chip.to_ascii_uppercase());
+            env::var_os(feature).is_some()
+        })
+        .collect::<Vec<_>>();
+
+    let chip = match selected.as_slice() {
+        [chip] => *chip,
+        [] => panic!("no chip selected, enable exactly one chip feature"),
+        _ => panic!("multiple chips selected: {}", selected.join(", ")),
+    };
+
+    let values = CHIPS_LIST
+        .iter()
+        .map(|chip| format!("\"{chip}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    println!("cargo:rustc-check-cfg=cfg(target_chip, values({values}))");
+    println!("cargo:rustc-cfg=target_chip=\"{chip}\"");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let memory = PathBuf::from("memory").join(format!("{chip}.x"));
+    println!("cargo:rerun-if-changed={}", memory.display());
+
+    if memory.exists() {
+        let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        fs::copy(&memory, out.join("memory.x")).expect("failed to copy memory.x");
+        println!("cargo:rustc-link-search={}", out.display());
+    }
+}
@@ -53,4 +53,37 @@ impl<T: Copy> Reg<T, RW> {
             self.write(f(self.read()))
         }
     }
+}
+
+/// Bit-band alias of a register, mapping every bit of the register to a word.
+/// Only Cortex-M3/M4 cores have bit-band regions.
+pub struct BitBand<T> {
+    ptr: *mut u32,
+    _phantom: ::core::marker::PhantomData<*mut T>
+}
+
+impl<T> BitBand<T> {
+    pub const unsafe fn from_addr(addr: usize) -> Self {
+        Self {
+            ptr: addr as _, _phantom: ::core::marker::PhantomData
+        }
+    }
+
+    pub const fn as_ptr(&self) -> *mut u32 {
+        self.ptr
+    }
+
+    /// Set a bit of the register, without a read-modify-write.
+    pub unsafe fn set_bit_atomic(&self, bit: usize) {
+        unsafe {
+            ::core::ptr::write_volatile(self.ptr.add(bit), 1)
+        }
+    }
+
+    /// Clear a bit of the register, without a read-modify-write.
+    pub unsafe fn clear_bit_atomic(&self, bit: usize) {
+        unsafe {
+            ::core::ptr::write_volatile(self.ptr.add(bit), 0)
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::error::Error as _;
 use std::path::Path;
 use std::{fs, io};
 
@@ -68,14 +69,39 @@ macro_rules! maybe_par_multi_join {
 
 pub use maybe_par_multi_join;
 
-pub fn create_dir_if_not_exist(path: &Path) -> io::Result<()> {
-    match fs::create_dir(path) {
-        Ok(_) => Ok(()),
-        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+/// Stable 64 bit FNV-1a hash, used to detect changes between runs.
+pub fn hash(data: &[u8]) -> u64 {
+    hash_extend(0xcbf29ce484222325, data)
+}
+
+/// Continue a [`hash`] with more data.
+pub fn hash_extend(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn hash_file(path: &Path) -> io::Result<Option<u64>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(hash(&data))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
         Err(err) => Err(err),
     }
 }
 
+/// Write the file only if its content differs, returns whether it was written.
+pub fn write_if_changed(path: &Path, data: &[u8]) -> io::Result<bool> {
+    match fs::read(path) {
+        Ok(old) if old == data => return Ok(false),
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    fs::write(path, data)?;
+    Ok(true)
+}
+
 pub fn tera() -> Tera {
     let mut tera = Tera::default();
 
@@ -131,9 +157,21 @@ pub fn tera() -> Tera {
     tera
 }
 
-pub fn unwrap_tera_error(error: tera::Error) -> io::Error {
+/// Convert a Tera error into an IO one, `file` and `item` identify what was
+/// being rendered.
+pub fn tera_error(file: &str, item: &str, error: tera::Error) -> io::Error {
+    // The actual cause (a missing variable, a failing filter...) is nested in
+    // the source chain
+    let mut msg = format!("{file} for {item}: {error}");
+    let mut source = error.source();
+    while let Some(err) = source {
+        msg.push_str(": ");
+        msg.push_str(&err.to_string());
+        source = err.source();
+    }
+
     match error.kind {
-        tera::ErrorKind::Io(kind) => io::Error::new(kind, "tera IO error"),
-        _ => panic!("{}", error),
+        tera::ErrorKind::Io(kind) => io::Error::new(kind, msg),
+        _ => io::Error::other(msg),
     }
 }
@@ -0,0 +1,50 @@
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::load_ir;
+use halogen_ir::diff;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Old halogen IR.
+        pub old: PathBuf,
+        /// New halogen IR.
+        pub new: PathBuf,
+        /// Print the changes as JSON
+        #[arg(long)]
+        pub json: bool,
+        /// Only report changes breaking the generated API
+        #[arg(long)]
+        pub breaking: bool,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let old = load_ir(&args.old)?;
+    let new = load_ir(&args.new)?;
+
+    let mut changes = diff::diff(&old, &new);
+    if args.breaking {
+        changes.retain(|change| change.breaking);
+    }
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &changes)?;
+        writeln!(out)?;
+    } else {
+        for change in &changes {
+            writeln!(out, "{change}")?;
+        }
+    }
+
+    let breaking = changes.iter().filter(|change| change.breaking).count();
+    log::info!("{} changes, {breaking} breaking", changes.len());
+
+    Ok(())
+}
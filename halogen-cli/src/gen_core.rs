@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Result};
+
+use crate::dump_ir;
+use crate::gen_rust::args::{Format, Generator};
+use crate::gen_rust::{to_format, to_generator};
+use halogen_backend::rust;
+use halogen_frontend::cortex_m;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Output folder for the core definitions, to be passed as `--core-path` to gen-rust
+        #[arg(short, long)]
+        pub output: PathBuf,
+        /// Only generate the cores matching this regex (cm0, cm0p, cm3, cm4, cm7, cm33)
+        #[arg(long)]
+        pub cores: Option<regex::Regex>,
+        #[arg(long, value_enum, default_value_t = Format::Rustfmt)]
+        pub format: Format,
+        /// Generator used for chip and module files
+        #[arg(long, value_enum, default_value_t = Generator::Tera)]
+        pub generator: Generator,
+        /// Also dump the IR of the cores to this path
+        #[arg(long)]
+        pub ir: Option<PathBuf>,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let names = cortex_m::core_names()
+        .filter(|name| args.cores.as_ref().is_none_or(|cores| cores.is_match(name)))
+        .collect::<Vec<_>>();
+
+    if let Some(cores) = &args.cores {
+        ensure!(!names.is_empty(), "no core matches {cores}");
+    }
+
+    log::info!("Describing cores...");
+    let ir = cortex_m::convert_cores(names)?;
+
+    if let Some(path) = &args.ir {
+        dump_ir(path, &ir, false)?;
+    }
+
+    log::info!("Generating bindings...");
+
+    // The chips are included by the chips of gen-rust, which provide utils
+    let settings = rust::GenMultiChipSettings {
+        utils: rust::Utils::Super,
+        format: to_format(args.format),
+        generator: to_generator(args.generator),
+        core_path: None,
+        gen_chips: false,
+        gen_list: false,
+    };
+    rust::GenCtx::new().gen_multi_chip(&ir, &args.output, settings)?;
+
+    log::info!("Generation finished!");
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use std::path::PathBuf;
+use std::{fs, io};
 
-use anyhow::Result;
+use anyhow::{ensure, Context as _, Result};
 
-use crate::load_ir;
+use crate::{load_ir, open_repository};
 use halogen_backend::rust;
+use halogen_ir::repository::IrRepository;
 
 pub mod args {
     use super::*;
@@ -21,7 +23,10 @@ pub mod args {
         pub utils: Utils,
         #[arg(long, value_enum, default_value_t = Format::Rustfmt)]
         pub format: Format,
-        /// Path used for auxiliary core definitions (Cortex-M)
+        /// Generator used for chip and module files
+        #[arg(long, value_enum, default_value_t = Generator::Tera)]
+        pub generator: Generator,
+        /// Path used for auxiliary core definitions (Cortex-M), as generated by gen-core
         #[arg(long)]
         pub core_path: Option<String>,
         /// Do not generate chips.rs file
@@ -30,6 +35,28 @@ pub mod args {
         /// Do not generate list.rs file
         #[arg(long)]
         pub dont_gen_list: bool,
+        /// Only generate the chips matching this regex, each as a self-contained file
+        /// rendered with the Tera templates
+        #[arg(long, conflicts_with_all = ["krate", "generator"])]
+        pub chip: Option<regex::Regex>,
+        /// Generate a complete crate (Cargo.toml, lib.rs, build.rs and README)
+        #[arg(long = "crate")]
+        pub krate: bool,
+        /// Name of the generated crate, defaults to the output folder name
+        #[arg(long, requires = "krate")]
+        pub crate_name: Option<String>,
+        /// Version of the generated crate
+        #[arg(long, default_value = "0.1.0", requires = "krate")]
+        pub crate_version: String,
+        /// Edition of the generated crate
+        #[arg(long, default_value = "2024", requires = "krate")]
+        pub crate_edition: String,
+        /// Do not write anything, fail if the output folder is not up to date
+        #[arg(long, conflicts_with = "chip")]
+        pub check: bool,
+        /// Document where every item comes from, as recorded in the IR
+        #[arg(long)]
+        pub source_docs: bool,
     }
 
     #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -42,40 +69,184 @@ pub mod args {
         None,
     }
 
+    #[derive(Debug, Clone, Copy, clap::ValueEnum)]
+    pub enum Generator {
+        /// Render the Tera templates
+        Tera,
+        /// Build the code directly with quote
+        Quote,
+    }
+
     #[derive(Debug, Clone, Copy, clap::ValueEnum)]
     pub enum Format {
         /// Use rustfmt as the formatter
         Rustfmt,
+        /// Format in-process with prettyplease, no toolchain needed
+        Prettyplease,
         /// Do not use any formatter
         None,
     }
 }
 
 pub fn run(args: &args::Args) -> Result<()> {
+    let ctx = rust::GenCtx::new();
+
+    if let Some(filter) = &args.chip {
+        // Only load the selected chips and what they import
+        log::info!("Opening IR...");
+        let repository = open_repository(&args.input)?;
+
+        log::info!("Generating bindings...");
+        gen_single_chips(&ctx, &repository, filter, args)?;
+
+        log::info!("Generation finished!");
+        return Ok(());
+    }
+
     log::info!("Loading IR...");
-    let ir = load_ir(&args.input)?;
+    let mut ir = load_ir(&args.input)?;
+    if args.source_docs {
+        rust::add_source_docs(&mut ir);
+    }
 
     log::info!("Generating bindings...");
-    let ctx = rust::GenCtx::new();
-    ctx.gen_multi_chip(
-        &ir,
-        &args.output,
-        rust::GenMultiChipSettings {
-            utils: match args.utils {
-                args::Utils::Embed => rust::Utils::Embed,
-                args::Utils::Super => rust::Utils::Super,
-                args::Utils::None => rust::Utils::None,
-            },
-            format: match args.format {
-                args::Format::Rustfmt => rust::Format::Rustfmt,
-                args::Format::None => rust::Format::None,
-            },
-            core_path: args.core_path.as_deref(),
-            gen_chips: !args.dont_gen_chips,
-            gen_list: !args.dont_gen_list,
-        },
-    )?;
+
+    let settings = rust::GenMultiChipSettings {
+        utils: to_utils(args.utils),
+        format: to_format(args.format),
+        generator: to_generator(args.generator),
+        core_path: args.core_path.as_deref(),
+        gen_chips: !args.dont_gen_chips,
+        gen_list: !args.dont_gen_list,
+    };
+
+    if args.check {
+        let mismatches = if args.krate {
+            let name = crate_name(args)?;
+            ctx.check_crate(&ir, &args.output, crate_settings(&name, args))?
+        } else {
+            ctx.check_multi_chip(&ir, &args.output, settings)?
+        };
+
+        report_mismatches(&mismatches);
+        ensure!(
+            mismatches.is_empty(),
+            "{} generated files are not up to date",
+            mismatches.len()
+        );
+
+        log::info!("Generated files are up to date!");
+        return Ok(());
+    }
+
+    if args.krate {
+        let name = crate_name(args)?;
+        ctx.gen_crate(&ir, &args.output, crate_settings(&name, args))?;
+    } else {
+        ctx.gen_multi_chip(&ir, &args.output, settings)?;
+    }
 
     log::info!("Generation finished!");
     Ok(())
 }
+
+fn crate_name(args: &args::Args) -> Result<String> {
+    match &args.crate_name {
+        Some(name) => Ok(name.clone()),
+        None => Ok(args
+            .output
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("cannot infer crate name from output folder, use --crate-name")?
+            .to_string()),
+    }
+}
+
+fn crate_settings<'a>(name: &'a str, args: &'a args::Args) -> rust::GenCrateSettings<'a> {
+    rust::GenCrateSettings {
+        name,
+        version: &args.crate_version,
+        edition: &args.crate_edition,
+        format: to_format(args.format),
+        generator: to_generator(args.generator),
+        core_path: args.core_path.as_deref(),
+    }
+}
+
+fn report_mismatches(mismatches: &[rust::Mismatch]) {
+    for mismatch in mismatches {
+        let path = mismatch.path.display();
+        match &mismatch.kind {
+            rust::MismatchKind::Missing => println!("missing: {path}"),
+            rust::MismatchKind::Stale => println!("stale: {path}"),
+            rust::MismatchKind::Changed { expected, actual } => {
+                println!("changed: {path}");
+
+                let expected = String::from_utf8_lossy(expected);
+                let actual = String::from_utf8_lossy(actual);
+                let diff = similar::TextDiff::from_lines(actual.as_ref(), expected.as_ref());
+
+                print!(
+                    "{}",
+                    diff.unified_diff()
+                        .header(&format!("a/{path}"), &format!("b/{path}"))
+                );
+            }
+        }
+    }
+}
+
+fn gen_single_chips(
+    ctx: &rust::GenCtx,
+    repository: &IrRepository,
+    filter: &regex::Regex,
+    args: &args::Args,
+) -> Result<()> {
+    let names = repository
+        .chip_names()?
+        .into_iter()
+        .filter(|name| filter.is_match(name))
+        .collect::<Vec<_>>();
+
+    ensure!(!names.is_empty(), "no chip matches {filter}");
+
+    fs::create_dir_all(&args.output)?;
+
+    for name in names {
+        let mut ir = repository.chip_ir(name)?;
+        if args.source_docs {
+            rust::add_source_docs(&mut ir);
+        }
+        let chip = &ir.chips[0];
+
+        let path = args.output.join(rust::chip_file_name(chip));
+        let out = io::BufWriter::new(fs::File::create(path)?);
+
+        ctx.gen_single_chip(&ir, chip, to_utils(args.utils), to_format(args.format), out)?;
+    }
+
+    Ok(())
+}
+
+fn to_utils(utils: args::Utils) -> rust::Utils {
+    match utils {
+        args::Utils::Embed => rust::Utils::Embed,
+        args::Utils::Super => rust::Utils::Super,
+        args::Utils::None => rust::Utils::None,
+    }
+}
+
+pub(crate) fn to_generator(generator: args::Generator) -> rust::Generator {
+    match generator {
+        args::Generator::Tera => rust::Generator::Tera,
+        args::Generator::Quote => rust::Generator::Quote,
+    }
+}
+
+pub(crate) fn to_format(format: args::Format) -> rust::Format {
+    match format {
+        args::Format::Rustfmt => rust::Format::Rustfmt,
+        args::Format::Prettyplease => rust::Format::Prettyplease,
+        args::Format::None => rust::Format::None,
+    }
+}
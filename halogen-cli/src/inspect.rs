@@ -0,0 +1,370 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Context as _, Result};
+use serde::Serialize;
+
+use crate::open_repository;
+use halogen_ir::ir;
+use halogen_ir::resolve::{self, ModuleIndex};
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Input halogen IR.
+        pub input: PathBuf,
+        /// Chip to inspect
+        pub chip: String,
+        /// Item to inspect, as PERIPH[.REG[.FIELD]]. Arrays are indexed with
+        /// NAME[IDX] and sub-blocks add more levels (TIM1.CH[2].CFG)
+        pub path: Option<String>,
+        /// Print the result as JSON
+        #[arg(long)]
+        pub json: bool,
+    }
+}
+
+/// A resolved item, along with its direct children.
+#[derive(Debug, Serialize)]
+struct Node {
+    kind: &'static str,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Absolute address, for everything but fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    array: Option<ir::Array>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access: Option<ir::Access>,
+    /// Position inside the register, for fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reset: Option<u64>,
+    /// Block, bitfield or enum backing the item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    type_name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<ir::enum_name::Variant>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: &'static str, name: &str, description: &Option<String>) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            description: description.clone(),
+            address: None,
+            array: None,
+            access: None,
+            bit_offset: None,
+            bit_size: None,
+            reset: None,
+            type_name: None,
+            variants: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Where the path resolution currently is.
+enum State<'a> {
+    Block(&'a ir::Block),
+    Register(&'a ir::block::Field, Option<&'a ir::Bitfield>),
+    Field,
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let ir = open_repository(&args.input)?.chip_ir(&args.chip)?;
+    let index = resolve::Index::new(&ir);
+
+    let node = inspect(&index, &args.chip, args.path.as_deref())?;
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &node)?;
+        writeln!(out)?;
+    } else {
+        print_node(&mut out, &node, 0)?;
+        for child in &node.children {
+            print_node(&mut out, child, 1)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn inspect(index: &resolve::Index, chip: &str, path: Option<&str>) -> Result<Node> {
+    let chip = index.chip(chip)?;
+
+    let Some(path) = path else {
+        let mut node = Node::new("chip", &chip.chip.name, &chip.chip.description);
+        for peripheral in &chip.chip.peripherals {
+            node.children.push(peripheral_node(peripheral));
+        }
+        return Ok(node);
+    };
+
+    let mut segments = path.split('.').map(parse_segment);
+    let (name, idx) = segments.next().unwrap()?;
+    ensure!(idx.is_none(), "peripheral {name} is not an array");
+
+    let peripheral = find(&chip.chip.peripherals, name, |p| &p.name)
+        .with_context(|| format!("unknown peripheral {name}"))?;
+    let module = index.import(chip, &peripheral.module)?;
+    let block = module.block(&peripheral.block_name)?;
+    let mut address = peripheral.address;
+
+    let mut node = peripheral_node(peripheral);
+    node.children = block_children(module, block, address)?;
+    let mut state = State::Block(block);
+
+    for segment in segments {
+        let (name, idx) = segment?;
+
+        state = match state {
+            State::Block(block) => {
+                let field = find(&block.fields, name, |f| &f.name)
+                    .with_context(|| format!("unknown register {name} in {}", node.name))?;
+                address += field.byte_offset + array_offset(&field.array, idx, name)?;
+
+                if let Some(sub_block) = module.field_block(field)? {
+                    node = field_node(module, field, address)?;
+                    node.children = block_children(module, sub_block, address)?;
+                    State::Block(sub_block)
+                } else {
+                    let bitfield = module.field_bitfield(field)?;
+                    node = field_node(module, field, address)?;
+                    node.variants = module
+                        .field_enum(field)?
+                        .map(|enum_name| enum_name.variants.clone())
+                        .unwrap_or_default();
+                    if let Some(bitfield) = bitfield {
+                        node.children = bitfield_children(module, bitfield, field_access(field))?;
+                    }
+                    State::Register(field, bitfield)
+                }
+            }
+            State::Register(register, bitfield) => {
+                let Some(bitfield) = bitfield else {
+                    bail!("register {} has no fields", register.name);
+                };
+
+                let field = find(&bitfield.fields, name, |f| &f.name)
+                    .with_context(|| format!("unknown field {name} in {}", register.name))?;
+                let bit_offset = field.bit_offset as u64 + array_offset(&field.array, idx, name)?;
+
+                node = bitfield_field_node(module, bitfield, field, field_access(register))?;
+                node.bit_offset = Some(bit_offset as u32);
+                node.reset = Some(reset_value(bitfield, bit_offset as u32, field.bit_size));
+                node.variants = module
+                    .bitfield_field_enum(field)?
+                    .map(|enum_name| enum_name.variants.clone())
+                    .unwrap_or_default();
+                State::Field
+            }
+            State::Field => bail!("field {} has no children", node.name),
+        };
+    }
+
+    Ok(node)
+}
+
+/// Split `NAME[IDX]` into its parts.
+fn parse_segment(segment: &str) -> Result<(&str, Option<u64>)> {
+    let Some(rest) = segment.strip_suffix(']') else {
+        return Ok((segment, None));
+    };
+
+    let (name, idx) = rest
+        .split_once('[')
+        .with_context(|| format!("malformed path segment {segment}"))?;
+    let idx = idx
+        .parse()
+        .with_context(|| format!("malformed index in {segment}"))?;
+
+    Ok((name, Some(idx)))
+}
+
+/// Look up an item by name, falling back to a case insensitive match.
+fn find<'a, T>(items: &'a [T], name: &str, key: impl Fn(&T) -> &String) -> Option<&'a T> {
+    items.iter().find(|item| key(item) == name).or_else(|| {
+        items
+            .iter()
+            .find(|item| key(item).eq_ignore_ascii_case(name))
+    })
+}
+
+fn array_offset(array: &Option<ir::Array>, idx: Option<u64>, name: &str) -> Result<u64> {
+    match (array, idx) {
+        (Some(array), Some(idx)) => {
+            ensure!(
+                idx < array.len,
+                "index {idx} out of bounds for {name}[{}]",
+                array.len
+            );
+            Ok(idx * array.stride)
+        }
+        (None, Some(_)) => bail!("{name} is not an array"),
+        (_, None) => Ok(0),
+    }
+}
+
+fn field_access(field: &ir::block::Field) -> Option<ir::Access> {
+    match &field.inner {
+        ir::block::FieldInner::Block(_) => None,
+        ir::block::FieldInner::Bitfield(inner) => Some(inner.access.clone()),
+        ir::block::FieldInner::Simple(inner) => Some(inner.access.clone()),
+    }
+}
+
+fn reset_value(bitfield: &ir::Bitfield, bit_offset: u32, bit_size: u32) -> u64 {
+    let mask = 1u64.checked_shl(bit_size).map_or(u64::MAX, |bit| bit - 1);
+    bitfield.default.checked_shr(bit_offset).unwrap_or(0) & mask
+}
+
+fn peripheral_node(peripheral: &ir::chip::Peripheral) -> Node {
+    let mut node = Node::new("peripheral", &peripheral.name, &peripheral.description);
+    node.address = Some(peripheral.address);
+    node.type_name = Some(format!("{}::{}", peripheral.module, peripheral.block_name));
+    node
+}
+
+fn field_node(module: &ModuleIndex, field: &ir::block::Field, address: u64) -> Result<Node> {
+    let kind = match &field.inner {
+        ir::block::FieldInner::Block(_) => "block",
+        _ => "register",
+    };
+
+    let mut node = Node::new(kind, &field.name, &field.description);
+    node.address = Some(address);
+    node.array = field.array.clone();
+    node.access = field_access(field);
+
+    match &field.inner {
+        ir::block::FieldInner::Block(inner) => {
+            node.type_name = Some(inner.block_name.clone());
+        }
+        ir::block::FieldInner::Bitfield(inner) => {
+            let bitfield = module.bitfield(&inner.bitfield_name)?;
+            node.bit_size = Some(bitfield.bit_size);
+            node.reset = Some(bitfield.default);
+            node.type_name = Some(inner.bitfield_name.clone());
+        }
+        ir::block::FieldInner::Simple(inner) => {
+            node.bit_size = Some(inner.bit_size);
+            node.type_name = inner.enum_name.clone();
+        }
+    }
+
+    Ok(node)
+}
+
+fn bitfield_field_node(
+    module: &ModuleIndex,
+    bitfield: &ir::Bitfield,
+    field: &ir::bitfield::Field,
+    access: Option<ir::Access>,
+) -> Result<Node> {
+    if let Some(enum_name) = &field.enum_name {
+        module.enum_name(enum_name)?;
+    }
+
+    let mut node = Node::new("field", &field.name, &field.description);
+    node.array = field.array.clone();
+    node.access = access;
+    node.bit_offset = Some(field.bit_offset);
+    node.bit_size = Some(field.bit_size);
+    node.reset = Some(reset_value(bitfield, field.bit_offset, field.bit_size));
+    node.type_name = field.enum_name.clone();
+
+    Ok(node)
+}
+
+fn block_children(module: &ModuleIndex, block: &ir::Block, address: u64) -> Result<Vec<Node>> {
+    block
+        .fields
+        .iter()
+        .map(|field| field_node(module, field, address + field.byte_offset))
+        .collect()
+}
+
+fn bitfield_children(
+    module: &ModuleIndex,
+    bitfield: &ir::Bitfield,
+    access: Option<ir::Access>,
+) -> Result<Vec<Node>> {
+    bitfield
+        .fields
+        .iter()
+        .map(|field| bitfield_field_node(module, bitfield, field, access.clone()))
+        .collect()
+}
+
+fn print_node(out: &mut impl Write, node: &Node, depth: usize) -> io::Result<()> {
+    write!(
+        out,
+        "{:indent$}{} {}",
+        "",
+        node.kind,
+        node.name,
+        indent = depth * 2
+    )?;
+
+    if let Some(array) = &node.array {
+        write!(out, "[{}; stride {:#x}]", array.len, array.stride)?;
+    }
+    if let Some(address) = node.address {
+        write!(out, " @ {address:#010x}")?;
+    }
+    match (node.bit_offset, node.bit_size) {
+        (Some(offset), Some(1)) => write!(out, " [{offset}]")?,
+        (Some(offset), Some(size)) => write!(out, " [{}:{offset}]", offset + size - 1)?,
+        (None, Some(size)) => write!(out, " {size} bits")?,
+        _ => {}
+    }
+    if let Some(access) = &node.access {
+        let access = match access {
+            ir::Access::ReadWrite => "rw",
+            ir::Access::Read => "ro",
+            ir::Access::Write => "wo",
+        };
+        write!(out, " {access}")?;
+    }
+    if let Some(reset) = node.reset {
+        write!(out, " reset {reset:#x}")?;
+    }
+    if let Some(type_name) = &node.type_name {
+        write!(out, " ({type_name})")?;
+    }
+    if let Some(description) = &node.description {
+        let description = description.lines().next().unwrap_or_default().trim();
+        write!(out, " - {description}")?;
+    }
+    writeln!(out)?;
+
+    for variant in &node.variants {
+        write!(
+            out,
+            "{:indent$}= {:#x} {}",
+            "",
+            variant.value,
+            variant.name,
+            indent = depth * 2 + 2
+        )?;
+        if let Some(description) = &variant.description {
+            write!(out, " - {description}")?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
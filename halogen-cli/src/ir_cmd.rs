@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result};
+
+use halogen_ir::format::FORMAT_VERSION;
+use halogen_ir::load;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        #[command(subcommand)]
+        pub cmd: Cmds,
+    }
+
+    #[derive(Debug, clap::Subcommand)]
+    pub enum Cmds {
+        /// Rewrite IRs in place in the current format version
+        Upgrade(UpgradeArgs),
+    }
+
+    #[derive(Debug, clap::Args)]
+    pub struct UpgradeArgs {
+        /// Halogen IRs to upgrade, as single files or multi-file indices.
+        #[arg(required = true)]
+        pub inputs: Vec<PathBuf>,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    match &args.cmd {
+        args::Cmds::Upgrade(args) => upgrade(args),
+    }
+}
+
+fn upgrade(args: &args::UpgradeArgs) -> Result<()> {
+    for input in &args.inputs {
+        let format_version = load::upgrade_file(input)
+            .with_context(|| format!("failed to upgrade {}", input.display()))?;
+
+        if format_version == FORMAT_VERSION {
+            log::info!("{} is up to date", input.display());
+        } else {
+            log::info!(
+                "upgraded {} from format version {format_version} to {FORMAT_VERSION}",
+                input.display()
+            );
+        }
+    }
+
+    Ok(())
+}
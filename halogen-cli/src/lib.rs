@@ -1,13 +1,25 @@
 use std::ffi::OsStr;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 
-use halogen_ir::ir;
 use halogen_ir::load::*;
+use halogen_ir::repository::IrRepository;
+use halogen_ir::{arrays, glob, ir};
 
+pub mod diff;
+#[cfg(feature = "frontend-cortex-m")]
+pub mod gen_core;
 pub mod gen_rust;
+pub mod inspect;
+pub mod ir_cmd;
+pub mod merge;
+pub mod patch;
+#[cfg(feature = "frontend-riscv")]
+pub mod riscv_csrs;
+pub mod schema;
+pub mod stats;
 pub mod stm32_data_convert;
 
 #[derive(Debug, clap::Parser)]
@@ -21,18 +33,79 @@ pub struct Args {
 pub enum Cmds {
     Stm32DataConvert(stm32_data_convert::args::Args),
     GenRust(gen_rust::args::Args),
+    #[cfg(feature = "frontend-cortex-m")]
+    GenCore(gen_core::args::Args),
+    #[cfg(feature = "frontend-riscv")]
+    RiscvCsrs(riscv_csrs::args::Args),
+    Patch(patch::args::Args),
+    Diff(diff::args::Args),
+    Merge(merge::args::Args),
+    Inspect(inspect::args::Args),
+    Stats(stats::args::Args),
+    Schema(schema::args::Args),
+    Ir(ir_cmd::args::Args),
+}
+
+// Options of the array inference pass, flattened into the commands running it.
+// Not a doc comment, clap would use it as the about of those commands.
+#[derive(Debug, clap::Args)]
+pub struct InferArgs {
+    /// Collapse repeated fields (CCR1, CCR2, ...) into arrays
+    #[arg(long)]
+    pub infer_arrays: bool,
+    /// Minimum number of repeated fields needed to build an array
+    #[arg(long, default_value_t = 3, requires = "infer_arrays")]
+    pub array_min_len: usize,
+    /// Only consider the fields matching this glob
+    #[arg(long, default_value = "*", requires = "infer_arrays")]
+    pub array_filter: String,
+    /// Do not group repeated registers sharing a prefix into sub-blocks
+    #[arg(long, requires = "infer_arrays")]
+    pub no_clusters: bool,
 }
 
 fn load_ir(path: impl AsRef<Path>) -> Result<ir::MultiChip> {
-    if path.as_ref() == OsStr::new("-") {
-        Ok(load_reader(io::stdin().lock())?)
-    } else {
-        Ok(load_multi_file(path)?)
+    let path = path.as_ref();
+
+    if path == OsStr::new("-") {
+        return Ok(load_reader(io::stdin().lock())?);
     }
+
+    #[cfg(feature = "bundle")]
+    if halogen_ir::bundle::is_bundle_file(path)? {
+        return Ok(halogen_ir::bundle::load_bundle_file(path)?);
+    }
+
+    Ok(load_multi_file(path)?)
+}
+
+/// Open the IR for on demand loading, when it is a multi-file index.
+fn open_repository(path: impl AsRef<Path>) -> Result<IrRepository> {
+    let path = path.as_ref();
+
+    #[cfg(feature = "bundle")]
+    let is_bundle = path != OsStr::new("-") && halogen_ir::bundle::is_bundle_file(path)?;
+    #[cfg(not(feature = "bundle"))]
+    let is_bundle = false;
+
+    if path == OsStr::new("-") || is_bundle {
+        return Ok(IrRepository::from_ir(load_ir(path)?));
+    }
+
+    Ok(IrRepository::open(path)?)
 }
 
+/// Dump the IR, as a bundle if the path has the bundle extension.
 fn dump_ir(path: impl AsRef<Path>, ir: &ir::MultiChip, multi: bool) -> Result<()> {
-    if path.as_ref() == OsStr::new("-") {
+    let path = path.as_ref();
+
+    #[cfg(feature = "bundle")]
+    if !multi && path.extension() == Some(OsStr::new(halogen_ir::bundle::EXTENSION)) {
+        halogen_ir::bundle::dump_bundle_file(path, ir)?;
+        return Ok(());
+    }
+
+    if path == OsStr::new("-") {
         dump_writer(io::stdout().lock(), ir)?;
     } else if multi {
         dump_multi_file(path, ir)?;
@@ -42,3 +115,27 @@ fn dump_ir(path: impl AsRef<Path>, ir: &ir::MultiChip, multi: bool) -> Result<()
 
     Ok(())
 }
+
+fn apply_patches(ir: &mut ir::MultiChip, paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        let patch = halogen_ir::patch::load_file(path)
+            .with_context(|| format!("failed to load patch {}", path.display()))?;
+        patch
+            .apply(ir)
+            .with_context(|| format!("failed to apply patch {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+fn infer_arrays(ir: &mut ir::MultiChip, args: &InferArgs) {
+    if args.infer_arrays {
+        let settings = arrays::Settings {
+            min_len: args.array_min_len,
+            filter: glob::Glob::new(&args.array_filter),
+            clusters: !args.no_clusters,
+        };
+
+        arrays::infer(ir, &settings);
+    }
+}
@@ -16,7 +16,7 @@ fn main() -> ExitCode {
     match try_main(&args) {
         Ok(_) => ExitCode::SUCCESS,
         Err(err) => {
-            log::error!("{err}");
+            log::error!("{err:#}");
             ExitCode::FAILURE
         }
     }
@@ -24,7 +24,18 @@ fn main() -> ExitCode {
 
 fn try_main(args: &Args) -> Result<()> {
     match &args.cmd {
-        Cmds::GenRust(args) => gen_rust::run(&args),
-        Cmds::Stm32DataConvert(args) => stm32_data_convert::run(&args),
+        Cmds::GenRust(args) => gen_rust::run(args),
+        #[cfg(feature = "frontend-cortex-m")]
+        Cmds::GenCore(args) => gen_core::run(args),
+        #[cfg(feature = "frontend-riscv")]
+        Cmds::RiscvCsrs(args) => riscv_csrs::run(args),
+        Cmds::Patch(args) => patch::run(args),
+        Cmds::Diff(args) => diff::run(args),
+        Cmds::Merge(args) => merge::run(args),
+        Cmds::Inspect(args) => inspect::run(args),
+        Cmds::Stats(args) => stats::run(args),
+        Cmds::Schema(args) => schema::run(args),
+        Cmds::Ir(args) => ir_cmd::run(args),
+        Cmds::Stm32DataConvert(args) => stm32_data_convert::run(args),
     }
 }
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{dump_ir, load_ir};
+use halogen_ir::{merge, resolve};
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Input halogen IRs, merged in order.
+        #[arg(required = true, num_args = 2..)]
+        pub inputs: Vec<PathBuf>,
+        /// Output path of the merged IR, `.bundle` files are written as bundles
+        #[arg(short, long)]
+        pub output: PathBuf,
+        /// What to do with chips and modules defined differently by two inputs
+        #[arg(long, value_enum, default_value_t = Policy::Error)]
+        pub policy: Policy,
+        /// Output using the multi-file IR format.
+        #[arg(long, default_value_t = false)]
+        pub multi: bool,
+    }
+
+    #[derive(Debug, Clone, Copy, clap::ValueEnum)]
+    pub enum Policy {
+        /// Fail the merge
+        Error,
+        /// Keep the definition from the first input
+        PreferLeft,
+        /// Keep the definition from the last input
+        PreferRight,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let irs = args
+        .inputs
+        .iter()
+        .map(load_ir)
+        .collect::<Result<Vec<_>>>()?;
+
+    let merged = match merge::merge(irs, to_policy(args.policy)) {
+        Ok(merged) => merged,
+        Err(err) => {
+            for conflict in &err.conflicts {
+                log_conflict(conflict);
+            }
+            return Err(err.into());
+        }
+    };
+
+    for path in &merged.duplicates {
+        log::debug!("{path} is defined multiple times identically");
+    }
+    for conflict in &merged.conflicts {
+        log_conflict(conflict);
+    }
+
+    for err in resolve::Index::new(&merged.ir).validate() {
+        log::warn!("{err}");
+    }
+
+    dump_ir(&args.output, &merged.ir, args.multi)?;
+
+    Ok(())
+}
+
+fn log_conflict(conflict: &merge::Conflict) {
+    log::warn!("{conflict}");
+    for change in &conflict.changes {
+        log::warn!("  {change}");
+    }
+}
+
+fn to_policy(policy: args::Policy) -> merge::Policy {
+    match policy {
+        args::Policy::Error => merge::Policy::Error,
+        args::Policy::PreferLeft => merge::Policy::PreferLeft,
+        args::Policy::PreferRight => merge::Policy::PreferRight,
+    }
+}
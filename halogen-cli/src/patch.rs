@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{apply_patches, dump_ir, infer_arrays, load_ir, InferArgs};
+use halogen_ir::resolve;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Input halogen IR.
+        #[arg(short, long)]
+        pub input: PathBuf,
+        /// Output path of the patched IR, `.bundle` files are written as bundles
+        #[arg(short, long)]
+        pub output: PathBuf,
+        /// Patch files to apply in order (YAML, or JSON with a .json extension)
+        #[arg(short, long)]
+        pub patch: Vec<PathBuf>,
+        /// Output using the multi-file IR format.
+        #[arg(long, default_value_t = false)]
+        pub multi: bool,
+        #[command(flatten)]
+        pub infer: InferArgs,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let mut ir = load_ir(&args.input)?;
+
+    apply_patches(&mut ir, &args.patch)?;
+    infer_arrays(&mut ir, &args.infer);
+
+    for err in resolve::Index::new(&ir).validate() {
+        log::warn!("{err}");
+    }
+
+    dump_ir(&args.output, &ir, args.multi)?;
+
+    Ok(())
+}
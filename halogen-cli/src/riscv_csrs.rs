@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Result};
+
+use crate::{dump_ir, load_ir};
+use halogen_frontend::riscv;
+use halogen_ir::resolve;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Input halogen IR.
+        #[arg(short, long)]
+        pub input: PathBuf,
+        /// Output path of the IR, `.bundle` files are written as bundles
+        #[arg(short, long)]
+        pub output: PathBuf,
+        /// Only add the CSRs to the chips matching this regex
+        #[arg(long)]
+        pub chips: Option<regex::Regex>,
+        /// Output using the multi-file IR format.
+        #[arg(long, default_value_t = false)]
+        pub multi: bool,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let mut ir = load_ir(&args.input)?;
+
+    let is_match = |chip: &halogen_ir::ir::Chip| {
+        args.chips
+            .as_ref()
+            .is_none_or(|chips| chips.is_match(&chip.name))
+    };
+    if let Some(chips) = &args.chips {
+        ensure!(ir.chips.iter().any(is_match), "no chip matches {chips}");
+    }
+
+    riscv::add_privileged_csrs(&mut ir, is_match)?;
+
+    for err in resolve::Index::new(&ir).validate() {
+        log::warn!("{err}");
+    }
+
+    dump_ir(&args.output, &ir, args.multi)?;
+
+    Ok(())
+}
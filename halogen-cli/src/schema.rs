@@ -0,0 +1,41 @@
+use std::io::{self, Write as _};
+
+use anyhow::Result;
+
+use halogen_ir::schema;
+
+pub mod args {
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// File the schema describes
+        #[arg(long, value_enum, default_value_t = Kind::Ir)]
+        pub kind: Kind,
+    }
+
+    #[derive(Debug, Clone, Copy, clap::ValueEnum)]
+    pub enum Kind {
+        /// Single-file IR
+        Ir,
+        /// Index of a multi-file IR, or a single-file IR
+        Index,
+        /// Chip file of a multi-file IR
+        Chip,
+        /// Module file of a multi-file IR
+        Module,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let schema = match args.kind {
+        args::Kind::Ir => schema::multi_chip(),
+        args::Kind::Index => schema::index(),
+        args::Kind::Chip => schema::chip(),
+        args::Kind::Module => schema::module(),
+    };
+
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &schema)?;
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::load_ir;
+use halogen_ir::stats;
+
+pub mod args {
+    use super::*;
+
+    #[derive(Debug, clap::Args)]
+    pub struct Args {
+        /// Input halogen IR.
+        pub input: PathBuf,
+        /// Number of most shared modules to list
+        #[arg(long, default_value_t = 10)]
+        pub top: usize,
+        /// Print the statistics as JSON
+        #[arg(long)]
+        pub json: bool,
+    }
+}
+
+pub fn run(args: &args::Args) -> Result<()> {
+    let ir = load_ir(&args.input)?;
+
+    let mut stats = stats::stats(&ir);
+    stats.module_usage.truncate(args.top);
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    writeln!(out, "chips            {:>8}", stats.chips)?;
+    writeln!(out, "peripherals      {:>8}", stats.peripherals)?;
+    writeln!(out, "modules          {:>8}", stats.modules)?;
+    writeln!(out, "module versions  {:>8}", stats.module_versions)?;
+    writeln!(out, "blocks           {:>8}", stats.blocks)?;
+    writeln!(out, "registers        {:>8}", stats.registers)?;
+    writeln!(out, "fields           {:>8}", stats.fields)?;
+    writeln!(out, "enums            {:>8}", stats.enums)?;
+    writeln!(out, "variants         {:>8}", stats.variants)?;
+
+    writeln!(out)?;
+    writeln!(out, "coverage:")?;
+    let ratios = [
+        (
+            "registers without description",
+            stats.registers_without_description,
+            stats.registers,
+        ),
+        (
+            "fields without description",
+            stats.fields_without_description,
+            stats.fields,
+        ),
+        (
+            "multi-bit fields without enum",
+            stats.fields_without_enum,
+            stats.multi_bit_fields,
+        ),
+    ];
+    for (label, count, total) in ratios {
+        writeln!(out, "  {label:<30} {count:>8} ({})", percent(count, total))?;
+    }
+
+    if !stats.skipped.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "skipped by the frontend:")?;
+        for (reason, count) in &stats.skipped {
+            writeln!(out, "  {reason:<30} {count:>8}")?;
+        }
+    }
+
+    if !stats.module_usage.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "most shared modules:")?;
+        for usage in &stats.module_usage {
+            let name = match &usage.version {
+                Some(version) => format!("{} {version}", usage.name),
+                None => usage.name.clone(),
+            };
+            writeln!(out, "  {name:<30} {:>8} chips", usage.chips)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn percent(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+
+    format!("{:.1}%", count as f64 * 100.0 / total as f64)
+}
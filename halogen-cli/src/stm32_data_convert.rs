@@ -2,8 +2,9 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 
-use crate::dump_ir;
+use crate::{apply_patches, dump_ir, infer_arrays, InferArgs};
 use halogen_frontend::stm32_data;
+use halogen_ir::resolve;
 
 pub mod args {
     use super::*;
@@ -13,23 +14,36 @@ pub mod args {
         /// Root of the stm32-data folder
         #[arg(short, long)]
         pub input: PathBuf,
-        /// Output path of the IR
+        /// Output path of the IR, `.bundle` files are written as bundles
         #[arg(short, long)]
         pub output: PathBuf,
         /// Regex filtering which boards will actually be included in the generated IR.
         #[arg(long)]
         pub filter: Option<regex::Regex>,
+        /// Patch files applied to the generated IR, in order
+        #[arg(short, long)]
+        pub patch: Vec<PathBuf>,
         /// Output using the multi-file IR format.
         #[arg(long, default_value_t = false)]
         pub multi: bool,
+        #[command(flatten)]
+        pub infer: InferArgs,
     }
 }
 
 pub fn run(args: &args::Args) -> Result<()> {
     // First generate IR
-    let ir = stm32_data::convert_multi_chips(&args.input, args.filter.as_ref())?;
+    let mut ir = stm32_data::convert_multi_chips(&args.input, args.filter.as_ref())?;
+
+    // Then fix it up
+    apply_patches(&mut ir, &args.patch)?;
+    infer_arrays(&mut ir, &args.infer);
+
+    for err in resolve::Index::new(&ir).validate() {
+        log::warn!("{err}");
+    }
 
-    // TODO: Do a bit of postprocess on the IR (normalization, dead stuff elimination, validation)
+    // TODO: Do a bit of postprocess on the IR (normalization, dead stuff elimination)
 
     // Finally dump IR
     dump_ir(&args.output, &ir, args.multi)?;
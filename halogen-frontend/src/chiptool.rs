@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::{Context as _, Result, ensure};
 use chiptool::ir as cir;
 use log::warn;
@@ -8,16 +10,20 @@ pub fn convert_chiptool_source(
     name: String,
     version: Option<String>,
     source: &str,
+    origin: ir::Source,
 ) -> Result<ir::Module> {
     let data: cir::IR = serde_json::from_str(source).context("failed to parse chiptool json")?;
 
-    convert_chiptool(name, version, data)
+    convert_chiptool(name, version, data, origin)
 }
 
+/// Convert a chiptool IR into a module. Every item gets a copy of `origin` as
+/// its source, pointing to where it is defined in the chiptool IR.
 pub fn convert_chiptool(
     name: String,
     version: Option<String>,
     mut data: cir::IR,
+    origin: ir::Source,
 ) -> Result<ir::Module> {
     let res = chiptool::validate::validate(
         &data,
@@ -36,6 +42,17 @@ pub fn convert_chiptool(
         res.join("\n")
     );
 
+    // Inherited items are pointed to in the block or fieldset they come from
+    let origins = Origins {
+        blocks: own_items(&data.blocks, |block| {
+            block.items.iter().map(|item| &item.name)
+        }),
+        fieldsets: own_items(&data.fieldsets, |fieldset| {
+            fieldset.fields.iter().map(|field| &field.name)
+        }),
+        origin,
+    };
+
     // Expand all definitions
     chiptool::transform::expand_extends::ExpandExtends {}.run(&mut data)?;
 
@@ -46,24 +63,98 @@ pub fn convert_chiptool(
         blocks: Vec::new(),
         bitfields: Vec::new(),
         enums: Vec::new(),
+        skipped: Vec::new(),
+        source: Some(origins.origin.clone()),
     };
 
     for (name, block) in data.blocks {
-        out.blocks.push(convert_block(name, block));
+        out.blocks
+            .push(convert_block(name, block, &origins, &mut out.skipped));
     }
 
     for (name, fieldset) in data.fieldsets {
-        out.bitfields.push(convert_fieldset(name, fieldset));
+        out.bitfields
+            .push(convert_fieldset(name, fieldset, &origins, &mut out.skipped));
     }
 
     for (name, enum_name) in data.enums {
-        out.enums.push(convert_enum(name, enum_name));
+        let source = origins.source(&["enums", &name]);
+        out.enums.push(convert_enum(name, enum_name, source));
     }
 
     Ok(out)
 }
 
-fn convert_block(name: String, block: cir::Block) -> ir::Block {
+/// Where the items of the chiptool IR are defined, before expanding
+/// `extends`.
+struct Origins {
+    /// Items defined by each block
+    blocks: HashMap<String, Vec<String>>,
+    /// Fields defined by each fieldset
+    fieldsets: HashMap<String, Vec<String>>,
+    origin: ir::Source,
+}
+
+impl Origins {
+    fn source(&self, segments: &[&str]) -> ir::Source {
+        let mut source = self.origin.clone();
+        source.pointer = Some(json_pointer(segments));
+        source
+    }
+
+    fn block_item(&self, block: &str, name: &str) -> ir::Source {
+        self.item(&self.blocks, ["blocks", block, "items"], name)
+    }
+
+    fn fieldset_field(&self, fieldset: &str, name: &str) -> ir::Source {
+        self.item(&self.fieldsets, ["fieldsets", fieldset, "fields"], name)
+    }
+
+    /// Source of an item of a block or fieldset, pointing to its parent if
+    /// the item is inherited.
+    fn item(
+        &self,
+        own: &HashMap<String, Vec<String>>,
+        [kind, parent, items]: [&str; 3],
+        name: &str,
+    ) -> ir::Source {
+        match own
+            .get(parent)
+            .and_then(|own| own.iter().position(|item| item == name))
+        {
+            Some(index) => self.source(&[kind, parent, items, &index.to_string()]),
+            None => self.source(&[kind, parent]),
+        }
+    }
+}
+
+fn own_items<'a, T, I>(
+    items: &'a BTreeMap<String, T>,
+    names: impl Fn(&'a T) -> I,
+) -> HashMap<String, Vec<String>>
+where
+    I: Iterator<Item = &'a String>,
+{
+    items
+        .iter()
+        .map(|(name, item)| (name.clone(), names(item).cloned().collect()))
+        .collect()
+}
+
+/// Build a JSON pointer, as defined by RFC 6901.
+pub(crate) fn json_pointer(segments: &[&str]) -> String {
+    segments
+        .iter()
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+fn convert_block(
+    name: String,
+    block: cir::Block,
+    origins: &Origins,
+    skipped: &mut Vec<ir::module::Skipped>,
+) -> ir::Block {
     let mut fields = Vec::new();
     for item in block.items {
         let array = match item.array {
@@ -73,6 +164,7 @@ fn convert_block(name: String, block: cir::Block) -> ir::Block {
             }),
             Some(cir::Array::Cursed(_)) => {
                 warn!("skipped cursed array field {} in block {}", item.name, name);
+                skipped.push(skip(&name, item.name, "cursed array"));
                 continue;
             }
             None => None,
@@ -108,23 +200,31 @@ fn convert_block(name: String, block: cir::Block) -> ir::Block {
             }
         };
 
+        let source = origins.block_item(&name, &item.name);
         fields.push(ir::block::Field {
             name: item.name,
             description: item.description,
             array,
             byte_offset: item.byte_offset as u64,
+            source: Some(source),
             inner,
         });
     }
 
     ir::Block {
+        source: Some(origins.source(&["blocks", &name])),
         name,
         description: block.description,
         fields,
     }
 }
 
-fn convert_fieldset(name: String, fieldset: cir::FieldSet) -> ir::Bitfield {
+fn convert_fieldset(
+    name: String,
+    fieldset: cir::FieldSet,
+    origins: &Origins,
+    skipped: &mut Vec<ir::module::Skipped>,
+) -> ir::Bitfield {
     let mut fields = Vec::new();
     for field in fieldset.fields {
         let array = match field.array {
@@ -137,6 +237,7 @@ fn convert_fieldset(name: String, fieldset: cir::FieldSet) -> ir::Bitfield {
                     "skipped cursed array field {} in fieldset {}",
                     field.name, name
                 );
+                skipped.push(skip(&name, field.name, "cursed array"));
                 continue;
             }
             None => None,
@@ -149,10 +250,12 @@ fn convert_fieldset(name: String, fieldset: cir::FieldSet) -> ir::Bitfield {
                     "skipped cursed bit offset field {} in fieldset {}",
                     field.name, name
                 );
+                skipped.push(skip(&name, field.name, "cursed bit offset"));
                 continue;
             }
         };
 
+        let source = origins.fieldset_field(&name, &field.name);
         fields.push(ir::bitfield::Field {
             name: field.name,
             description: field.description,
@@ -160,10 +263,12 @@ fn convert_fieldset(name: String, fieldset: cir::FieldSet) -> ir::Bitfield {
             bit_offset,
             bit_size: field.bit_size,
             enum_name: field.enumm,
+            source: Some(source),
         });
     }
 
     ir::Bitfield {
+        source: Some(origins.source(&["fieldsets", &name])),
         name,
         description: fieldset.description,
         bit_size: fieldset.bit_size,
@@ -172,7 +277,15 @@ fn convert_fieldset(name: String, fieldset: cir::FieldSet) -> ir::Bitfield {
     }
 }
 
-fn convert_enum(name: String, enum_name: cir::Enum) -> ir::Enum {
+fn skip(parent: &str, name: String, reason: &str) -> ir::module::Skipped {
+    ir::module::Skipped {
+        parent: parent.to_string(),
+        name,
+        reason: reason.to_string(),
+    }
+}
+
+fn convert_enum(name: String, enum_name: cir::Enum, source: ir::Source) -> ir::Enum {
     let variants = enum_name
         .variants
         .into_iter()
@@ -188,5 +301,6 @@ fn convert_enum(name: String, enum_name: cir::Enum) -> ir::Enum {
         description: enum_name.description,
         bit_size: enum_name.bit_size,
         variants,
+        source: Some(source),
     }
 }
@@ -0,0 +1,852 @@
+//! Built-in description of the Cortex-M cores, following the ARMv6-M, ARMv7-M
+//! and ARMv8-M architecture reference manuals.
+//!
+//! Every core becomes a chip named like [`Stm32Ext::cm_name`], instantiating
+//! the core peripherals and listing the special registers. Modules are shared
+//! between cores of the same architecture.
+//!
+//! [`Stm32Ext::cm_name`]: halogen_ir::ir::chip::Stm32Ext::cm_name
+
+use anyhow::{Context as _, Result, anyhow};
+
+use halogen_ir::builder::*;
+use halogen_ir::ir::{self, Access};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arch {
+    V6m,
+    V7m,
+    V8m,
+}
+
+impl Arch {
+    fn version(self) -> &'static str {
+        match self {
+            Arch::V6m => "v6m",
+            Arch::V7m => "v7m",
+            Arch::V8m => "v8m",
+        }
+    }
+}
+
+struct Core {
+    name: &'static str,
+    description: &'static str,
+    arch: Arch,
+    /// Architecture of the protected memory system, if any
+    mpu: Option<Arch>,
+    fpu: bool,
+    dwt: bool,
+}
+
+static CORES: &[Core] = &[
+    Core {
+        name: "cm0",
+        description: "Cortex-M0",
+        arch: Arch::V6m,
+        mpu: None,
+        fpu: false,
+        dwt: false,
+    },
+    Core {
+        name: "cm0p",
+        description: "Cortex-M0+",
+        arch: Arch::V6m,
+        mpu: Some(Arch::V7m),
+        fpu: false,
+        dwt: false,
+    },
+    Core {
+        name: "cm3",
+        description: "Cortex-M3",
+        arch: Arch::V7m,
+        mpu: Some(Arch::V7m),
+        fpu: false,
+        dwt: true,
+    },
+    Core {
+        name: "cm4",
+        description: "Cortex-M4",
+        arch: Arch::V7m,
+        mpu: Some(Arch::V7m),
+        fpu: true,
+        dwt: true,
+    },
+    Core {
+        name: "cm7",
+        description: "Cortex-M7",
+        arch: Arch::V7m,
+        mpu: Some(Arch::V7m),
+        fpu: true,
+        dwt: true,
+    },
+    Core {
+        name: "cm33",
+        description: "Cortex-M33",
+        arch: Arch::V8m,
+        mpu: Some(Arch::V8m),
+        fpu: true,
+        dwt: true,
+    },
+];
+
+/// Names of the supported cores.
+pub fn core_names() -> impl Iterator<Item = &'static str> {
+    CORES.iter().map(|core| core.name)
+}
+
+/// Describe the given cores, along with the modules they use.
+pub fn convert_cores<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<ir::MultiChip> {
+    let mut out = ir::MultiChip {
+        chips: Vec::new(),
+        modules: Vec::new(),
+    };
+
+    for name in names {
+        let core = CORES
+            .iter()
+            .find(|core| core.name == name)
+            .ok_or_else(|| anyhow!("unknown Cortex-M core {name}"))?;
+
+        let chip = convert_core(core, &mut out.modules)
+            .with_context(|| format!("failed to describe core {name}"))?;
+        out.chips.push(chip);
+    }
+
+    Ok(out)
+}
+
+fn convert_core(core: &Core, modules: &mut Vec<ir::Module>) -> Result<ir::Chip> {
+    let mut chip = ChipBuilder::new(core.name)
+        .description(core.description)
+        .source(source());
+
+    let mut peripherals = vec![
+        ("SCB", 0xE000_ED00, scb(core.arch)?),
+        ("NVIC", 0xE000_E100, nvic(core.arch)?),
+        ("SYST", 0xE000_E010, systick()?),
+    ];
+    if let Some(arch) = core.mpu {
+        peripherals.push(("MPU", 0xE000_ED90, mpu(arch)?));
+    }
+    if core.fpu {
+        peripherals.push(("FPU", 0xE000_EF34, fpu()?));
+    }
+    if core.dwt {
+        peripherals.push(("DWT", 0xE000_1000, dwt(core.arch)?));
+    }
+
+    for (name, address, module) in peripherals {
+        let block_name = module.blocks[0].name.clone();
+        chip = chip
+            .add_import(&module.name, module.version.as_deref())
+            .add_peripheral(PeripheralBuilder::new(
+                name,
+                address,
+                &module.name,
+                block_name,
+            ));
+        add_module(modules, module);
+    }
+
+    let special = special(core.arch)?;
+    chip = chip.add_import(&special.name, special.version.as_deref());
+    for bitfield in &special.bitfields {
+        let name = bitfield.name.to_uppercase();
+        let reg_name = bitfield.name.to_lowercase();
+        chip = chip.add_cm_reg(
+            CmRegBuilder::new(name, reg_name, &special.name, &bitfield.name)
+                .description(bitfield.description.clone().unwrap_or_default()),
+        );
+    }
+    add_module(modules, special);
+
+    Ok(chip.build()?)
+}
+
+fn add_module(modules: &mut Vec<ir::Module>, module: ir::Module) {
+    let known = modules
+        .iter()
+        .any(|other| other.name == module.name && other.version == module.version);
+    if !known {
+        modules.push(module);
+    }
+}
+
+fn source() -> ir::Source {
+    ir::Source {
+        frontend: Some("cortex-m".into()),
+        ..Default::default()
+    }
+}
+
+fn module(name: &str, version: &str, description: &str) -> ModuleBuilder {
+    ModuleBuilder::new(name)
+        .version(version)
+        .description(description)
+        .source(source())
+}
+
+fn reg(name: &str, byte_offset: u64, bitfield: &str, description: &str) -> BlockFieldBuilder {
+    BlockFieldBuilder::register(name, byte_offset, bitfield).description(description)
+}
+
+fn word(name: &str, byte_offset: u64, description: &str) -> BlockFieldBuilder {
+    BlockFieldBuilder::simple(name, byte_offset, 32).description(description)
+}
+
+/// A 32 bit register layout, from name, bit offset and bit size of its
+/// fields.
+fn bitfield(name: &str, fields: &[(&str, u32, u32)]) -> BitfieldBuilder {
+    fields.iter().fold(
+        BitfieldBuilder::new(name, 32),
+        |bitfield, &(name, offset, size)| {
+            bitfield.add_field(BitfieldFieldBuilder::new(name, offset, size))
+        },
+    )
+}
+
+fn scb(arch: Arch) -> Result<ir::Module> {
+    let mut block = BlockBuilder::new("Scb")
+        .description("System control block")
+        .add_field(reg("CPUID", 0x00, "Cpuid", "CPUID base register").access(Access::Read))
+        .add_field(reg(
+            "ICSR",
+            0x04,
+            "Icsr",
+            "Interrupt control and state register",
+        ))
+        .add_field(reg(
+            "VTOR",
+            0x08,
+            "Vtor",
+            "Vector table offset register, optional on ARMv6-M",
+        ))
+        .add_field(reg(
+            "AIRCR",
+            0x0C,
+            "Aircr",
+            "Application interrupt and reset control register",
+        ))
+        .add_field(reg("SCR", 0x10, "Scr", "System control register"))
+        .add_field(reg(
+            "CCR",
+            0x14,
+            "Ccr",
+            "Configuration and control register",
+        ));
+
+    block = match arch {
+        Arch::V6m => block
+            .add_field(reg(
+                "SHPR2",
+                0x1C,
+                "Shpr",
+                "System handler priority register 2",
+            ))
+            .add_field(reg(
+                "SHPR3",
+                0x20,
+                "Shpr",
+                "System handler priority register 3",
+            )),
+        Arch::V7m | Arch::V8m => block
+            .add_field(reg("SHPR", 0x18, "Shpr", "System handler priority registers").array(3, 4))
+            .add_field(reg(
+                "SHCSR",
+                0x24,
+                "Shcsr",
+                "System handler control and state register",
+            ))
+            .add_field(reg(
+                "CFSR",
+                0x28,
+                "Cfsr",
+                "Configurable fault status register",
+            ))
+            .add_field(reg("HFSR", 0x2C, "Hfsr", "HardFault status register"))
+            .add_field(reg("DFSR", 0x30, "Dfsr", "Debug fault status register"))
+            .add_field(word("MMFAR", 0x34, "MemManage fault address register"))
+            .add_field(word("BFAR", 0x38, "BusFault address register"))
+            .add_field(word("AFSR", 0x3C, "Auxiliary fault status register"))
+            .add_field(reg(
+                "CPACR",
+                0x88,
+                "Cpacr",
+                "Coprocessor access control register",
+            )),
+    };
+
+    let icsr: &[_] = match arch {
+        Arch::V6m => &[
+            ("VECTACTIVE", 0, 9),
+            ("VECTPENDING", 12, 9),
+            ("ISRPENDING", 22, 1),
+            ("PENDSTCLR", 25, 1),
+            ("PENDSTSET", 26, 1),
+            ("PENDSVCLR", 27, 1),
+            ("PENDSVSET", 28, 1),
+            ("NMIPENDSET", 31, 1),
+        ],
+        Arch::V7m | Arch::V8m => &[
+            ("VECTACTIVE", 0, 9),
+            ("RETTOBASE", 11, 1),
+            ("VECTPENDING", 12, 9),
+            ("ISRPENDING", 22, 1),
+            ("ISRPREEMPT", 23, 1),
+            ("PENDSTCLR", 25, 1),
+            ("PENDSTSET", 26, 1),
+            ("PENDSVCLR", 27, 1),
+            ("PENDSVSET", 28, 1),
+            ("NMIPENDSET", 31, 1),
+        ],
+    };
+
+    let aircr: &[_] = match arch {
+        Arch::V6m => &[
+            ("VECTCLRACTIVE", 1, 1),
+            ("SYSRESETREQ", 2, 1),
+            ("ENDIANNESS", 15, 1),
+            ("VECTKEY", 16, 16),
+        ],
+        Arch::V7m => &[
+            ("VECTRESET", 0, 1),
+            ("VECTCLRACTIVE", 1, 1),
+            ("SYSRESETREQ", 2, 1),
+            ("PRIGROUP", 8, 3),
+            ("ENDIANNESS", 15, 1),
+            ("VECTKEY", 16, 16),
+        ],
+        Arch::V8m => &[
+            ("VECTCLRACTIVE", 1, 1),
+            ("SYSRESETREQ", 2, 1),
+            ("SYSRESETREQS", 3, 1),
+            ("PRIGROUP", 8, 3),
+            ("BFHFNMINS", 13, 1),
+            ("PRIS", 14, 1),
+            ("ENDIANNESS", 15, 1),
+            ("VECTKEY", 16, 16),
+        ],
+    };
+
+    let scr: &[_] = match arch {
+        Arch::V6m | Arch::V7m => &[
+            ("SLEEPONEXIT", 1, 1),
+            ("SLEEPDEEP", 2, 1),
+            ("SEVONPEND", 4, 1),
+        ],
+        Arch::V8m => &[
+            ("SLEEPONEXIT", 1, 1),
+            ("SLEEPDEEP", 2, 1),
+            ("SLEEPDEEPS", 3, 1),
+            ("SEVONPEND", 4, 1),
+        ],
+    };
+
+    let ccr: &[_] = match arch {
+        Arch::V6m => &[("UNALIGN_TRP", 3, 1), ("STKALIGN", 9, 1)],
+        Arch::V7m => &[
+            ("NONBASETHRDENA", 0, 1),
+            ("USERSETMPEND", 1, 1),
+            ("UNALIGN_TRP", 3, 1),
+            ("DIV_0_TRP", 4, 1),
+            ("BFHFNMIGN", 8, 1),
+            ("STKALIGN", 9, 1),
+            ("DC", 16, 1),
+            ("IC", 17, 1),
+            ("BP", 18, 1),
+        ],
+        Arch::V8m => &[
+            ("USERSETMPEND", 1, 1),
+            ("UNALIGN_TRP", 3, 1),
+            ("DIV_0_TRP", 4, 1),
+            ("BFHFNMIGN", 8, 1),
+            ("STKOFHFNMIGN", 10, 1),
+            ("DC", 16, 1),
+            ("IC", 17, 1),
+            ("BP", 18, 1),
+        ],
+    };
+
+    let mut module = module("scb", arch.version(), "System control block")
+        .add_block(block)
+        .add_bitfield(bitfield(
+            "Cpuid",
+            &[
+                ("REVISION", 0, 4),
+                ("PARTNO", 4, 12),
+                ("ARCHITECTURE", 16, 4),
+                ("VARIANT", 20, 4),
+                ("IMPLEMENTER", 24, 8),
+            ],
+        ))
+        .add_bitfield(bitfield("Icsr", icsr))
+        .add_bitfield(bitfield("Vtor", &[("TBLOFF", 7, 25)]))
+        .add_bitfield(bitfield("Aircr", aircr))
+        .add_bitfield(bitfield("Scr", scr))
+        .add_bitfield(bitfield("Ccr", ccr))
+        .add_bitfield(
+            BitfieldBuilder::new("Shpr", 32).add_field(
+                BitfieldFieldBuilder::new("PRI", 0, 8)
+                    .array(4, 8)
+                    .description("Priority of the system handlers, one per byte"),
+            ),
+        );
+
+    if arch == Arch::V6m {
+        return Ok(module.build()?);
+    }
+
+    let mut shcsr = vec![
+        ("MEMFAULTACT", 0, 1),
+        ("BUSFAULTACT", 1, 1),
+        ("USGFAULTACT", 3, 1),
+        ("SVCALLACT", 7, 1),
+        ("MONITORACT", 8, 1),
+        ("PENDSVACT", 10, 1),
+        ("SYSTICKACT", 11, 1),
+        ("USGFAULTPENDED", 12, 1),
+        ("MEMFAULTPENDED", 13, 1),
+        ("BUSFAULTPENDED", 14, 1),
+        ("SVCALLPENDED", 15, 1),
+        ("MEMFAULTENA", 16, 1),
+        ("BUSFAULTENA", 17, 1),
+        ("USGFAULTENA", 18, 1),
+    ];
+    let mut cfsr = vec![
+        ("IACCVIOL", 0, 1),
+        ("DACCVIOL", 1, 1),
+        ("MUNSTKERR", 3, 1),
+        ("MSTKERR", 4, 1),
+        ("MLSPERR", 5, 1),
+        ("MMARVALID", 7, 1),
+        ("IBUSERR", 8, 1),
+        ("PRECISERR", 9, 1),
+        ("IMPRECISERR", 10, 1),
+        ("UNSTKERR", 11, 1),
+        ("STKERR", 12, 1),
+        ("LSPERR", 13, 1),
+        ("BFARVALID", 15, 1),
+        ("UNDEFINSTR", 16, 1),
+        ("INVSTATE", 17, 1),
+        ("INVPC", 18, 1),
+        ("NOCP", 19, 1),
+        ("UNALIGNED", 24, 1),
+        ("DIVBYZERO", 25, 1),
+    ];
+    if arch == Arch::V8m {
+        shcsr.extend([
+            ("HARDFAULTACT", 2, 1),
+            ("SECUREFAULTACT", 4, 1),
+            ("NMIACT", 5, 1),
+            ("SECUREFAULTENA", 19, 1),
+            ("SECUREFAULTPENDED", 20, 1),
+            ("HARDFAULTPENDED", 21, 1),
+        ]);
+        cfsr.push(("STKOF", 20, 1));
+    }
+
+    module = module
+        .add_bitfield(bitfield("Shcsr", &shcsr))
+        .add_bitfield(bitfield("Cfsr", &cfsr))
+        .add_bitfield(bitfield(
+            "Hfsr",
+            &[("VECTTBL", 1, 1), ("FORCED", 30, 1), ("DEBUGEVT", 31, 1)],
+        ))
+        .add_bitfield(bitfield(
+            "Dfsr",
+            &[
+                ("HALTED", 0, 1),
+                ("BKPT", 1, 1),
+                ("DWTTRAP", 2, 1),
+                ("VCATCH", 3, 1),
+                ("EXTERNAL", 4, 1),
+            ],
+        ))
+        .add_bitfield(
+            BitfieldBuilder::new("Cpacr", 32)
+                .add_field(BitfieldFieldBuilder::new("CP10", 20, 2).enum_name("CpAccess"))
+                .add_field(BitfieldFieldBuilder::new("CP11", 22, 2).enum_name("CpAccess")),
+        )
+        .add_enum(
+            EnumBuilder::new("CpAccess", 2)
+                .add_variant(VariantBuilder::new("DENIED", 0).description("Access denied"))
+                .add_variant(
+                    VariantBuilder::new("PRIVILEGED", 1).description("Privileged access only"),
+                )
+                .add_variant(VariantBuilder::new("FULL", 3).description("Full access")),
+        );
+
+    Ok(module.build()?)
+}
+
+fn nvic(arch: Arch) -> Result<ir::Module> {
+    let block = BlockBuilder::new("Nvic").description("Nested vectored interrupt controller");
+
+    let block = match arch {
+        Arch::V6m => block
+            .add_field(word("ISER", 0x000, "Interrupt set-enable register"))
+            .add_field(word("ICER", 0x080, "Interrupt clear-enable register"))
+            .add_field(word("ISPR", 0x100, "Interrupt set-pending register"))
+            .add_field(word("ICPR", 0x180, "Interrupt clear-pending register"))
+            .add_field(reg("IPR", 0x300, "Ipr", "Interrupt priority registers").array(8, 4)),
+        Arch::V7m | Arch::V8m => {
+            let block = block
+                .add_field(word("ISER", 0x000, "Interrupt set-enable registers").array(16, 4))
+                .add_field(word("ICER", 0x080, "Interrupt clear-enable registers").array(16, 4))
+                .add_field(word("ISPR", 0x100, "Interrupt set-pending registers").array(16, 4))
+                .add_field(word("ICPR", 0x180, "Interrupt clear-pending registers").array(16, 4))
+                .add_field(word("IABR", 0x200, "Interrupt active bit registers").array(16, 4))
+                .add_field(
+                    reg(
+                        "STIR",
+                        0xE00,
+                        "Stir",
+                        "Software triggered interrupt register",
+                    )
+                    .access(Access::Write),
+                );
+
+            let (block, ipr_len) = match arch {
+                Arch::V8m => (
+                    block.add_field(
+                        word("ITNS", 0x280, "Interrupt target non-secure registers").array(16, 4),
+                    ),
+                    480,
+                ),
+                _ => (block, 240),
+            };
+
+            block.add_field(
+                BlockFieldBuilder::simple("IPR", 0x300, 8)
+                    .description("Interrupt priority registers, one byte per interrupt")
+                    .array(ipr_len, 1),
+            )
+        }
+    };
+
+    let module = module(
+        "nvic",
+        arch.version(),
+        "Nested vectored interrupt controller",
+    )
+    .add_block(block);
+
+    let module = match arch {
+        Arch::V6m => module.add_bitfield(
+            BitfieldBuilder::new("Ipr", 32).add_field(
+                BitfieldFieldBuilder::new("PRI", 0, 8)
+                    .array(4, 8)
+                    .description("Priority of the interrupts, one per byte"),
+            ),
+        ),
+        Arch::V7m | Arch::V8m => module.add_bitfield(bitfield("Stir", &[("INTID", 0, 9)])),
+    };
+
+    Ok(module.build()?)
+}
+
+fn systick() -> Result<ir::Module> {
+    let module = module("systick", "v1", "System timer")
+        .add_block(
+            BlockBuilder::new("Systick")
+                .description("System timer")
+                .add_field(reg("CSR", 0x0, "Csr", "Control and status register"))
+                .add_field(reg("RVR", 0x4, "Rvr", "Reload value register"))
+                .add_field(reg("CVR", 0x8, "Cvr", "Current value register"))
+                .add_field(
+                    reg("CALIB", 0xC, "Calib", "Calibration value register").access(Access::Read),
+                ),
+        )
+        .add_bitfield(
+            BitfieldBuilder::new("Csr", 32)
+                .add_field(BitfieldFieldBuilder::new("ENABLE", 0, 1))
+                .add_field(BitfieldFieldBuilder::new("TICKINT", 1, 1))
+                .add_field(BitfieldFieldBuilder::new("CLKSOURCE", 2, 1).enum_name("ClkSource"))
+                .add_field(BitfieldFieldBuilder::new("COUNTFLAG", 16, 1)),
+        )
+        .add_bitfield(bitfield("Rvr", &[("RELOAD", 0, 24)]))
+        .add_bitfield(bitfield("Cvr", &[("CURRENT", 0, 24)]))
+        .add_bitfield(bitfield(
+            "Calib",
+            &[("TENMS", 0, 24), ("SKEW", 30, 1), ("NOREF", 31, 1)],
+        ))
+        .add_enum(
+            EnumBuilder::new("ClkSource", 1)
+                .add_variant(VariantBuilder::new("EXTERNAL", 0).description("Reference clock"))
+                .add_variant(VariantBuilder::new("PROCESSOR", 1).description("Processor clock")),
+        );
+
+    Ok(module.build()?)
+}
+
+fn mpu(arch: Arch) -> Result<ir::Module> {
+    let block = BlockBuilder::new("Mpu")
+        .description("Memory protection unit")
+        .add_field(reg("TYPE", 0x0, "Type", "Type register").access(Access::Read))
+        .add_field(reg("CTRL", 0x4, "Ctrl", "Control register"))
+        .add_field(reg("RNR", 0x8, "Rnr", "Region number register"))
+        .add_field(reg("RBAR", 0xC, "Rbar", "Region base address register"));
+
+    let module = module("mpu", arch.version(), "Memory protection unit")
+        .add_bitfield(bitfield(
+            "Type",
+            &[("SEPARATE", 0, 1), ("DREGION", 8, 8), ("IREGION", 16, 8)],
+        ))
+        .add_bitfield(bitfield(
+            "Ctrl",
+            &[("ENABLE", 0, 1), ("HFNMIENA", 1, 1), ("PRIVDEFENA", 2, 1)],
+        ))
+        .add_bitfield(bitfield("Rnr", &[("REGION", 0, 8)]));
+
+    let module = match arch {
+        Arch::V6m | Arch::V7m => module
+            .add_block(block.add_field(reg(
+                "RASR",
+                0x10,
+                "Rasr",
+                "Region attribute and size register",
+            )))
+            .add_bitfield(bitfield(
+                "Rbar",
+                &[("REGION", 0, 4), ("VALID", 4, 1), ("ADDR", 5, 27)],
+            ))
+            .add_bitfield(bitfield(
+                "Rasr",
+                &[
+                    ("ENABLE", 0, 1),
+                    ("SIZE", 1, 5),
+                    ("SRD", 8, 8),
+                    ("B", 16, 1),
+                    ("C", 17, 1),
+                    ("S", 18, 1),
+                    ("TEX", 19, 3),
+                    ("AP", 24, 3),
+                    ("XN", 28, 1),
+                ],
+            )),
+        Arch::V8m => module
+            .add_block(
+                block
+                    .add_field(reg("RLAR", 0x10, "Rlar", "Region limit address register"))
+                    .add_field(
+                        reg(
+                            "MAIR",
+                            0x30,
+                            "Mair",
+                            "Memory attribute indirection registers",
+                        )
+                        .array(2, 4),
+                    ),
+            )
+            .add_bitfield(bitfield(
+                "Rbar",
+                &[("XN", 0, 1), ("AP", 1, 2), ("SH", 3, 2), ("BASE", 5, 27)],
+            ))
+            .add_bitfield(bitfield(
+                "Rlar",
+                &[("EN", 0, 1), ("ATTRINDX", 1, 3), ("LIMIT", 5, 27)],
+            ))
+            .add_bitfield(
+                BitfieldBuilder::new("Mair", 32).add_field(
+                    BitfieldFieldBuilder::new("ATTR", 0, 8)
+                        .array(4, 8)
+                        .description("Memory attributes, one per byte"),
+                ),
+            ),
+    };
+
+    Ok(module.build()?)
+}
+
+fn fpu() -> Result<ir::Module> {
+    let module = module("fpu", "v1", "Floating point unit")
+        .add_block(
+            BlockBuilder::new("Fpu")
+                .description("Floating point unit")
+                .add_field(reg(
+                    "FPCCR",
+                    0x00,
+                    "Fpccr",
+                    "Floating-point context control register",
+                ))
+                .add_field(reg(
+                    "FPCAR",
+                    0x04,
+                    "Fpcar",
+                    "Floating-point context address register",
+                ))
+                .add_field(reg(
+                    "FPDSCR",
+                    0x08,
+                    "Fpdscr",
+                    "Floating-point default status control register",
+                ))
+                .add_field(
+                    word("MVFR0", 0x0C, "Media and FP feature register 0").access(Access::Read),
+                )
+                .add_field(
+                    word("MVFR1", 0x10, "Media and FP feature register 1").access(Access::Read),
+                )
+                .add_field(
+                    word(
+                        "MVFR2",
+                        0x14,
+                        "Media and FP feature register 2, Cortex-M7 only",
+                    )
+                    .access(Access::Read),
+                ),
+        )
+        .add_bitfield(bitfield(
+            "Fpccr",
+            &[
+                ("LSPACT", 0, 1),
+                ("USER", 1, 1),
+                ("THREAD", 3, 1),
+                ("HFRDY", 4, 1),
+                ("MMRDY", 5, 1),
+                ("BFRDY", 6, 1),
+                ("MONRDY", 8, 1),
+                ("LSPEN", 30, 1),
+                ("ASPEN", 31, 1),
+            ],
+        ))
+        .add_bitfield(bitfield("Fpcar", &[("ADDRESS", 3, 29)]))
+        .add_bitfield(bitfield(
+            "Fpdscr",
+            &[
+                ("RMODE", 22, 2),
+                ("FZ", 24, 1),
+                ("DN", 25, 1),
+                ("AHP", 26, 1),
+            ],
+        ));
+
+    Ok(module.build()?)
+}
+
+fn dwt(arch: Arch) -> Result<ir::Module> {
+    let comparator = BlockBuilder::new("Comparator")
+        .description("Comparator")
+        .add_field(word("COMP", 0x0, "Comparator register"));
+
+    let comparator = match arch {
+        Arch::V6m | Arch::V7m => comparator
+            .add_field(reg("MASK", 0x4, "Mask", "Comparator mask register"))
+            .add_field(reg(
+                "FUNCTION",
+                0x8,
+                "Function",
+                "Comparator function register",
+            )),
+        Arch::V8m => comparator.add_field(reg(
+            "FUNCTION",
+            0x8,
+            "Function",
+            "Comparator function register",
+        )),
+    };
+
+    let function: &[_] = match arch {
+        Arch::V6m | Arch::V7m => &[
+            ("FUNCTION", 0, 4),
+            ("EMITRANGE", 5, 1),
+            ("CYCMATCH", 7, 1),
+            ("DATAVMATCH", 8, 1),
+            ("LNK1ENA", 9, 1),
+            ("DATAVSIZE", 10, 2),
+            ("DATAVADDR0", 12, 4),
+            ("DATAVADDR1", 16, 4),
+            ("MATCHED", 24, 1),
+        ],
+        Arch::V8m => &[
+            ("MATCH", 0, 4),
+            ("ACTION", 4, 2),
+            ("DATAVSIZE", 10, 2),
+            ("MATCHED", 24, 1),
+            ("ID", 27, 5),
+        ],
+    };
+
+    let mut module = module("dwt", arch.version(), "Data watchpoint and trace unit")
+        .add_block(
+            BlockBuilder::new("Dwt")
+                .description("Data watchpoint and trace unit")
+                .add_field(reg("CTRL", 0x00, "Ctrl", "Control register"))
+                .add_field(word("CYCCNT", 0x04, "Cycle count register"))
+                .add_field(word("CPICNT", 0x08, "CPI count register"))
+                .add_field(word("EXCCNT", 0x0C, "Exception overhead count register"))
+                .add_field(word("SLEEPCNT", 0x10, "Sleep count register"))
+                .add_field(word("LSUCNT", 0x14, "LSU count register"))
+                .add_field(word("FOLDCNT", 0x18, "Folded-instruction count register"))
+                .add_field(
+                    word("PCSR", 0x1C, "Program counter sample register").access(Access::Read),
+                )
+                .add_field(
+                    BlockFieldBuilder::block("COMP", 0x20, "Comparator")
+                        .description("Comparators")
+                        .array(4, 16),
+                )
+                .add_field(word("LAR", 0xFB0, "Lock access register").access(Access::Write)),
+        )
+        .add_block(comparator)
+        .add_bitfield(bitfield(
+            "Ctrl",
+            &[
+                ("CYCCNTENA", 0, 1),
+                ("POSTPRESET", 1, 4),
+                ("POSTINIT", 5, 4),
+                ("CYCTAP", 9, 1),
+                ("SYNCTAP", 10, 2),
+                ("PCSAMPLENA", 12, 1),
+                ("EXCTRCENA", 16, 1),
+                ("CPIEVTENA", 17, 1),
+                ("EXCEVTENA", 18, 1),
+                ("SLEEPEVTENA", 19, 1),
+                ("LSUEVTENA", 20, 1),
+                ("FOLDEVTENA", 21, 1),
+                ("CYCEVTENA", 22, 1),
+                ("NOPRFCNT", 24, 1),
+                ("NOCYCCNT", 25, 1),
+                ("NOEXTTRIG", 26, 1),
+                ("NOTRCPKT", 27, 1),
+                ("NUMCOMP", 28, 4),
+            ],
+        ))
+        .add_bitfield(bitfield("Function", function));
+
+    if arch != Arch::V8m {
+        module = module.add_bitfield(bitfield("Mask", &[("MASK", 0, 5)]));
+    }
+
+    Ok(module.build()?)
+}
+
+/// Layouts of the special registers, each bitfield is named after the
+/// register it describes.
+fn special(arch: Arch) -> Result<ir::Module> {
+    let mut control = vec![("NPRIV", 0, 1), ("SPSEL", 1, 1)];
+    if arch != Arch::V6m {
+        control.push(("FPCA", 2, 1));
+    }
+    if arch == Arch::V8m {
+        control.push(("SFPA", 3, 1));
+    }
+
+    let mut module = module("special", arch.version(), "Special registers")
+        .add_bitfield(bitfield("Primask", &[("PM", 0, 1)]).description("Priority mask register"))
+        .add_bitfield(bitfield("Control", &control).description("Control register"));
+
+    if arch != Arch::V6m {
+        module = module
+            .add_bitfield(
+                bitfield("Basepri", &[("BASEPRI", 0, 8)])
+                    .description("Base priority mask register"),
+            )
+            .add_bitfield(
+                bitfield("Faultmask", &[("FM", 0, 1)]).description("Fault mask register"),
+            );
+    }
+
+    Ok(module.build()?)
+}
@@ -2,5 +2,9 @@ mod utils;
 
 #[cfg(feature = "chiptool")]
 pub mod chiptool;
+#[cfg(feature = "cortex-m")]
+pub mod cortex_m;
+#[cfg(feature = "riscv")]
+pub mod riscv;
 #[cfg(feature = "stm32-data")]
 pub mod stm32_data;
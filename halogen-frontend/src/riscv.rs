@@ -0,0 +1,185 @@
+//! Machine-level CSRs of the RISC-V privileged specification, as laid out on
+//! RV32 harts.
+//!
+//! Vendor descriptions of RISC-V chips only cover the memory mapped
+//! peripherals, the CSRs are added on top of them by
+//! [`add_privileged_csrs`].
+
+use anyhow::{Result, bail};
+
+use halogen_ir::builder::*;
+use halogen_ir::ir::{self, Access};
+
+/// Name of the module holding the layouts of the CSRs.
+pub const MODULE_NAME: &str = "riscv";
+
+/// Version of the module, after the width of the registers.
+pub const MODULE_VERSION: &str = "rv32";
+
+/// Name, number, layout and description of every CSR.
+static CSRS: &[(&str, u16, &str, &str)] = &[
+    ("MSTATUS", 0x300, "Mstatus", "Machine status register"),
+    ("MIE", 0x304, "Mie", "Machine interrupt enable register"),
+    (
+        "MTVEC",
+        0x305,
+        "Mtvec",
+        "Machine trap-vector base address register",
+    ),
+    ("MCAUSE", 0x342, "Mcause", "Machine cause register"),
+    ("MIP", 0x344, "Mip", "Machine interrupt pending register"),
+];
+
+/// The module laying out the CSRs.
+pub fn privileged_module() -> Result<ir::Module> {
+    let module = ModuleBuilder::new(MODULE_NAME)
+        .version(MODULE_VERSION)
+        .description("Machine-level CSRs")
+        .source(source())
+        .add_bitfield(
+            BitfieldBuilder::new("Mstatus", 32)
+                .add_field(field("SIE", 1, 1, "Supervisor interrupt enable"))
+                .add_field(field("MIE", 3, 1, "Machine interrupt enable"))
+                .add_field(field("SPIE", 5, 1, "SIE prior to the trap"))
+                .add_field(field("UBE", 6, 1, "User mode big endian"))
+                .add_field(field("MPIE", 7, 1, "MIE prior to the trap"))
+                .add_field(field("SPP", 8, 1, "Supervisor previous privilege"))
+                .add_field(field("VS", 9, 2, "Vector extension state"))
+                .add_field(field("MPP", 11, 2, "Machine previous privilege").enum_name("Privilege"))
+                .add_field(field("FS", 13, 2, "Floating point state"))
+                .add_field(field("XS", 15, 2, "Additional extensions state"))
+                .add_field(field("MPRV", 17, 1, "Modify privilege"))
+                .add_field(field("SUM", 18, 1, "Permit supervisor user memory access"))
+                .add_field(field("MXR", 19, 1, "Make executable readable"))
+                .add_field(field("TVM", 20, 1, "Trap virtual memory"))
+                .add_field(field("TW", 21, 1, "Timeout wait"))
+                .add_field(field("TSR", 22, 1, "Trap SRET"))
+                .add_field(field("SD", 31, 1, "Dirty state summary")),
+        )
+        .add_bitfield(interrupts("Mie", "IE", "enable"))
+        .add_bitfield(interrupts("Mip", "IP", "pending"))
+        .add_bitfield(
+            BitfieldBuilder::new("Mtvec", 32)
+                .add_field(field("MODE", 0, 2, "Vector mode").enum_name("TrapMode"))
+                .add_field(field("BASE", 2, 30, "Vector base address, in words")),
+        )
+        .add_bitfield(
+            BitfieldBuilder::new("Mcause", 32)
+                .add_field(field("CODE", 0, 31, "Exception code"))
+                .add_field(field("INTERRUPT", 31, 1, "Trap caused by an interrupt")),
+        )
+        .add_enum(
+            EnumBuilder::new("Privilege", 2)
+                .add_variant(VariantBuilder::new("USER", 0))
+                .add_variant(VariantBuilder::new("SUPERVISOR", 1))
+                .add_variant(VariantBuilder::new("MACHINE", 3)),
+        )
+        .add_enum(
+            EnumBuilder::new("TrapMode", 2)
+                .add_variant(VariantBuilder::new("DIRECT", 0).description("All traps jump to BASE"))
+                .add_variant(
+                    VariantBuilder::new("VECTORED", 1)
+                        .description("Interrupts jump to BASE + 4 * cause"),
+                ),
+        );
+
+    Ok(module.build()?)
+}
+
+/// The CSRs, laid out by [`privileged_module`].
+pub fn privileged_csrs() -> Vec<ir::chip::riscv_ext::Csr> {
+    CSRS.iter()
+        .map(
+            |&(name, number, bitfield_name, description)| ir::chip::riscv_ext::Csr {
+                name: name.into(),
+                description: Some(description.into()),
+                module: MODULE_NAME.into(),
+                number,
+                access: Access::ReadWrite,
+                bitfield_name: bitfield_name.into(),
+            },
+        )
+        .collect()
+}
+
+/// Add the CSRs to the selected chips, along with the module laying them
+/// out. CSRs already described by a chip are kept.
+pub fn add_privileged_csrs(
+    ir: &mut ir::MultiChip,
+    mut filter: impl FnMut(&ir::Chip) -> bool,
+) -> Result<()> {
+    let module = privileged_module()?;
+    let mut used = false;
+
+    for chip in ir.chips.iter_mut().filter(|chip| filter(chip)) {
+        match chip
+            .imports
+            .iter()
+            .find(|import| import.name == MODULE_NAME)
+        {
+            Some(import) if import.version.as_deref() != Some(MODULE_VERSION) => {
+                bail!("chip {} already imports a {MODULE_NAME} module", chip.name)
+            }
+            Some(_) => {}
+            None => chip.imports.push(ir::chip::Import {
+                name: MODULE_NAME.into(),
+                version: Some(MODULE_VERSION.into()),
+            }),
+        }
+
+        let riscv_ext = chip
+            .riscv_ext
+            .get_or_insert_with(|| ir::chip::RiscvExt { csrs: Vec::new() });
+        used = true;
+        for csr in privileged_csrs() {
+            if !riscv_ext.csrs.iter().any(|other| other.name == csr.name) {
+                riscv_ext.csrs.push(csr);
+            }
+        }
+    }
+
+    let known = ir
+        .modules
+        .iter()
+        .any(|other| other.name == module.name && other.version == module.version);
+    if used && !known {
+        ir.modules.push(module);
+    }
+
+    Ok(())
+}
+
+fn source() -> ir::Source {
+    ir::Source {
+        frontend: Some("riscv".into()),
+        ..Default::default()
+    }
+}
+
+fn field(name: &str, bit_offset: u32, bit_size: u32, description: &str) -> BitfieldFieldBuilder {
+    BitfieldFieldBuilder::new(name, bit_offset, bit_size).description(description)
+}
+
+/// Layout shared by `mie` and `mip`, one bit per interrupt source.
+fn interrupts(name: &str, suffix: &str, what: &str) -> BitfieldBuilder {
+    [
+        ("SS", 1, "Supervisor software interrupt"),
+        ("MS", 3, "Machine software interrupt"),
+        ("ST", 5, "Supervisor timer interrupt"),
+        ("MT", 7, "Machine timer interrupt"),
+        ("SE", 9, "Supervisor external interrupt"),
+        ("ME", 11, "Machine external interrupt"),
+    ]
+    .into_iter()
+    .fold(
+        BitfieldBuilder::new(name, 32),
+        |bitfield, (source, bit_offset, description)| {
+            bitfield.add_field(field(
+                &format!("{source}{suffix}"),
+                bit_offset,
+                1,
+                &format!("{description} {what}"),
+            ))
+        },
+    )
+}
@@ -10,7 +10,7 @@ use stm32_data_serde::*;
 
 use halogen_ir::ir::{self, MultiChip};
 
-use crate::chiptool::convert_chiptool;
+use crate::chiptool::{convert_chiptool, json_pointer};
 use crate::utils;
 use crate::utils::rayon_prelude::*;
 
@@ -26,6 +26,7 @@ pub fn convert_multi_chips(
         .filter(|chip| filter.map(|filter| filter.is_match(chip)).unwrap_or(true))
         .map(|chip| -> Result<ir::Chip> {
             let mut chip = parse_chip(root, &chip)?;
+            let file = chip_file(&chip.name);
             let core = extract_core(&mut chip)?;
 
             let imports = validate_and_extract_imports(&core)?
@@ -36,12 +37,20 @@ pub fn convert_multi_chips(
                 })
                 .collect();
 
+            // Only the Cortex-M3 and M4 have bit-band regions
+            let cm_ext = matches!(core.name.as_str(), "cm3" | "cm4").then(|| ir::chip::CmExt {
+                cm_regs: Vec::new(),
+                bit_band: true,
+            });
+
             Ok(ir::Chip {
                 name: chip.name,
                 description: None,
                 imports,
-                peripherals: convert_peripherals(core.peripherals),
+                peripherals: convert_peripherals(&file, core.peripherals),
                 stm32_ext: Some(ir::chip::Stm32Ext { cm_name: core.name }),
+                cm_ext,
+                source: Some(source(file, None)),
                 ..Default::default()
             })
         })
@@ -58,7 +67,8 @@ pub fn convert_multi_chips(
 
             let regs = parse_registers(root, &import.name, &version)?;
 
-            let module = convert_chiptool(import.name.clone(), Some(version), regs)?;
+            let origin = source(registers_file(&import.name, &version), None);
+            let module = convert_chiptool(import.name.clone(), Some(version), regs, origin)?;
             Ok(module)
         })
         .collect::<Result<Vec<_>, _>>()?;
@@ -89,10 +99,27 @@ fn list_chips(root: &Path) -> Result<Vec<String>> {
     Ok(chips)
 }
 
+/// Path of a chip file, relative to the data directory.
+fn chip_file(chip: &str) -> String {
+    format!("chips/{}.json", chip.to_uppercase())
+}
+
+/// Path of a registers file, relative to the data directory.
+fn registers_file(name: &str, version: &str) -> String {
+    format!("registers/{name}_{version}.json")
+}
+
+fn source(file: String, pointer: Option<String>) -> ir::Source {
+    ir::Source {
+        frontend: Some("stm32-data".to_string()),
+        file: Some(file),
+        pointer,
+        patches: Vec::new(),
+    }
+}
+
 fn parse_chip(root: &Path, chip: &str) -> Result<Chip> {
-    let path = root
-        .join("chips")
-        .join(format!("{}.json", chip.to_uppercase()));
+    let path = root.join(chip_file(chip));
     ensure!(path.exists(), "chip not found in data directory");
 
     let data = fs::read(path).context("failed to read chip file")?;
@@ -102,9 +129,7 @@ fn parse_chip(root: &Path, chip: &str) -> Result<Chip> {
 }
 
 fn parse_registers(root: &Path, name: &str, version: &str) -> Result<cir::IR> {
-    let path = root
-        .join("registers")
-        .join(format!("{name}_{version}.json"));
+    let path = root.join(registers_file(name, version));
     ensure!(path.exists(), "registers not found in data directory");
 
     let data = fs::read(path).context("failed to read registers file")?;
@@ -113,9 +138,12 @@ fn parse_registers(root: &Path, name: &str, version: &str) -> Result<cir::IR> {
     Ok(data)
 }
 
-fn convert_peripherals(peripherals: Vec<chip::core::Peripheral>) -> Vec<ir::chip::Peripheral> {
+fn convert_peripherals(
+    file: &str,
+    peripherals: Vec<chip::core::Peripheral>,
+) -> Vec<ir::chip::Peripheral> {
     let mut out = Vec::new();
-    for peripheral in peripherals {
+    for (i, peripheral) in peripherals.into_iter().enumerate() {
         let Some(regs) = peripheral.registers else {
             continue;
         };
@@ -126,6 +154,10 @@ fn convert_peripherals(peripherals: Vec<chip::core::Peripheral>) -> Vec<ir::chip
             module: regs.kind,
             address: peripheral.address as _,
             block_name: regs.block,
+            source: Some(source(
+                file.to_string(),
+                Some(json_pointer(&["cores", "0", "peripherals", &i.to_string()])),
+            )),
         });
     }
 
@@ -0,0 +1,629 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::glob::Glob;
+use crate::ir::*;
+use crate::utils;
+use crate::utils::rayon_prelude::*;
+
+/// Settings for [`infer`].
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Minimum number of fields needed to build an array
+    pub min_len: usize,
+    /// Only the fields whose name matches are considered
+    pub filter: Glob,
+    /// Also move registers repeated with a shared prefix (`CH0_CFG`,
+    /// `CH0_DATA`, `CH1_CFG`, ...) into an array of sub-blocks
+    pub clusters: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            min_len: 3,
+            filter: Glob::new("*"),
+            clusters: true,
+        }
+    }
+}
+
+/// Collapse runs of identical fields with a numeric index in their name and a
+/// constant stride (`CCR1`, `CCR2`, ...) into arrays, in every module.
+pub fn infer(multi: &mut MultiChip, settings: &Settings) {
+    utils::into_maybe_par_iter(&mut multi.modules)
+        .for_each(|module| infer_module(module, settings));
+}
+
+/// Same as [`infer`], for a single module.
+pub fn infer_module(module: &mut Module, settings: &Settings) {
+    let mut block_names = module
+        .blocks
+        .iter()
+        .map(|block| block.name.clone())
+        .collect::<HashSet<_>>();
+    let mut new_blocks = Vec::new();
+
+    for block in &mut module.blocks {
+        let mut names = names(&block.fields);
+        let mut runs = find_runs(&block.fields, settings);
+        let mut groups = Vec::new();
+
+        if settings.clusters {
+            let mut clustered = HashSet::new();
+
+            for (members, field, sub_block) in find_clusters(block, &runs) {
+                if block_names.contains(&sub_block.name) || !names.insert(field.name.clone()) {
+                    continue;
+                }
+
+                let positions = members
+                    .iter()
+                    .flat_map(|&i| runs[i].members.iter().copied())
+                    .collect();
+
+                clustered.extend(members);
+                block_names.insert(sub_block.name.clone());
+                new_blocks.push(sub_block);
+                groups.push((positions, field));
+            }
+
+            runs = runs
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !clustered.contains(i))
+                .map(|(_, run)| run)
+                .collect();
+        }
+
+        groups.extend(collapse_runs(&block.fields, runs, &mut names));
+        replace(&mut block.fields, groups);
+    }
+
+    module.blocks.extend(new_blocks);
+
+    for bitfield in &mut module.bitfields {
+        let mut names = names(&bitfield.fields);
+        let runs = find_runs(&bitfield.fields, settings);
+        let groups = collapse_runs(&bitfield.fields, runs, &mut names);
+        replace(&mut bitfield.fields, groups);
+    }
+}
+
+/// A field which can be merged into an array.
+pub(crate) trait Element: Clone + PartialEq {
+    fn name(&self) -> &str;
+
+    fn name_mut(&mut self) -> &mut String;
+
+    fn description_mut(&mut self) -> &mut Option<String>;
+
+    fn source_mut(&mut self) -> &mut Option<Source>;
+
+    fn array(&self) -> Option<&Array>;
+
+    fn array_mut(&mut self) -> &mut Option<Array>;
+
+    fn offset(&self) -> u64;
+
+    fn set_offset(&mut self, offset: u64);
+}
+
+impl Element for block::Field {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+
+    fn description_mut(&mut self) -> &mut Option<String> {
+        &mut self.description
+    }
+
+    fn source_mut(&mut self) -> &mut Option<Source> {
+        &mut self.source
+    }
+
+    fn array(&self) -> Option<&Array> {
+        self.array.as_ref()
+    }
+
+    fn array_mut(&mut self) -> &mut Option<Array> {
+        &mut self.array
+    }
+
+    fn offset(&self) -> u64 {
+        self.byte_offset
+    }
+
+    fn set_offset(&mut self, offset: u64) {
+        self.byte_offset = offset;
+    }
+}
+
+impl Element for bitfield::Field {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+
+    fn description_mut(&mut self) -> &mut Option<String> {
+        &mut self.description
+    }
+
+    fn source_mut(&mut self) -> &mut Option<Source> {
+        &mut self.source
+    }
+
+    fn array(&self) -> Option<&Array> {
+        self.array.as_ref()
+    }
+
+    fn array_mut(&mut self) -> &mut Option<Array> {
+        &mut self.array
+    }
+
+    fn offset(&self) -> u64 {
+        self.bit_offset as u64
+    }
+
+    fn set_offset(&mut self, offset: u64) {
+        self.bit_offset = offset as u32;
+    }
+}
+
+/// Everything but what is allowed to differ between elements of an array.
+fn layout<T: Element>(item: &T) -> T {
+    let mut item = item.clone();
+    item.name_mut().clear();
+    *item.description_mut() = None;
+    *item.source_mut() = None;
+    item.set_offset(0);
+    item
+}
+
+/// Merge evenly spaced items with the same layout into a single array, which
+/// takes the description and source of the first one.
+pub(crate) fn collapse<T: Element>(mut items: Vec<T>, name: &str) -> Result<T, &'static str> {
+    items.sort_by_key(T::offset);
+
+    let [first, second, ..] = items.as_slice() else {
+        return Err("at least two fields are needed");
+    };
+
+    let base = first.offset();
+    let stride = second.offset() - base;
+    if stride == 0 {
+        return Err("fields overlap");
+    }
+
+    let first_layout = layout(first);
+    for (i, item) in items.iter().enumerate() {
+        if item.array().is_some() {
+            return Err("a field is already an array");
+        }
+        if item.offset() != base + stride * i as u64 {
+            return Err("fields are not evenly spaced");
+        }
+        if layout(item) != first_layout {
+            return Err("fields have different layouts");
+        }
+    }
+
+    let len = items.len() as u64;
+    let mut array = items.swap_remove(0);
+    *array.name_mut() = name.to_string();
+    *array.array_mut() = Some(Array { len, stride });
+
+    Ok(array)
+}
+
+/// Consecutive fields named after the same template.
+struct Run {
+    prefix: String,
+    suffix: String,
+    /// Positions of the fields, sorted by the index in their name
+    members: Vec<usize>,
+    stride: u64,
+}
+
+/// Split a name around its last number, `CH2_EN` gives `("CH", 2, "_EN")`.
+fn split_index(name: &str) -> Option<(&str, u64, &str)> {
+    let end = name.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = name[..end]
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .len();
+    let index = name[start..end].parse().ok()?;
+
+    Some((&name[..start], index, &name[end..]))
+}
+
+fn join_name(prefix: &str, suffix: &str) -> Option<String> {
+    let name = if prefix.ends_with('_') && suffix.starts_with('_') {
+        format!("{prefix}{}", &suffix[1..])
+    } else {
+        format!("{prefix}{suffix}")
+    };
+
+    let name = name.trim_matches('_');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn names<T: Element>(items: &[T]) -> HashSet<String> {
+    items.iter().map(|item| item.name().to_string()).collect()
+}
+
+fn find_runs<T: Element>(items: &[T], settings: &Settings) -> Vec<Run> {
+    let mut templates = BTreeMap::<_, Vec<_>>::new();
+    for (pos, item) in items.iter().enumerate() {
+        if item.array().is_some() || !settings.filter.matches(item.name()) {
+            continue;
+        }
+
+        if let Some((prefix, index, suffix)) = split_index(item.name()) {
+            templates
+                .entry((prefix, suffix))
+                .or_default()
+                .push((index, pos));
+        }
+    }
+
+    let offset = |pos: usize| items[pos].offset();
+    let min_len = settings.min_len.max(2);
+
+    let mut runs = Vec::new();
+    for ((prefix, suffix), mut members) in templates {
+        members.sort();
+
+        let mut start = 0;
+        for end in 1..=members.len() {
+            let run = &members[start..end];
+            let (last_index, last) = run[run.len() - 1];
+
+            if let Some(&(index, pos)) = members.get(end) {
+                let stride = match run {
+                    [(_, first)] => offset(pos).checked_sub(offset(*first)),
+                    [(_, first), (_, second), ..] => Some(offset(*second) - offset(*first)),
+                    [] => unreachable!(),
+                };
+
+                let continues = index == last_index + 1
+                    && stride
+                        .is_some_and(|stride| stride > 0 && offset(pos) == offset(last) + stride)
+                    && layout(&items[pos]) == layout(&items[run[0].1]);
+
+                if continues {
+                    continue;
+                }
+            }
+
+            if run.len() >= min_len {
+                runs.push(Run {
+                    prefix: prefix.to_string(),
+                    suffix: suffix.to_string(),
+                    members: run.iter().map(|&(_, pos)| pos).collect(),
+                    stride: offset(run[1].1) - offset(run[0].1),
+                });
+            }
+
+            start = end;
+        }
+    }
+
+    runs
+}
+
+fn collapse_runs<T: Element>(
+    items: &[T],
+    runs: Vec<Run>,
+    names: &mut HashSet<String>,
+) -> Vec<(Vec<usize>, T)> {
+    let mut groups = Vec::new();
+
+    for run in runs {
+        let Some(name) = join_name(&run.prefix, &run.suffix) else {
+            continue;
+        };
+        if names.contains(&name) {
+            continue;
+        }
+
+        let members = run.members.iter().map(|&pos| items[pos].clone()).collect();
+        if let Ok(array) = collapse(members, &name) {
+            names.insert(name);
+            groups.push((run.members, array));
+        }
+    }
+
+    groups
+}
+
+/// Find runs sharing a prefix, index range and stride, and build a sub-block
+/// out of each group. Returns the indices of the runs in every cluster.
+fn find_clusters(block: &Block, runs: &[Run]) -> Vec<(Vec<usize>, block::Field, Block)> {
+    let fields = &block.fields;
+
+    let mut candidates = BTreeMap::<_, Vec<_>>::new();
+    for (i, run) in runs.iter().enumerate() {
+        if run.prefix.trim_matches('_').is_empty() || run.suffix.trim_matches('_').is_empty() {
+            continue;
+        }
+
+        let (first_index, ..) = split_index(&fields[run.members[0]].name).unwrap();
+        let key = (&run.prefix, first_index, run.members.len(), run.stride);
+        candidates.entry(key).or_default().push(i);
+    }
+
+    let mut clusters = Vec::new();
+
+    for ((prefix, _, len, stride), members) in candidates {
+        if members.len() < 2 {
+            continue;
+        }
+
+        let base = |i: usize| fields[runs[i].members[0]].byte_offset;
+        let min_base = members.iter().map(|&i| base(i)).min().unwrap();
+        if members.iter().any(|&i| base(i) - min_base >= stride) {
+            continue;
+        }
+
+        let mut sub_fields = Vec::new();
+        for &i in &members {
+            let mut field = fields[runs[i].members[0]].clone();
+            field.name = runs[i].suffix.trim_matches('_').to_string();
+            field.byte_offset = base(i) - min_base;
+            sub_fields.push(field);
+        }
+        sub_fields.sort_by_key(|field| field.byte_offset);
+
+        if names(&sub_fields).len() != sub_fields.len() {
+            continue;
+        }
+
+        let name = prefix.trim_matches('_');
+        let sub_block = Block {
+            name: format!("{}{}", block.name, camel_case(name)),
+            description: None,
+            fields: sub_fields,
+            source: block.source.clone(),
+        };
+
+        let field = block::Field {
+            name: name.to_string(),
+            description: None,
+            array: Some(Array {
+                len: len as u64,
+                stride,
+            }),
+            byte_offset: min_base,
+            source: block.source.clone(),
+            inner: block::FieldInner::Block(block::field::Block {
+                block_name: sub_block.name.clone(),
+            }),
+        };
+
+        clusters.push((members, field, sub_block));
+    }
+
+    clusters
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase());
+            first
+                .into_iter()
+                .chain(chars.map(|c| c.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Replace every group of items with a single one, placed where the first
+/// member of the group was.
+fn replace<T>(items: &mut Vec<T>, groups: Vec<(Vec<usize>, T)>) {
+    let mut removed = HashSet::new();
+    let mut inserted = HashMap::new();
+
+    for (members, item) in groups {
+        if let Some(&first) = members.iter().min() {
+            removed.extend(members);
+            inserted.insert(first, item);
+        }
+    }
+
+    for (pos, item) in std::mem::take(items).into_iter().enumerate() {
+        if let Some(new) = inserted.remove(&pos) {
+            items.push(new);
+        } else if !removed.contains(&pos) {
+            items.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(name: &str, byte_offset: u64, bit_size: u32) -> block::Field {
+        serde_json::from_value(json!({
+            "name": name,
+            "byte_offset": byte_offset,
+            "access": "rw",
+            "bit_size": bit_size,
+        }))
+        .unwrap()
+    }
+
+    fn simple_block(fields: &[(&str, u64)]) -> Module {
+        let fields = fields
+            .iter()
+            .map(|&(name, offset)| field(name, offset, 32))
+            .collect::<Vec<_>>();
+
+        serde_json::from_value(json!({
+            "name": "tim",
+            "blocks": [{ "name": "Tim", "fields": fields }],
+            "bitfields": [],
+            "enums": [],
+        }))
+        .unwrap()
+    }
+
+    fn field_names(block: &Block) -> Vec<&str> {
+        block
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn infer_registers() {
+        let mut module = simple_block(&[
+            ("CR", 0x0),
+            ("CCR1", 0x34),
+            ("CCR2", 0x38),
+            ("CCR3", 0x3c),
+            ("CCR4", 0x40),
+            ("DMAR", 0x4c),
+        ]);
+        infer_module(&mut module, &Settings::default());
+
+        let block = &module.blocks[0];
+        assert_eq!(field_names(block), ["CR", "CCR", "DMAR"]);
+        assert_eq!(block.fields[1].byte_offset, 0x34);
+        assert_eq!(block.fields[1].array, Some(Array { len: 4, stride: 4 }));
+    }
+
+    #[test]
+    fn infer_respects_settings() {
+        let fields = [("CCR1", 0x0), ("CCR2", 0x4), ("CCR3", 0x8)];
+
+        let mut module = simple_block(&fields);
+        let settings = Settings {
+            min_len: 4,
+            ..Settings::default()
+        };
+        infer_module(&mut module, &settings);
+        assert_eq!(field_names(&module.blocks[0]), ["CCR1", "CCR2", "CCR3"]);
+
+        let mut module = simple_block(&fields);
+        let settings = Settings {
+            filter: Glob::new("DR*"),
+            ..Settings::default()
+        };
+        infer_module(&mut module, &settings);
+        assert_eq!(field_names(&module.blocks[0]), ["CCR1", "CCR2", "CCR3"]);
+    }
+
+    #[test]
+    fn infer_skips_uneven_runs() {
+        let mut module = simple_block(&[("CCR1", 0x0), ("CCR2", 0x4), ("CCR3", 0xc)]);
+        infer_module(&mut module, &Settings::default());
+        assert_eq!(field_names(&module.blocks[0]), ["CCR1", "CCR2", "CCR3"]);
+    }
+
+    #[test]
+    fn infer_bitfield_fields() {
+        let fields = (0..8)
+            .map(|i| json!({ "name": format!("CH{i}_IF"), "bit_offset": i * 4, "bit_size": 1 }))
+            .collect::<Vec<_>>();
+        let mut module: Module = serde_json::from_value(json!({
+            "name": "dma",
+            "blocks": [],
+            "bitfields": [{ "name": "Isr", "bit_size": 32, "fields": fields }],
+            "enums": [],
+        }))
+        .unwrap();
+        infer_module(&mut module, &Settings::default());
+
+        let fields = &module.bitfields[0].fields;
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "CH_IF");
+        assert_eq!(fields[0].array, Some(Array { len: 8, stride: 4 }));
+    }
+
+    #[test]
+    fn infer_clusters() {
+        let fields = [
+            ("CH0_CFG", 0x10),
+            ("CH0_DATA", 0x14),
+            ("CH1_CFG", 0x20),
+            ("CH1_DATA", 0x24),
+            ("CH2_CFG", 0x30),
+            ("CH2_DATA", 0x34),
+        ];
+
+        let mut module = simple_block(&fields);
+        infer_module(&mut module, &Settings::default());
+
+        let block = &module.blocks[0];
+        assert_eq!(field_names(block), ["CH"]);
+        assert_eq!(block.fields[0].byte_offset, 0x10);
+        assert_eq!(
+            block.fields[0].array,
+            Some(Array {
+                len: 3,
+                stride: 0x10
+            })
+        );
+
+        let sub_block = &module.blocks[1];
+        assert_eq!(sub_block.name, "TimCh");
+        assert_eq!(field_names(sub_block), ["CFG", "DATA"]);
+        assert_eq!(sub_block.fields[1].byte_offset, 0x4);
+
+        let mut module = simple_block(&fields);
+        let settings = Settings {
+            clusters: false,
+            ..Settings::default()
+        };
+        infer_module(&mut module, &settings);
+        assert_eq!(field_names(&module.blocks[0]), ["CH_CFG", "CH_DATA"]);
+    }
+
+    #[test]
+    fn collapse_fields() {
+        let mut afr1 = field("AFR1", 0x24, 32);
+        afr1.description = Some("second".into());
+        let mut afr0 = field("AFR0", 0x20, 32);
+        afr0.description = Some("first".into());
+
+        let array = collapse(vec![afr1, afr0.clone()], "AFR").unwrap();
+        assert_eq!(array.name, "AFR");
+        assert_eq!(array.byte_offset, 0x20);
+        assert_eq!(array.description.as_deref(), Some("first"));
+        assert_eq!(array.array, Some(Array { len: 2, stride: 4 }));
+
+        assert_eq!(
+            collapse(vec![afr0], "AFR"),
+            Err("at least two fields are needed")
+        );
+    }
+
+    #[test]
+    fn collapse_rejects_mismatches() {
+        let a0 = field("A0", 0x0, 32);
+
+        assert_eq!(
+            collapse(vec![a0.clone(), field("A1", 0x4, 16)], "A"),
+            Err("fields have different layouts")
+        );
+        assert_eq!(
+            collapse(
+                vec![a0.clone(), field("A2", 0x8, 32), field("A3", 0x14, 32)],
+                "A"
+            ),
+            Err("fields are not evenly spaced")
+        );
+        assert_eq!(collapse(vec![a0.clone(), a0], "A"), Err("fields overlap"));
+    }
+}
@@ -0,0 +1,1341 @@
+//! Fluent builders for the IR, checking sizes and offsets as items are added.
+//!
+//! ```
+//! use halogen_ir::builder::*;
+//!
+//! let module = ModuleBuilder::new("uart")
+//!     .add_block(
+//!         BlockBuilder::new("Uart")
+//!             .add_field(BlockFieldBuilder::register("CR", 0x0, "Cr"))
+//!             .add_field(BlockFieldBuilder::simple("DR", 0x4, 32)),
+//!     )
+//!     .add_bitfield(
+//!         BitfieldBuilder::new("Cr", 32)
+//!             .add_field(BitfieldFieldBuilder::new("EN", 0, 1).description("Enable"))
+//!             .add_field(BitfieldFieldBuilder::new("MODE", 1, 2).enum_name("Mode")),
+//!     )
+//!     .add_enum(
+//!         EnumBuilder::new("Mode", 2)
+//!             .add_variant(VariantBuilder::new("RX", 1))
+//!             .add_variant(VariantBuilder::new("TX", 2)),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Builders keep the first error found and return it from `build`. Checks
+//! which need the rest of the module, like references and the size of the
+//! registers laid out by bitfields, are done by [`ModuleBuilder::build`].
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ir::*;
+use crate::visit::{Path, Segment};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Location of the offending item
+    pub path: Path,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    Duplicate,
+    InvalidSize { bit_size: u32 },
+    OutOfBounds { end: u64, bit_size: u32 },
+    Overlap { other: String },
+    InvalidArray { reason: &'static str },
+    ValueTooLarge { value: u64, bit_size: u32 },
+    InvalidProperty { property: &'static str },
+    UnknownItem { kind: &'static str, name: String },
+    EnumTooWide { enum_name: String, bit_size: u32 },
+    MissingImport { module: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+
+        match &self.kind {
+            ErrorKind::Duplicate => write!(f, "defined multiple times"),
+            ErrorKind::InvalidSize { bit_size } => write!(f, "invalid size of {bit_size} bits"),
+            ErrorKind::OutOfBounds { end, bit_size } => {
+                write!(f, "ends at bit {end}, past the {bit_size} bits available")
+            }
+            ErrorKind::Overlap { other } => write!(f, "overlaps with {other}"),
+            ErrorKind::InvalidArray { reason } => write!(f, "invalid array, {reason}"),
+            ErrorKind::ValueTooLarge { value, bit_size } => {
+                write!(f, "value {value} does not fit in {bit_size} bits")
+            }
+            ErrorKind::InvalidProperty { property } => {
+                write!(f, "cannot set {property} on this kind of field")
+            }
+            ErrorKind::UnknownItem { kind, name } => write!(f, "unknown {kind} {name}"),
+            ErrorKind::EnumTooWide {
+                enum_name,
+                bit_size,
+            } => write!(f, "enum {enum_name} does not fit in {bit_size} bits"),
+            ErrorKind::MissingImport { module } => {
+                write!(f, "uses module {module} without importing it")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The first error found by a builder.
+#[derive(Debug, Clone, Default)]
+struct Errors(Option<Error>);
+
+impl Errors {
+    fn push(&mut self, path: Path, kind: ErrorKind) {
+        self.0.get_or_insert(Error { path, kind });
+    }
+
+    /// Keep the error of a child builder, found under `base`.
+    fn extend(&mut self, base: &Path, errors: Errors) {
+        if let Some(error) = errors.0 {
+            let path = error
+                .path
+                .segments()
+                .iter()
+                .cloned()
+                .fold(base.clone(), |path, segment| path.join(segment));
+            self.push(path, error.kind);
+        }
+    }
+
+    fn finish<T>(self, item: T) -> Result<T> {
+        match self.0 {
+            Some(error) => Err(error),
+            None => Ok(item),
+        }
+    }
+}
+
+/// A chip, with its peripherals and the modules it imports.
+#[derive(Debug, Clone)]
+pub struct ChipBuilder {
+    chip: Chip,
+    errors: Errors,
+}
+
+impl ChipBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            chip: Chip {
+                name: name.into(),
+                ..Default::default()
+            },
+            errors: Errors::default(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.chip.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.chip.source = Some(source);
+        self
+    }
+
+    pub fn add_import(mut self, name: impl Into<String>, version: Option<&str>) -> Self {
+        let import = chip::Import {
+            name: name.into(),
+            version: version.map(str::to_string),
+        };
+
+        if self
+            .chip
+            .imports
+            .iter()
+            .any(|other| other.name == import.name)
+        {
+            let path = self.path().join(Segment::Module {
+                name: import.name,
+                version: import.version,
+            });
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        self.chip.imports.push(import);
+        self
+    }
+
+    pub fn add_peripheral(mut self, peripheral: PeripheralBuilder) -> Self {
+        let peripheral = peripheral.peripheral;
+        let path = self
+            .path()
+            .join(Segment::Peripheral(peripheral.name.clone()));
+
+        if self
+            .chip
+            .peripherals
+            .iter()
+            .any(|other| other.name == peripheral.name)
+        {
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        self.chip.peripherals.push(peripheral);
+        self
+    }
+
+    /// Mark the chip as having a Cortex-M3/M4 style bit-band region.
+    pub fn bit_band(mut self) -> Self {
+        let cm_ext = self.chip.cm_ext.get_or_insert_with(|| chip::CmExt {
+            cm_regs: Vec::new(),
+            bit_band: false,
+        });
+        cm_ext.bit_band = true;
+        self
+    }
+
+    pub fn add_cm_reg(mut self, cm_reg: CmRegBuilder) -> Self {
+        let cm_reg = cm_reg.cm_reg;
+        let cm_ext = self.chip.cm_ext.get_or_insert_with(|| chip::CmExt {
+            cm_regs: Vec::new(),
+            bit_band: false,
+        });
+
+        if cm_ext.cm_regs.iter().any(|other| other.name == cm_reg.name) {
+            let path = self.path().join(Segment::CmReg(cm_reg.name));
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        cm_ext.cm_regs.push(cm_reg);
+        self
+    }
+
+    pub fn add_csr(mut self, csr: CsrBuilder) -> Self {
+        let csr = csr.csr;
+        let path = self.path().join(Segment::Csr(csr.name.clone()));
+
+        if csr.number > 0xfff {
+            let value = csr.number.into();
+            self.errors.push(
+                path,
+                ErrorKind::ValueTooLarge {
+                    value,
+                    bit_size: 12,
+                },
+            );
+            return self;
+        }
+
+        let riscv_ext = self
+            .chip
+            .riscv_ext
+            .get_or_insert_with(|| chip::RiscvExt { csrs: Vec::new() });
+
+        if riscv_ext.csrs.iter().any(|other| other.name == csr.name) {
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        riscv_ext.csrs.push(csr);
+        self
+    }
+
+    /// Check that every peripheral, core register and CSR uses an imported
+    /// module.
+    pub fn build(mut self) -> Result<Chip> {
+        let peripherals = self.chip.peripherals.iter().map(|peripheral| {
+            (
+                Segment::Peripheral(peripheral.name.clone()),
+                &peripheral.module,
+            )
+        });
+        let cm_regs = self
+            .chip
+            .cm_ext
+            .iter()
+            .flat_map(|cm_ext| &cm_ext.cm_regs)
+            .map(|cm_reg| (Segment::CmReg(cm_reg.name.clone()), &cm_reg.module));
+        let csrs = self
+            .chip
+            .riscv_ext
+            .iter()
+            .flat_map(|riscv_ext| &riscv_ext.csrs)
+            .map(|csr| (Segment::Csr(csr.name.clone()), &csr.module));
+
+        for (segment, module) in peripherals.chain(cm_regs).chain(csrs) {
+            if !self
+                .chip
+                .imports
+                .iter()
+                .any(|import| import.name == *module)
+            {
+                let path = self.path().join(segment);
+                let module = module.clone();
+                self.errors.push(path, ErrorKind::MissingImport { module });
+            }
+        }
+
+        self.errors.finish(self.chip)
+    }
+
+    fn path(&self) -> Path {
+        Path::new().join(Segment::Chip(self.chip.name.clone()))
+    }
+}
+
+/// A peripheral of a chip, see [`ChipBuilder::add_peripheral`].
+#[derive(Debug, Clone)]
+pub struct PeripheralBuilder {
+    peripheral: chip::Peripheral,
+}
+
+impl PeripheralBuilder {
+    /// A peripheral instantiating a block of a module at the given address.
+    pub fn new(
+        name: impl Into<String>,
+        address: u64,
+        module: impl Into<String>,
+        block_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            peripheral: chip::Peripheral {
+                name: name.into(),
+                description: None,
+                module: module.into(),
+                address,
+                block_name: block_name.into(),
+                source: None,
+            },
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.peripheral.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.peripheral.source = Some(source);
+        self
+    }
+}
+
+/// A core register of a Cortex-M chip, accessed with `mrs` and `msr`, see
+/// [`ChipBuilder::add_cm_reg`]. Read-write unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct CmRegBuilder {
+    cm_reg: chip::cm_ext::CmReg,
+}
+
+impl CmRegBuilder {
+    /// A register laid out by a bitfield of a module, `reg_name` is the name
+    /// used by the instructions.
+    pub fn new(
+        name: impl Into<String>,
+        reg_name: impl Into<String>,
+        module: impl Into<String>,
+        bitfield_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            cm_reg: chip::cm_ext::CmReg {
+                name: name.into(),
+                description: None,
+                module: module.into(),
+                reg_name: reg_name.into(),
+                access: Access::ReadWrite,
+                bitfield_name: bitfield_name.into(),
+            },
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.cm_reg.description = Some(description.into());
+        self
+    }
+
+    pub fn access(mut self, access: Access) -> Self {
+        self.cm_reg.access = access;
+        self
+    }
+}
+
+/// A control and status register of a RISC-V chip, see
+/// [`ChipBuilder::add_csr`]. Read-write unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct CsrBuilder {
+    csr: chip::riscv_ext::Csr,
+}
+
+impl CsrBuilder {
+    /// A register laid out by a bitfield of a module, `number` is its
+    /// address in the CSR space.
+    pub fn new(
+        name: impl Into<String>,
+        number: u16,
+        module: impl Into<String>,
+        bitfield_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            csr: chip::riscv_ext::Csr {
+                name: name.into(),
+                description: None,
+                module: module.into(),
+                number,
+                access: Access::ReadWrite,
+                bitfield_name: bitfield_name.into(),
+            },
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.csr.description = Some(description.into());
+        self
+    }
+
+    pub fn access(mut self, access: Access) -> Self {
+        self.csr.access = access;
+        self
+    }
+}
+
+/// A module, with its blocks, bitfields and enums.
+#[derive(Debug, Clone)]
+pub struct ModuleBuilder {
+    module: Module,
+    errors: Errors,
+}
+
+impl ModuleBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            module: Module {
+                name: name.into(),
+                version: None,
+                description: None,
+                blocks: Vec::new(),
+                bitfields: Vec::new(),
+                enums: Vec::new(),
+                skipped: Vec::new(),
+                source: None,
+            },
+            errors: Errors::default(),
+        }
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.module.version = Some(version.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.module.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.module.source = Some(source);
+        self
+    }
+
+    pub fn add_block(mut self, block: BlockBuilder) -> Self {
+        let path = self.path();
+        self.errors.extend(&path, block.errors);
+
+        if self
+            .module
+            .blocks
+            .iter()
+            .any(|other| other.name == block.block.name)
+        {
+            let path = path.join(Segment::Block(block.block.name));
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        self.module.blocks.push(block.block);
+        self
+    }
+
+    pub fn add_bitfield(mut self, bitfield: BitfieldBuilder) -> Self {
+        let path = self.path();
+        self.errors.extend(&path, bitfield.errors);
+
+        if self
+            .module
+            .bitfields
+            .iter()
+            .any(|other| other.name == bitfield.bitfield.name)
+        {
+            let path = path.join(Segment::Bitfield(bitfield.bitfield.name));
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        self.module.bitfields.push(bitfield.bitfield);
+        self
+    }
+
+    pub fn add_enum(mut self, enum_name: EnumBuilder) -> Self {
+        let path = self.path();
+        self.errors.extend(&path, enum_name.errors);
+
+        if self
+            .module
+            .enums
+            .iter()
+            .any(|other| other.name == enum_name.enum_name.name)
+        {
+            let path = path.join(Segment::Enum(enum_name.enum_name.name));
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        self.module.enums.push(enum_name.enum_name);
+        self
+    }
+
+    /// Check the references between items, that enums fit the fields using
+    /// them, and that registers laid out by bitfields do not overlap.
+    pub fn build(mut self) -> Result<Module> {
+        let path = self.path();
+        let module = &self.module;
+
+        let bitfields = module
+            .bitfields
+            .iter()
+            .map(|bitfield| (bitfield.name.as_str(), bitfield))
+            .collect::<HashMap<_, _>>();
+        let enums = module
+            .enums
+            .iter()
+            .map(|enum_name| (enum_name.name.as_str(), enum_name))
+            .collect::<HashMap<_, _>>();
+
+        for block in &module.blocks {
+            let path = path.join(Segment::Block(block.name.clone()));
+
+            for field in &block.fields {
+                let path = path.join(Segment::Field(field.name.clone()));
+
+                match &field.inner {
+                    block::FieldInner::Block(inner) => {
+                        if !module
+                            .blocks
+                            .iter()
+                            .any(|block| block.name == inner.block_name)
+                        {
+                            let name = inner.block_name.clone();
+                            let kind = "block";
+                            self.errors
+                                .push(path, ErrorKind::UnknownItem { kind, name });
+                        }
+                    }
+                    block::FieldInner::Bitfield(inner) => {
+                        if !bitfields.contains_key(inner.bitfield_name.as_str()) {
+                            let name = inner.bitfield_name.clone();
+                            let kind = "bitfield";
+                            self.errors
+                                .push(path, ErrorKind::UnknownItem { kind, name });
+                        }
+                    }
+                    block::FieldInner::Simple(inner) => {
+                        if let Some(name) = &inner.enum_name {
+                            check_enum(&mut self.errors, &path, &enums, name, inner.bit_size);
+                        }
+                    }
+                }
+            }
+
+            // Simple registers were checked against each other by the block
+            // builder already, only the ones involving bitfields are left
+            let registers = block
+                .fields
+                .iter()
+                .filter_map(|field| match &field.inner {
+                    block::FieldInner::Block(_) => None,
+                    block::FieldInner::Bitfield(inner) => {
+                        let bitfield = bitfields.get(inner.bitfield_name.as_str())?;
+                        Some((field, true, Span::register(field, bitfield.bit_size)))
+                    }
+                    block::FieldInner::Simple(inner) => {
+                        Some((field, false, Span::register(field, inner.bit_size)))
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            for (i, (field, bitfield, span)) in registers.iter().enumerate() {
+                for (other, other_bitfield, other_span) in &registers[..i] {
+                    if (*bitfield || *other_bitfield) && span.overlaps(other_span) {
+                        let path = path.join(Segment::Field(field.name.clone()));
+                        let other = other.name.clone();
+                        self.errors.push(path, ErrorKind::Overlap { other });
+                    }
+                }
+            }
+        }
+
+        for bitfield in &module.bitfields {
+            let path = path.join(Segment::Bitfield(bitfield.name.clone()));
+
+            for field in &bitfield.fields {
+                if let Some(name) = &field.enum_name {
+                    let path = path.join(Segment::Field(field.name.clone()));
+                    check_enum(&mut self.errors, &path, &enums, name, field.bit_size);
+                }
+            }
+        }
+
+        self.errors.finish(self.module)
+    }
+
+    fn path(&self) -> Path {
+        Path::new().join(Segment::Module {
+            name: self.module.name.clone(),
+            version: self.module.version.clone(),
+        })
+    }
+}
+
+fn check_enum(
+    errors: &mut Errors,
+    path: &Path,
+    enums: &HashMap<&str, &Enum>,
+    name: &str,
+    bit_size: u32,
+) {
+    match enums.get(name) {
+        Some(enum_name) if enum_name.bit_size > bit_size => {
+            let enum_name = name.to_string();
+            errors.push(
+                path.clone(),
+                ErrorKind::EnumTooWide {
+                    enum_name,
+                    bit_size,
+                },
+            );
+        }
+        Some(_) => {}
+        None => {
+            let name = name.to_string();
+            let kind = "enum";
+            errors.push(path.clone(), ErrorKind::UnknownItem { kind, name });
+        }
+    }
+}
+
+/// A block of registers.
+#[derive(Debug, Clone)]
+pub struct BlockBuilder {
+    block: Block,
+    errors: Errors,
+}
+
+impl BlockBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            block: Block {
+                name: name.into(),
+                description: None,
+                fields: Vec::new(),
+                source: None,
+            },
+            errors: Errors::default(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.block.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.block.source = Some(source);
+        self
+    }
+
+    /// Add a field, checking its array and that simple registers have a
+    /// valid size and do not overlap.
+    pub fn add_field(mut self, field: BlockFieldBuilder) -> Self {
+        let path = Path::new()
+            .join(Segment::Block(self.block.name.clone()))
+            .join(Segment::Field(field.field.name.clone()));
+
+        if let Some(kind) = field.error {
+            self.errors.push(path, kind);
+            return self;
+        }
+        let field = field.field;
+
+        if self
+            .block
+            .fields
+            .iter()
+            .any(|other| other.name == field.name)
+        {
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        if let block::FieldInner::Simple(inner) = &field.inner {
+            if !matches!(inner.bit_size, 8 | 16 | 32 | 64) {
+                let bit_size = inner.bit_size;
+                self.errors.push(path, ErrorKind::InvalidSize { bit_size });
+                return self;
+            }
+
+            let span = Span::register(&field, inner.bit_size);
+            if let Err(reason) = span.check_array() {
+                self.errors.push(path, ErrorKind::InvalidArray { reason });
+                return self;
+            }
+
+            for other in &self.block.fields {
+                let block::FieldInner::Simple(other_inner) = &other.inner else {
+                    continue;
+                };
+
+                if span.overlaps(&Span::register(other, other_inner.bit_size)) {
+                    let other = other.name.clone();
+                    self.errors.push(path.clone(), ErrorKind::Overlap { other });
+                }
+            }
+        } else if let Some(array) = &field.array {
+            // The size of the element is not known yet
+            let span = Span {
+                offset: field.byte_offset,
+                size: 1,
+                array: Some(array.clone()),
+            };
+            if let Err(reason) = span.check_array() {
+                self.errors.push(path, ErrorKind::InvalidArray { reason });
+                return self;
+            }
+        }
+
+        self.block.fields.push(field);
+        self
+    }
+
+    pub fn build(self) -> Result<Block> {
+        self.errors.finish(self.block)
+    }
+}
+
+/// A field of a block, see [`BlockBuilder::add_field`]. Registers are
+/// read-write unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct BlockFieldBuilder {
+    field: block::Field,
+    error: Option<ErrorKind>,
+}
+
+impl BlockFieldBuilder {
+    fn new(name: impl Into<String>, byte_offset: u64, inner: block::FieldInner) -> Self {
+        Self {
+            field: block::Field {
+                name: name.into(),
+                description: None,
+                array: None,
+                byte_offset,
+                source: None,
+                inner,
+            },
+            error: None,
+        }
+    }
+
+    /// A register laid out by a bitfield.
+    pub fn register(
+        name: impl Into<String>,
+        byte_offset: u64,
+        bitfield_name: impl Into<String>,
+    ) -> Self {
+        let inner = block::FieldInner::Bitfield(block::field::Bitfield {
+            access: Access::ReadWrite,
+            bitfield_name: bitfield_name.into(),
+        });
+        Self::new(name, byte_offset, inner)
+    }
+
+    /// A plain register, 8, 16, 32 or 64 bits wide.
+    pub fn simple(name: impl Into<String>, byte_offset: u64, bit_size: u32) -> Self {
+        let inner = block::FieldInner::Simple(block::field::Simple {
+            access: Access::ReadWrite,
+            bit_size,
+            enum_name: None,
+        });
+        Self::new(name, byte_offset, inner)
+    }
+
+    /// A nested block.
+    pub fn block(name: impl Into<String>, byte_offset: u64, block_name: impl Into<String>) -> Self {
+        let inner = block::FieldInner::Block(block::field::Block {
+            block_name: block_name.into(),
+        });
+        Self::new(name, byte_offset, inner)
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.field.description = Some(description.into());
+        self
+    }
+
+    pub fn array(mut self, len: u64, stride: u64) -> Self {
+        self.field.array = Some(Array { len, stride });
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.field.source = Some(source);
+        self
+    }
+
+    /// Only for registers.
+    pub fn access(mut self, access: Access) -> Self {
+        match &mut self.field.inner {
+            block::FieldInner::Bitfield(inner) => inner.access = access,
+            block::FieldInner::Simple(inner) => inner.access = access,
+            block::FieldInner::Block(_) => self.invalid("access"),
+        }
+        self
+    }
+
+    /// Only for simple registers.
+    pub fn enum_name(mut self, enum_name: impl Into<String>) -> Self {
+        match &mut self.field.inner {
+            block::FieldInner::Simple(inner) => inner.enum_name = Some(enum_name.into()),
+            _ => self.invalid("enum_name"),
+        }
+        self
+    }
+
+    fn invalid(&mut self, property: &'static str) {
+        self.error
+            .get_or_insert(ErrorKind::InvalidProperty { property });
+    }
+}
+
+/// The layout of a register.
+#[derive(Debug, Clone)]
+pub struct BitfieldBuilder {
+    bitfield: Bitfield,
+    errors: Errors,
+}
+
+impl BitfieldBuilder {
+    pub fn new(name: impl Into<String>, bit_size: u32) -> Self {
+        let mut builder = Self {
+            bitfield: Bitfield {
+                name: name.into(),
+                description: None,
+                bit_size,
+                default: 0,
+                fields: Vec::new(),
+                source: None,
+            },
+            errors: Errors::default(),
+        };
+
+        if !(1..=64).contains(&bit_size) {
+            let path = builder.path();
+            builder
+                .errors
+                .push(path, ErrorKind::InvalidSize { bit_size });
+        }
+
+        builder
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.bitfield.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.bitfield.source = Some(source);
+        self
+    }
+
+    /// Value of the register after reset.
+    pub fn default(mut self, default: u64) -> Self {
+        if !fits(default, self.bitfield.bit_size) {
+            let path = self.path();
+            let kind = ErrorKind::ValueTooLarge {
+                value: default,
+                bit_size: self.bitfield.bit_size,
+            };
+            self.errors.push(path, kind);
+        }
+
+        self.bitfield.default = default;
+        self
+    }
+
+    /// Add a field, checking that it fits in the register and does not
+    /// overlap the others.
+    pub fn add_field(mut self, field: BitfieldFieldBuilder) -> Self {
+        let field = field.field;
+        let path = self.path().join(Segment::Field(field.name.clone()));
+
+        if self
+            .bitfield
+            .fields
+            .iter()
+            .any(|other| other.name == field.name)
+        {
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        if !(1..=64).contains(&field.bit_size) {
+            let bit_size = field.bit_size;
+            self.errors.push(path, ErrorKind::InvalidSize { bit_size });
+            return self;
+        }
+
+        let span = Span::bits(&field);
+        if let Err(reason) = span.check_array() {
+            self.errors.push(path, ErrorKind::InvalidArray { reason });
+            return self;
+        }
+
+        let end = span.end();
+        if end > self.bitfield.bit_size as u64 {
+            let bit_size = self.bitfield.bit_size;
+            self.errors
+                .push(path, ErrorKind::OutOfBounds { end, bit_size });
+            return self;
+        }
+
+        for other in &self.bitfield.fields {
+            if span.overlaps(&Span::bits(other)) {
+                let other = other.name.clone();
+                self.errors.push(path.clone(), ErrorKind::Overlap { other });
+            }
+        }
+
+        self.bitfield.fields.push(field);
+        self
+    }
+
+    pub fn build(self) -> Result<Bitfield> {
+        self.errors.finish(self.bitfield)
+    }
+
+    fn path(&self) -> Path {
+        Path::new().join(Segment::Bitfield(self.bitfield.name.clone()))
+    }
+}
+
+/// A field of a bitfield, see [`BitfieldBuilder::add_field`].
+#[derive(Debug, Clone)]
+pub struct BitfieldFieldBuilder {
+    field: bitfield::Field,
+}
+
+impl BitfieldFieldBuilder {
+    pub fn new(name: impl Into<String>, bit_offset: u32, bit_size: u32) -> Self {
+        Self {
+            field: bitfield::Field {
+                name: name.into(),
+                description: None,
+                bit_offset,
+                bit_size,
+                array: None,
+                enum_name: None,
+                source: None,
+            },
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.field.description = Some(description.into());
+        self
+    }
+
+    pub fn array(mut self, len: u64, stride: u64) -> Self {
+        self.field.array = Some(Array { len, stride });
+        self
+    }
+
+    pub fn enum_name(mut self, enum_name: impl Into<String>) -> Self {
+        self.field.enum_name = Some(enum_name.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.field.source = Some(source);
+        self
+    }
+}
+
+/// The values a field can take.
+#[derive(Debug, Clone)]
+pub struct EnumBuilder {
+    enum_name: Enum,
+    errors: Errors,
+}
+
+impl EnumBuilder {
+    pub fn new(name: impl Into<String>, bit_size: u32) -> Self {
+        let mut builder = Self {
+            enum_name: Enum {
+                name: name.into(),
+                description: None,
+                bit_size,
+                variants: Vec::new(),
+                source: None,
+            },
+            errors: Errors::default(),
+        };
+
+        if !(1..=64).contains(&bit_size) {
+            let path = builder.path();
+            builder
+                .errors
+                .push(path, ErrorKind::InvalidSize { bit_size });
+        }
+
+        builder
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.enum_name.description = Some(description.into());
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.enum_name.source = Some(source);
+        self
+    }
+
+    /// Add a variant, checking that its value fits in the enum.
+    pub fn add_variant(mut self, variant: VariantBuilder) -> Self {
+        let variant = variant.variant;
+        let path = self.path().join(Segment::Variant(variant.name.clone()));
+
+        if self
+            .enum_name
+            .variants
+            .iter()
+            .any(|other| other.name == variant.name)
+        {
+            self.errors.push(path, ErrorKind::Duplicate);
+            return self;
+        }
+
+        if !fits(variant.value, self.enum_name.bit_size) {
+            let kind = ErrorKind::ValueTooLarge {
+                value: variant.value,
+                bit_size: self.enum_name.bit_size,
+            };
+            self.errors.push(path, kind);
+            return self;
+        }
+
+        self.enum_name.variants.push(variant);
+        self
+    }
+
+    pub fn build(self) -> Result<Enum> {
+        self.errors.finish(self.enum_name)
+    }
+
+    fn path(&self) -> Path {
+        Path::new().join(Segment::Enum(self.enum_name.name.clone()))
+    }
+}
+
+/// A variant of an enum, see [`EnumBuilder::add_variant`].
+#[derive(Debug, Clone)]
+pub struct VariantBuilder {
+    variant: enum_name::Variant,
+}
+
+impl VariantBuilder {
+    pub fn new(name: impl Into<String>, value: u64) -> Self {
+        Self {
+            variant: enum_name::Variant {
+                name: name.into(),
+                description: None,
+                value,
+            },
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.variant.description = Some(description.into());
+        self
+    }
+}
+
+fn fits(value: u64, bit_size: u32) -> bool {
+    bit_size >= 64 || value >> bit_size == 0
+}
+
+/// The space taken by a field, in bytes or bits, repeated for arrays.
+struct Span {
+    offset: u64,
+    size: u64,
+    array: Option<Array>,
+}
+
+impl Span {
+    fn register(field: &block::Field, bit_size: u32) -> Self {
+        Self {
+            offset: field.byte_offset,
+            size: (bit_size as u64).div_ceil(8),
+            array: field.array.clone(),
+        }
+    }
+
+    fn bits(field: &bitfield::Field) -> Self {
+        Self {
+            offset: field.bit_offset as u64,
+            size: field.bit_size as u64,
+            array: field.array.clone(),
+        }
+    }
+
+    fn check_array(&self) -> std::result::Result<(), &'static str> {
+        let Some(array) = &self.array else {
+            return Ok(());
+        };
+
+        if array.len == 0 {
+            Err("no elements")
+        } else if array.len > 1 && array.stride < self.size {
+            Err("elements overlap")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start and end of every element.
+    fn ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let (len, stride) = self
+            .array
+            .as_ref()
+            .map_or((1, 0), |array| (array.len, array.stride));
+
+        (0..len).map(move |i| {
+            let start = self.offset + i * stride;
+            (start, start + self.size)
+        })
+    }
+
+    fn end(&self) -> u64 {
+        self.ranges().last().map_or(self.offset, |(_, end)| end)
+    }
+
+    fn overlaps(&self, other: &Span) -> bool {
+        self.ranges().any(|(start, end)| {
+            other
+                .ranges()
+                .any(|(other_start, other_end)| start < other_end && other_start < end)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn error<T: fmt::Debug>(result: Result<T>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn module() {
+        let module = ModuleBuilder::new("uart")
+            .version("v1")
+            .add_block(
+                BlockBuilder::new("Uart")
+                    .add_field(BlockFieldBuilder::register("CR", 0x0, "Cr"))
+                    .add_field(
+                        BlockFieldBuilder::simple("DR", 0x4, 8)
+                            .array(4, 1)
+                            .access(Access::Read),
+                    ),
+            )
+            .add_bitfield(
+                BitfieldBuilder::new("Cr", 32)
+                    .default(0x10)
+                    .add_field(BitfieldFieldBuilder::new("MODE", 1, 2).enum_name("Mode")),
+            )
+            .add_enum(EnumBuilder::new("Mode", 2).add_variant(VariantBuilder::new("TX", 2)))
+            .build()
+            .unwrap();
+
+        let expected: Module = serde_json::from_value(json!({
+            "name": "uart",
+            "version": "v1",
+            "blocks": [{
+                "name": "Uart",
+                "fields": [
+                    { "name": "CR", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr" },
+                    {
+                        "name": "DR",
+                        "byte_offset": 4,
+                        "array": { "len": 4, "stride": 1 },
+                        "access": "ro",
+                        "bit_size": 8,
+                    },
+                ],
+            }],
+            "bitfields": [{
+                "name": "Cr",
+                "bit_size": 32,
+                "default": 16,
+                "fields": [{ "name": "MODE", "bit_offset": 1, "bit_size": 2, "enum_name": "Mode" }],
+            }],
+            "enums": [{ "name": "Mode", "bit_size": 2, "variants": [{ "name": "TX", "value": 2 }] }],
+        }))
+        .unwrap();
+        assert_eq!(module, expected);
+    }
+
+    #[test]
+    fn field_errors() {
+        let bitfield = BitfieldBuilder::new("Cr", 8)
+            .add_field(BitfieldFieldBuilder::new("A", 0, 4))
+            .add_field(BitfieldFieldBuilder::new("B", 2, 2));
+        assert_eq!(
+            error(bitfield.build()),
+            "bitfield Cr > field B: overlaps with A"
+        );
+
+        let bitfield = BitfieldBuilder::new("Cr", 8)
+            .add_field(BitfieldFieldBuilder::new("A", 4, 1).array(3, 2));
+        assert_eq!(
+            error(bitfield.build()),
+            "bitfield Cr > field A: ends at bit 9, past the 8 bits available"
+        );
+
+        let bitfield = BitfieldBuilder::new("Cr", 4).default(0x10);
+        assert_eq!(
+            error(bitfield.build()),
+            "bitfield Cr: value 16 does not fit in 4 bits"
+        );
+
+        let block = BlockBuilder::new("Uart")
+            .add_field(BlockFieldBuilder::simple("DR", 0, 24))
+            .add_field(BlockFieldBuilder::simple("SR", 0, 32));
+        assert_eq!(
+            error(block.build()),
+            "block Uart > field DR: invalid size of 24 bits"
+        );
+
+        let block =
+            BlockBuilder::new("Uart").add_field(BlockFieldBuilder::simple("DR", 0, 32).array(2, 2));
+        assert_eq!(
+            error(block.build()),
+            "block Uart > field DR: invalid array, elements overlap"
+        );
+
+        let block = BlockBuilder::new("Uart")
+            .add_field(BlockFieldBuilder::block("SUB", 0, "Sub").access(Access::Read));
+        assert_eq!(
+            error(block.build()),
+            "block Uart > field SUB: cannot set access on this kind of field"
+        );
+    }
+
+    #[test]
+    fn module_errors() {
+        let module = ModuleBuilder::new("uart")
+            .add_block(
+                BlockBuilder::new("Uart").add_field(BlockFieldBuilder::register("CR", 0, "Cr")),
+            )
+            .build();
+        assert_eq!(
+            error(module),
+            "module uart > block Uart > field CR: unknown bitfield Cr"
+        );
+
+        let module = ModuleBuilder::new("uart")
+            .add_bitfield(
+                BitfieldBuilder::new("Cr", 32)
+                    .add_field(BitfieldFieldBuilder::new("MODE", 0, 1).enum_name("Mode")),
+            )
+            .add_enum(EnumBuilder::new("Mode", 2))
+            .build();
+        assert_eq!(
+            error(module),
+            "module uart > bitfield Cr > field MODE: enum Mode does not fit in 1 bits"
+        );
+
+        // Overlaps involving bitfields are only known once the module is built
+        let module = ModuleBuilder::new("uart")
+            .add_block(
+                BlockBuilder::new("Uart")
+                    .add_field(BlockFieldBuilder::register("CR", 0, "Cr"))
+                    .add_field(BlockFieldBuilder::simple("DR", 2, 8)),
+            )
+            .add_bitfield(BitfieldBuilder::new("Cr", 32))
+            .build();
+        assert_eq!(
+            error(module),
+            "module uart > block Uart > field DR: overlaps with CR"
+        );
+
+        let module = ModuleBuilder::new("uart")
+            .add_enum(EnumBuilder::new("Mode", 1).add_variant(VariantBuilder::new("A", 2)))
+            .build();
+        assert_eq!(
+            error(module),
+            "module uart > enum Mode > variant A: value 2 does not fit in 1 bits"
+        );
+    }
+
+    #[test]
+    fn chip_errors() {
+        let peripheral = || PeripheralBuilder::new("USART1", 0x4001_3800, "usart", "Usart");
+
+        let chip = ChipBuilder::new("F103")
+            .add_import("usart", Some("v1"))
+            .add_peripheral(peripheral())
+            .build()
+            .unwrap();
+        assert_eq!(chip.imports[0].version.as_deref(), Some("v1"));
+        assert_eq!(chip.peripherals[0].address, 0x4001_3800);
+
+        let chip = ChipBuilder::new("F103")
+            .add_peripheral(peripheral())
+            .build();
+        assert_eq!(
+            error(chip),
+            "chip F103 > peripheral USART1: uses module usart without importing it"
+        );
+
+        let chip = ChipBuilder::new("F103")
+            .add_import("usart", None)
+            .add_import("usart", Some("v2"))
+            .build();
+        assert_eq!(
+            error(chip),
+            "chip F103 > module usart (v2): defined multiple times"
+        );
+
+        let chip = ChipBuilder::new("CM4")
+            .add_import("cm_regs", None)
+            .add_cm_reg(CmRegBuilder::new(
+                "CONTROL", "control", "cm_regs", "Control",
+            ))
+            .add_cm_reg(CmRegBuilder::new("BASEPRI", "basepri", "nvic", "Basepri"))
+            .build();
+        assert_eq!(
+            error(chip),
+            "chip CM4 > cm_reg BASEPRI: uses module nvic without importing it"
+        );
+    }
+}
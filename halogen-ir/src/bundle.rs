@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::format::{self, FORMAT_VERSION};
+use crate::ir;
+
+/// First bytes of every bundle.
+pub const MAGIC: &[u8; 8] = b"HALOGEN\x00";
+
+/// Extension used for bundles by convention.
+pub const EXTENSION: &str = "bundle";
+
+/// Compression level used by [`dump_bundle_file`].
+pub const DEFAULT_LEVEL: i32 = 9;
+
+// A bundle is the magic, the format version as a little endian u32, then the
+// IR as zstd compressed MessagePack. The IR layout relies on untagged enums,
+// so the encoding has to be self-describing.
+
+/// Load the IR from a bundle.
+pub fn load_bundle_reader(mut reader: impl Read) -> io::Result<ir::MultiChip> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a halogen IR bundle",
+        ));
+    }
+
+    let mut format_version = [0; 4];
+    reader.read_exact(&mut format_version)?;
+    let format_version = u32::from_le_bytes(format_version);
+    format::check_version(format_version)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut decoder = rmp_serde::Deserializer::new(zstd::Decoder::new(reader)?);
+    if format_version == FORMAT_VERSION {
+        return ir::MultiChip::deserialize(&mut decoder).map_err(invalid_data);
+    }
+
+    let mut value = Value::deserialize(&mut decoder).map_err(invalid_data)?;
+    format::migrate_multi_chip(&mut value, format_version);
+    Ok(ir::MultiChip::deserialize(value)?)
+}
+
+/// Dump the IR to a bundle, with a zstd compression level from 1 to 22.
+pub fn dump_bundle_writer(
+    mut writer: impl Write,
+    ir: &ir::MultiChip,
+    level: i32,
+) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+    let mut encoder = zstd::Encoder::new(writer, level)?;
+    rmp_serde::encode::write_named(&mut encoder, ir).map_err(invalid_data)?;
+    encoder.finish()?.flush()
+}
+
+/// Load the IR from a bundle file.
+pub fn load_bundle_file(path: impl AsRef<Path>) -> io::Result<ir::MultiChip> {
+    load_bundle_reader(BufReader::new(File::open(path)?))
+}
+
+/// Dump the IR to a bundle file.
+pub fn dump_bundle_file(path: impl AsRef<Path>, ir: &ir::MultiChip) -> io::Result<()> {
+    dump_bundle_writer(BufWriter::new(File::create(path)?), ir, DEFAULT_LEVEL)
+}
+
+/// Check whether a file is a bundle, by looking at its first bytes. Missing
+/// files are not bundles.
+pub fn is_bundle_file(path: impl AsRef<Path>) -> io::Result<bool> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    file.take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+
+    Ok(magic == MAGIC)
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn ir() -> ir::MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "F103",
+                "peripherals": [{ "name": "USART1", "module": "usart", "address": 0x4001_3800u32, "block_name": "Usart" }],
+                "imports": [{ "name": "usart" }],
+            }],
+            "modules": [{
+                "name": "usart",
+                "blocks": [{ "name": "Usart", "fields": [] }],
+                "bitfields": [],
+                "enums": [],
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let ir = ir();
+        let mut data = Vec::new();
+        dump_bundle_writer(&mut data, &ir, DEFAULT_LEVEL).unwrap();
+
+        assert!(data.starts_with(MAGIC));
+        assert_eq!(load_bundle_reader(data.as_slice()).unwrap(), ir);
+    }
+
+    #[test]
+    fn detection() {
+        let dir = std::env::temp_dir().join(format!("halogen-bundle-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let bundle = dir.join(format!("ir.{EXTENSION}"));
+        dump_bundle_file(&bundle, &ir()).unwrap();
+        assert!(is_bundle_file(&bundle).unwrap());
+
+        let json = dir.join("ir.json");
+        fs::write(&json, "{}").unwrap();
+        assert!(!is_bundle_file(&json).unwrap());
+    }
+
+    #[test]
+    fn bad_header() {
+        let err = load_bundle_reader(&b"{\"chips\": []}"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "not a halogen IR bundle");
+
+        let mut data = MAGIC.to_vec();
+        data.extend((FORMAT_VERSION + 1).to_le_bytes());
+        let err = load_bundle_reader(data.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
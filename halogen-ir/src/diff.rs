@@ -0,0 +1,592 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+use serde::Serialize;
+
+use crate::ir::*;
+use crate::visit::{Path, Segment};
+
+/// A single difference between two IRs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// Location of the changed item, in the old IR for removed and changed
+    /// items and in the new one for added items
+    pub path: Path,
+    #[serde(flatten)]
+    pub kind: ChangeKind,
+    /// Whether the change breaks code using the generated API
+    pub breaking: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed {
+        property: &'static str,
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ChangeKind::Added => write!(f, "+ {}", self.path)?,
+            ChangeKind::Removed => write!(f, "- {}", self.path)?,
+            ChangeKind::Changed { property, old, new } => {
+                write!(f, "~ {}: {property} {old} -> {new}", self.path)?
+            }
+        }
+
+        if self.breaking {
+            write!(f, " (breaking)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare two IRs. Descriptions are ignored, and renamed items show up as
+/// removed and added. A module whose only version changed is compared
+/// against its previous version.
+pub fn diff(old: &MultiChip, new: &MultiChip) -> Vec<Change> {
+    let mut differ = Differ::default();
+    let root = Path::new();
+
+    let chips = pair(&old.chips, &new.chips, |chip| &chip.name);
+    differ.items(
+        &root,
+        &chips,
+        |chip| Segment::Chip(chip.name.clone()),
+        false,
+    );
+    for (old, new) in chips.both {
+        differ.chip(&root.join(Segment::Chip(old.name.clone())), old, new);
+    }
+
+    let mut modules = pair(&old.modules, &new.modules, |module| {
+        (&module.name, &module.version)
+    });
+    let moved = pair_versions(&mut modules);
+    differ.items(&root, &modules, module_segment, false);
+
+    for (old, new) in modules.both.iter().chain(&moved) {
+        let path = root.join(module_segment(old));
+        if old.version != new.version {
+            let version = |module: &Module| module.version.clone().unwrap_or_default();
+            differ.changed(&path, "version", version(old), version(new), false);
+        }
+        differ.module(&path, old, new);
+    }
+
+    differ.changes
+}
+
+fn module_segment(module: &Module) -> Segment {
+    Segment::Module {
+        name: module.name.clone(),
+        version: module.version.clone(),
+    }
+}
+
+/// Items matched by key between the old and new list.
+struct Pairs<'a, T> {
+    removed: Vec<&'a T>,
+    added: Vec<&'a T>,
+    both: Vec<(&'a T, &'a T)>,
+}
+
+fn pair<'a, T, K, F>(old: &'a [T], new: &'a [T], key: F) -> Pairs<'a, T>
+where
+    K: Eq + Hash,
+    F: Fn(&'a T) -> K,
+{
+    let new_keys = new
+        .iter()
+        .map(|item| (key(item), item))
+        .collect::<HashMap<_, _>>();
+    let old_keys = old.iter().map(&key).collect::<HashSet<_>>();
+
+    let mut pairs = Pairs {
+        removed: Vec::new(),
+        added: Vec::new(),
+        both: Vec::new(),
+    };
+
+    for item in old {
+        match new_keys.get(&key(item)) {
+            Some(new) => pairs.both.push((item, new)),
+            None => pairs.removed.push(item),
+        }
+    }
+
+    for item in new {
+        if !old_keys.contains(&key(item)) {
+            pairs.added.push(item);
+        }
+    }
+
+    pairs
+}
+
+/// Pair a removed and an added module with the same name, when there is only
+/// one of each.
+fn pair_versions<'a>(modules: &mut Pairs<'a, Module>) -> Vec<(&'a Module, &'a Module)> {
+    let count =
+        |items: &[&Module], name: &str| items.iter().filter(|module| module.name == name).count();
+
+    let moved = modules
+        .removed
+        .iter()
+        .filter(|old| count(&modules.removed, &old.name) == 1)
+        .filter(|old| count(&modules.added, &old.name) == 1)
+        .map(|old| {
+            let new = modules.added.iter().find(|new| new.name == old.name);
+            (*old, *new.unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    modules
+        .removed
+        .retain(|old| !moved.iter().any(|(moved, _)| moved.name == old.name));
+    modules
+        .added
+        .retain(|new| !moved.iter().any(|(_, moved)| moved.name == new.name));
+
+    moved
+}
+
+#[derive(Default)]
+struct Differ {
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn push(&mut self, path: Path, kind: ChangeKind, breaking: bool) {
+        self.changes.push(Change {
+            path,
+            kind,
+            breaking,
+        });
+    }
+
+    /// Report added and removed items, removing something is always breaking.
+    fn items<T>(
+        &mut self,
+        path: &Path,
+        pairs: &Pairs<'_, T>,
+        segment: impl Fn(&T) -> Segment,
+        add_breaking: bool,
+    ) {
+        for item in &pairs.removed {
+            self.push(path.join(segment(item)), ChangeKind::Removed, true);
+        }
+        for item in &pairs.added {
+            self.push(path.join(segment(item)), ChangeKind::Added, add_breaking);
+        }
+    }
+
+    fn changed(
+        &mut self,
+        path: &Path,
+        property: &'static str,
+        old: String,
+        new: String,
+        breaking: bool,
+    ) {
+        if old != new {
+            let kind = ChangeKind::Changed { property, old, new };
+            self.push(path.clone(), kind, breaking);
+        }
+    }
+
+    fn chip(&mut self, path: &Path, old: &Chip, new: &Chip) {
+        let imports = pair(&old.imports, &new.imports, |import| &import.name);
+        let segment = |import: &chip::Import| Segment::Module {
+            name: import.name.clone(),
+            version: import.version.clone(),
+        };
+        self.items(path, &imports, segment, false);
+        for (old, new) in imports.both {
+            let version = |import: &chip::Import| import.version.clone().unwrap_or_default();
+            // The contents of the module are compared on their own
+            self.changed(
+                &path.join(segment(old)),
+                "version",
+                version(old),
+                version(new),
+                false,
+            );
+        }
+
+        let peripherals = pair(&old.peripherals, &new.peripherals, |p| &p.name);
+        let segment = |p: &chip::Peripheral| Segment::Peripheral(p.name.clone());
+        self.items(path, &peripherals, segment, false);
+        for (old, new) in peripherals.both {
+            let path = path.join(segment(old));
+            let address = |p: &chip::Peripheral| format!("{:#x}", p.address);
+            self.changed(&path, "address", address(old), address(new), false);
+            self.changed(
+                &path,
+                "module",
+                old.module.clone(),
+                new.module.clone(),
+                true,
+            );
+            let (old_block, new_block) = (old.block_name.clone(), new.block_name.clone());
+            self.changed(&path, "block", old_block, new_block, true);
+        }
+
+        let bit_band = |chip: &Chip| chip.cm_ext.as_ref().is_some_and(|cm_ext| cm_ext.bit_band);
+        self.changed(
+            path,
+            "bit_band",
+            bit_band(old).to_string(),
+            bit_band(new).to_string(),
+            bit_band(old) && !bit_band(new),
+        );
+
+        let regs = pair(cm_regs(old), cm_regs(new), |reg| &reg.name);
+        let segment = |reg: &chip::cm_ext::CmReg| Segment::CmReg(reg.name.clone());
+        self.items(path, &regs, segment, false);
+        for (old, new) in regs.both {
+            let path = path.join(segment(old));
+            self.access(&path, &old.access, &new.access);
+            self.changed(
+                &path,
+                "module",
+                old.module.clone(),
+                new.module.clone(),
+                true,
+            );
+            let (old_bitfield, new_bitfield) = (&old.bitfield_name, &new.bitfield_name);
+            self.changed(
+                &path,
+                "bitfield",
+                old_bitfield.clone(),
+                new_bitfield.clone(),
+                true,
+            );
+            let (old_reg, new_reg) = (old.reg_name.clone(), new.reg_name.clone());
+            self.changed(&path, "register", old_reg, new_reg, false);
+        }
+
+        let csrs = pair(csrs(old), csrs(new), |csr| &csr.name);
+        let segment = |csr: &chip::riscv_ext::Csr| Segment::Csr(csr.name.clone());
+        self.items(path, &csrs, segment, false);
+        for (old, new) in csrs.both {
+            let path = path.join(segment(old));
+            self.access(&path, &old.access, &new.access);
+            self.changed(
+                &path,
+                "module",
+                old.module.clone(),
+                new.module.clone(),
+                true,
+            );
+            let (old_bitfield, new_bitfield) = (&old.bitfield_name, &new.bitfield_name);
+            self.changed(
+                &path,
+                "bitfield",
+                old_bitfield.clone(),
+                new_bitfield.clone(),
+                true,
+            );
+            let number = |csr: &chip::riscv_ext::Csr| format!("{:#x}", csr.number);
+            self.changed(&path, "number", number(old), number(new), false);
+        }
+    }
+
+    fn module(&mut self, path: &Path, old: &Module, new: &Module) {
+        let blocks = pair(&old.blocks, &new.blocks, |block| &block.name);
+        self.items(
+            path,
+            &blocks,
+            |block| Segment::Block(block.name.clone()),
+            false,
+        );
+        for (old, new) in blocks.both {
+            self.block(&path.join(Segment::Block(old.name.clone())), old, new);
+        }
+
+        let bitfields = pair(&old.bitfields, &new.bitfields, |bitfield| &bitfield.name);
+        let segment = |bitfield: &Bitfield| Segment::Bitfield(bitfield.name.clone());
+        self.items(path, &bitfields, segment, false);
+        for (old, new) in bitfields.both {
+            self.bitfield(&path.join(segment(old)), old, new);
+        }
+
+        let enums = pair(&old.enums, &new.enums, |enum_name| &enum_name.name);
+        let segment = |enum_name: &Enum| Segment::Enum(enum_name.name.clone());
+        self.items(path, &enums, segment, false);
+        for (old, new) in enums.both {
+            self.enum_name(&path.join(segment(old)), old, new);
+        }
+    }
+
+    fn block(&mut self, path: &Path, old: &Block, new: &Block) {
+        let fields = pair(&old.fields, &new.fields, |field| &field.name);
+        let segment = |field: &block::Field| Segment::Field(field.name.clone());
+        self.items(path, &fields, segment, false);
+
+        for (old, new) in fields.both {
+            let path = path.join(segment(old));
+            let offset = |field: &block::Field| format!("{:#x}", field.byte_offset);
+            self.changed(&path, "offset", offset(old), offset(new), false);
+            self.array(&path, &old.array, &new.array);
+
+            let (old_ty, new_ty) = (block_field_type(&old.inner), block_field_type(&new.inner));
+            if old_ty != new_ty {
+                self.changed(&path, "type", old_ty, new_ty, true);
+            } else if let (block::FieldInner::Simple(old), block::FieldInner::Simple(new)) =
+                (&old.inner, &new.inner)
+            {
+                let (old_size, new_size) = (old.bit_size.to_string(), new.bit_size.to_string());
+                self.changed(&path, "bit_size", old_size, new_size, false);
+            }
+
+            if let (Some(old), Some(new)) = (access(&old.inner), access(&new.inner)) {
+                self.access(&path, old, new);
+            }
+        }
+    }
+
+    fn bitfield(&mut self, path: &Path, old: &Bitfield, new: &Bitfield) {
+        let (old_ty, new_ty) = (int_type(old.bit_size), int_type(new.bit_size));
+        let (old_size, new_size) = (old.bit_size.to_string(), new.bit_size.to_string());
+        self.changed(path, "bit_size", old_size, new_size, old_ty != new_ty);
+        let default = |bitfield: &Bitfield| format!("{:#x}", bitfield.default);
+        self.changed(path, "default", default(old), default(new), false);
+
+        let fields = pair(&old.fields, &new.fields, |field| &field.name);
+        let segment = |field: &bitfield::Field| Segment::Field(field.name.clone());
+        self.items(path, &fields, segment, false);
+
+        for (old, new) in fields.both {
+            let path = path.join(segment(old));
+            let (old_offset, new_offset) = (old.bit_offset.to_string(), new.bit_offset.to_string());
+            self.changed(&path, "bit_offset", old_offset, new_offset, false);
+            self.array(&path, &old.array, &new.array);
+
+            let (old_ty, new_ty) = (bitfield_field_type(old), bitfield_field_type(new));
+            if old_ty != new_ty {
+                self.changed(&path, "type", old_ty, new_ty, true);
+            } else {
+                let (old_size, new_size) = (old.bit_size.to_string(), new.bit_size.to_string());
+                self.changed(&path, "bit_size", old_size, new_size, false);
+            }
+        }
+    }
+
+    fn enum_name(&mut self, path: &Path, old: &Enum, new: &Enum) {
+        let (old_ty, new_ty) = (int_type(old.bit_size), int_type(new.bit_size));
+        let (old_size, new_size) = (old.bit_size.to_string(), new.bit_size.to_string());
+        self.changed(path, "bit_size", old_size, new_size, old_ty != new_ty);
+
+        // Enums are generated as exhaustive, so new variants break matches
+        let variants = pair(&old.variants, &new.variants, |variant| &variant.name);
+        let segment = |variant: &enum_name::Variant| Segment::Variant(variant.name.clone());
+        self.items(path, &variants, segment, true);
+
+        for (old, new) in variants.both {
+            let path = path.join(segment(old));
+            let value = |variant: &enum_name::Variant| format!("{:#x}", variant.value);
+            self.changed(&path, "value", value(old), value(new), false);
+        }
+    }
+
+    fn array(&mut self, path: &Path, old: &Option<Array>, new: &Option<Array>) {
+        let show = |array: &Option<Array>| match array {
+            Some(array) => format!("[{}; stride {:#x}]", array.len, array.stride),
+            None => "none".to_string(),
+        };
+
+        // Only turning a field into an array, or back, changes the accessors
+        let breaking = old.is_some() != new.is_some();
+        self.changed(path, "array", show(old), show(new), breaking);
+    }
+
+    fn access(&mut self, path: &Path, old: &Access, new: &Access) {
+        let breaking = (readable(old) && !readable(new)) || (writable(old) && !writable(new));
+        self.changed(path, "access", access_name(old), access_name(new), breaking);
+    }
+}
+
+fn cm_regs(chip: &Chip) -> &[chip::cm_ext::CmReg] {
+    chip.cm_ext.as_ref().map_or(&[], |cm_ext| &cm_ext.cm_regs)
+}
+
+fn csrs(chip: &Chip) -> &[chip::riscv_ext::Csr] {
+    chip.riscv_ext
+        .as_ref()
+        .map_or(&[], |riscv_ext| &riscv_ext.csrs)
+}
+
+fn readable(access: &Access) -> bool {
+    matches!(access, Access::ReadWrite | Access::Read)
+}
+
+fn writable(access: &Access) -> bool {
+    matches!(access, Access::ReadWrite | Access::Write)
+}
+
+fn access_name(access: &Access) -> String {
+    match access {
+        Access::ReadWrite => "rw",
+        Access::Read => "ro",
+        Access::Write => "wo",
+    }
+    .to_string()
+}
+
+fn access(inner: &block::FieldInner) -> Option<&Access> {
+    match inner {
+        block::FieldInner::Block(_) => None,
+        block::FieldInner::Bitfield(inner) => Some(&inner.access),
+        block::FieldInner::Simple(inner) => Some(&inner.access),
+    }
+}
+
+/// Integer type used by the generated code for a given size.
+fn int_type(bit_size: u32) -> &'static str {
+    match bit_size {
+        0..=8 => "u8",
+        9..=16 => "u16",
+        17..=32 => "u32",
+        _ => "u64",
+    }
+}
+
+fn block_field_type(inner: &block::FieldInner) -> String {
+    match inner {
+        block::FieldInner::Block(inner) => format!("block {}", inner.block_name),
+        block::FieldInner::Bitfield(inner) => format!("bitfield {}", inner.bitfield_name),
+        block::FieldInner::Simple(inner) => match &inner.enum_name {
+            Some(enum_name) => format!("enum {enum_name}"),
+            None => int_type(inner.bit_size).to_string(),
+        },
+    }
+}
+
+fn bitfield_field_type(field: &bitfield::Field) -> String {
+    match &field.enum_name {
+        Some(enum_name) => format!("enum {enum_name}"),
+        None if field.bit_size == 1 => "bool".to_string(),
+        None => int_type(field.bit_size).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn uart(version: &str, dr_size: u32, modes: &[(&str, u64)]) -> Value {
+        let variants = modes
+            .iter()
+            .map(|&(name, value)| json!({ "name": name, "value": value }))
+            .collect::<Vec<_>>();
+
+        json!({
+            "name": "uart",
+            "version": version,
+            "blocks": [{
+                "name": "Uart",
+                "fields": [
+                    { "name": "CR", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr" },
+                    { "name": "DR", "byte_offset": 4, "access": "rw", "bit_size": dr_size },
+                ],
+            }],
+            "bitfields": [{
+                "name": "Cr",
+                "bit_size": 32,
+                "fields": [{ "name": "MODE", "bit_offset": 0, "bit_size": 2, "enum_name": "Mode" }],
+            }],
+            "enums": [{ "name": "Mode", "bit_size": 2, "variants": variants }],
+        })
+    }
+
+    fn chip(version: &str) -> Value {
+        json!({
+            "name": "F103",
+            "peripherals": [{ "name": "USART1", "module": "uart", "address": 0x4001_3800u32, "block_name": "Uart" }],
+            "imports": [{ "name": "uart", "version": version }],
+        })
+    }
+
+    fn multi(chips: Vec<Value>, modules: Vec<Value>) -> MultiChip {
+        serde_json::from_value(json!({ "chips": chips, "modules": modules })).unwrap()
+    }
+
+    fn changes(old: &MultiChip, new: &MultiChip) -> Vec<String> {
+        diff(old, new).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn identical() {
+        let multi = multi(vec![chip("v1")], vec![uart("v1", 32, &[("RX", 1)])]);
+        let mut other = multi.clone();
+        other.modules[0].blocks[0].description = Some("second".into());
+
+        assert!(diff(&multi, &other).is_empty());
+    }
+
+    #[test]
+    fn module_changes() {
+        let old = multi(vec![], vec![uart("v1", 32, &[("RX", 1), ("TX", 2)])]);
+        let new = multi(
+            vec![],
+            vec![uart("v1", 16, &[("RX", 1), ("TX", 3), ("BOTH", 2)])],
+        );
+
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "~ module uart (v1) > block Uart > field DR: type u32 -> u16 (breaking)",
+                "+ module uart (v1) > enum Mode > variant BOTH (breaking)",
+                "~ module uart (v1) > enum Mode > variant TX: value 0x2 -> 0x3",
+            ]
+        );
+    }
+
+    #[test]
+    fn removals_are_breaking() {
+        let old = multi(vec![chip("v1")], vec![uart("v1", 32, &[("RX", 1)])]);
+        let mut new = old.clone();
+        new.modules[0].blocks[0].fields.pop();
+        new.chips[0].peripherals[0].address = 0x4001_4000;
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes[0].to_string(),
+            "~ chip F103 > peripheral USART1: address 0x40013800 -> 0x40014000"
+        );
+        assert!(!changes[0].breaking);
+        assert_eq!(
+            changes[1].path.to_string(),
+            "module uart (v1) > block Uart > field DR"
+        );
+        assert_eq!(changes[1].kind, ChangeKind::Removed);
+        assert!(changes[1].breaking);
+    }
+
+    #[test]
+    fn new_module_version() {
+        let old = multi(vec![chip("v1")], vec![uart("v1", 32, &[("RX", 1)])]);
+        let new = multi(vec![chip("v2")], vec![uart("v2", 16, &[("RX", 1)])]);
+
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "~ chip F103 > module uart (v1): version v1 -> v2",
+                "~ module uart (v1): version v1 -> v2",
+                "~ module uart (v1) > block Uart > field DR: type u32 -> u16 (breaking)",
+            ]
+        );
+    }
+}
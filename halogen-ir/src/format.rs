@@ -0,0 +1,168 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::ir::{Chip, Module, MultiChip};
+
+/// Version of the layout of the IR files, bumped with every change to
+/// [`ir`](crate::ir) which older files would not load with.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// A top-level IR file, tagged with the version of its layout. Files written
+/// before versioning was introduced are version 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "{T}")
+)]
+pub struct Versioned<T> {
+    #[serde(default)]
+    pub format_version: u32,
+    #[serde(flatten)]
+    pub inner: T,
+}
+
+impl<T> Versioned<T> {
+    /// Tag with the current version.
+    pub fn new(inner: T) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            inner,
+        }
+    }
+}
+
+// Written by hand, `#[serde(flatten)]` would buffer the whole IR before
+// handing it over to `MultiChip`
+impl<'de> Deserialize<'de> for Versioned<MultiChip> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(default)]
+            format_version: u32,
+            chips: Vec<Chip>,
+            modules: Vec<Module>,
+        }
+
+        let Fields {
+            format_version,
+            chips,
+            modules,
+        } = Fields::deserialize(deserializer)?;
+
+        Ok(Self {
+            format_version,
+            inner: MultiChip { chips, modules },
+        })
+    }
+}
+
+/// A file written by a newer release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedVersion {
+    pub format_version: u32,
+}
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IR format version {} is newer than the supported {FORMAT_VERSION}",
+            self.format_version
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+pub fn check_version(format_version: u32) -> Result<(), UnsupportedVersion> {
+    if format_version > FORMAT_VERSION {
+        return Err(UnsupportedVersion { format_version });
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "load")]
+pub use migrate::*;
+
+#[cfg(feature = "load")]
+mod migrate {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A change to the layout of the files, upgrading them from version
+    /// `from` to the next one. Migrations work on the raw JSON, as older
+    /// files may not deserialize anymore.
+    pub struct Migration {
+        pub from: u32,
+        pub description: &'static str,
+        pub chip: fn(&mut Value),
+        pub module: fn(&mut Value),
+    }
+
+    /// Every migration, in order.
+    pub static MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: 0,
+            description: "add format_version",
+            chip: |_| {},
+            module: |_| {},
+        },
+        Migration {
+            from: 1,
+            description: "name the chip or module of index entries",
+            chip: |_| {},
+            module: |_| {},
+        },
+    ];
+
+    fn migrations(from: u32) -> impl Iterator<Item = &'static Migration> {
+        MIGRATIONS
+            .iter()
+            .filter(move |migration| migration.from >= from)
+    }
+
+    /// Upgrade a chip to the current version.
+    pub fn migrate_chip(chip: &mut Value, from: u32) {
+        for migration in migrations(from) {
+            (migration.chip)(chip);
+        }
+    }
+
+    /// Upgrade a module to the current version.
+    pub fn migrate_module(module: &mut Value, from: u32) {
+        for migration in migrations(from) {
+            (migration.module)(module);
+        }
+    }
+
+    /// Upgrade a single-file IR to the current version.
+    pub fn migrate_multi_chip(multi: &mut Value, from: u32) {
+        let Some(multi) = multi.as_object_mut() else {
+            return;
+        };
+
+        for chip in items(multi, "chips") {
+            migrate_chip(chip, from);
+        }
+        for module in items(multi, "modules") {
+            migrate_module(module, from);
+        }
+
+        multi.insert("format_version".into(), FORMAT_VERSION.into());
+    }
+
+    fn items<'a>(
+        multi: &'a mut serde_json::Map<String, Value>,
+        key: &str,
+    ) -> impl Iterator<Item = &'a mut Value> {
+        multi
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+    }
+}
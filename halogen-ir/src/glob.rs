@@ -0,0 +1,94 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// Name selector, `*` matches any sequence of characters, `?` matches a
+/// single character and `,` separates alternatives (`CR1,CR2`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Glob(String);
+
+impl Glob {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self(pattern.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.0
+            .split(',')
+            .any(|pattern| wildcard(pattern.as_bytes(), name.as_bytes()))
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last star and of the name when we met it
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    n = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal() {
+        let glob = Glob::new("CR1");
+        assert!(glob.matches("CR1"));
+        assert!(!glob.matches("CR10"));
+        assert!(!glob.matches("CR"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(Glob::new("*").matches(""));
+        assert!(Glob::new("CR*").matches("CR"));
+        assert!(Glob::new("CR*").matches("CR12"));
+        assert!(Glob::new("*_EN").matches("CH1_EN"));
+        assert!(!Glob::new("*_EN").matches("CH1_ENR"));
+        assert!(Glob::new("C?R").matches("CCR"));
+        assert!(!Glob::new("C?R").matches("CR"));
+        assert!(Glob::new("*C*R*").matches("XCCYRZ"));
+    }
+
+    #[test]
+    fn alternatives() {
+        let glob = Glob::new("CR1,CR2,SR*");
+        assert!(glob.matches("CR1"));
+        assert!(glob.matches("CR2"));
+        assert!(glob.matches("SR"));
+        assert!(!glob.matches("CR3"));
+    }
+}
@@ -1,6 +1,9 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Access {
     #[serde(rename = "rw")]
     ReadWrite,
@@ -11,18 +14,64 @@ pub enum Access {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Array {
     pub len: u64,
     pub stride: u64,
 }
 
+/// Where an item comes from, filled in by the frontends and extended by the
+/// patches applied to it.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Source {
+    /// Frontend which produced the item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontend: Option<String>,
+    /// File the item was read from, relative to the frontend input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// JSON pointer to the item in the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+    /// Patches applied to the item, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, &self.frontend) {
+            (Some(file), frontend) => {
+                write!(f, "{file}")?;
+                if let Some(pointer) = &self.pointer {
+                    write!(f, "#{pointer}")?;
+                }
+                if let Some(frontend) = frontend {
+                    write!(f, " ({frontend})")?;
+                }
+            }
+            (None, Some(frontend)) => write!(f, "{frontend}")?,
+            (None, None) => write!(f, "unknown")?,
+        }
+
+        if !self.patches.is_empty() {
+            write!(f, ", patched by {}", self.patches.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MultiChip {
     pub chips: Vec<Chip>,
     pub modules: Vec<Module>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Chip {
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -33,25 +82,36 @@ pub struct Chip {
     pub stm32_ext: Option<chip::Stm32Ext>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cm_ext: Option<chip::CmExt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub riscv_ext: Option<chip::RiscvExt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
 }
 
 pub mod chip {
     use super::*;
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub struct Stm32Ext {
         pub cm_name: String,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub struct CmExt {
-        cm_regs: Vec<cm_ext::CmReg>,
+        pub cm_regs: Vec<cm_ext::CmReg>,
+        /// Whether the core maps the SRAM and peripheral regions to bit-band
+        /// aliases, allowing single bits to be written atomically
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub bit_band: bool,
     }
 
     pub mod cm_ext {
         use super::*;
 
         #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
         pub struct CmReg {
             pub name: String,
             #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -64,6 +124,31 @@ pub mod chip {
     }
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub struct RiscvExt {
+        pub csrs: Vec<riscv_ext::Csr>,
+    }
+
+    pub mod riscv_ext {
+        use super::*;
+
+        /// A control and status register, laid out by a bitfield of a module.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+        pub struct Csr {
+            pub name: String,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub description: Option<String>,
+            pub module: String,
+            /// 12 bit address of the register in the CSR space
+            pub number: u16,
+            pub access: Access,
+            pub bitfield_name: String,
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub struct Peripheral {
         pub name: String,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -71,9 +156,12 @@ pub mod chip {
         pub module: String,
         pub address: u64,
         pub block_name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<Source>,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub struct Import {
         pub name: String,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -82,6 +170,7 @@ pub mod chip {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Module {
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -91,20 +180,46 @@ pub struct Module {
     pub blocks: Vec<Block>,
     pub bitfields: Vec<Bitfield>,
     pub enums: Vec<Enum>,
+    /// Items the frontend could not represent and left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<module::Skipped>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
+
+pub mod module {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub struct Skipped {
+        /// Block or bitfield containing the item
+        pub parent: String,
+        pub name: String,
+        pub reason: String,
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Block {
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub fields: Vec<block::Field>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
 }
 
 pub mod block {
     use super::*;
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(
+        feature = "schema",
+        derive(schemars::JsonSchema),
+        schemars(rename = "BlockField")
+    )]
     pub struct Field {
         pub name: String,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -112,11 +227,18 @@ pub mod block {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub array: Option<Array>,
         pub byte_offset: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<Source>,
         #[serde(flatten)]
         pub inner: FieldInner,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(
+        feature = "schema",
+        derive(schemars::JsonSchema),
+        schemars(rename = "BlockFieldInner")
+    )]
     #[serde(untagged)]
     pub enum FieldInner {
         Block(field::Block),
@@ -128,17 +250,32 @@ pub mod block {
         use super::*;
 
         #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[cfg_attr(
+            feature = "schema",
+            derive(schemars::JsonSchema),
+            schemars(rename = "BlockFieldBlock")
+        )]
         pub struct Block {
             pub block_name: String,
         }
 
         #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[cfg_attr(
+            feature = "schema",
+            derive(schemars::JsonSchema),
+            schemars(rename = "BlockFieldBitfield")
+        )]
         pub struct Bitfield {
             pub access: Access,
             pub bitfield_name: String,
         }
 
         #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[cfg_attr(
+            feature = "schema",
+            derive(schemars::JsonSchema),
+            schemars(rename = "BlockFieldSimple")
+        )]
         pub struct Simple {
             pub access: Access,
             pub bit_size: u32,
@@ -149,6 +286,7 @@ pub mod block {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Bitfield {
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -157,12 +295,19 @@ pub struct Bitfield {
     #[serde(default)]
     pub default: u64,
     pub fields: Vec<bitfield::Field>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
 }
 
 pub mod bitfield {
     use super::*;
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(
+        feature = "schema",
+        derive(schemars::JsonSchema),
+        schemars(rename = "BitfieldField")
+    )]
     pub struct Field {
         pub name: String,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -173,22 +318,28 @@ pub mod bitfield {
         pub array: Option<Array>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub enum_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<Source>,
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Enum {
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub bit_size: u32,
     pub variants: Vec<enum_name::Variant>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
 }
 
 pub mod enum_name {
     use super::*;
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub struct Variant {
         pub name: String,
         #[serde(default, skip_serializing_if = "Option::is_none")]
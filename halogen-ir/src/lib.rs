@@ -1,5 +1,22 @@
 mod utils;
 
+pub mod arrays;
+pub mod builder;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+pub mod diff;
+pub mod format;
+pub mod glob;
 pub mod ir;
 #[cfg(feature = "load")]
 pub mod load;
+pub mod merge;
+#[cfg(feature = "patch")]
+pub mod patch;
+#[cfg(feature = "load")]
+pub mod repository;
+pub mod resolve;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod stats;
+pub mod visit;
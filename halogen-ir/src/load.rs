@@ -1,136 +1,830 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::{fmt, fs, io};
 
 use heck::ToSnakeCase as _;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+use crate::format::{self, FORMAT_VERSION, Versioned};
 use crate::ir::MultiChip;
 use crate::utils::rayon_prelude::*;
-use crate::{ir, utils};
+use crate::{ir, schema, utils};
 
-fn load_json_file<T: for<'a> Deserialize<'a>>(path: impl AsRef<Path>) -> io::Result<T> {
-    let data = fs::read(path)?;
-    Ok(serde_json::from_slice(&data)?)
+/// A file not matching the IR schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidIr {
+    pub path: Option<PathBuf>,
+    pub errors: Vec<schema::Error>,
 }
 
-fn load_json_reader<T: for<'a> Deserialize<'a>>(reader: impl io::Read) -> io::Result<T> {
-    Ok(serde_json::from_reader(reader)?)
+impl fmt::Display for InvalidIr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "invalid IR")?;
+
+        const SHOWN: usize = 20;
+        for error in self.errors.iter().take(SHOWN) {
+            write!(f, "\n  at {error}")?;
+        }
+        if self.errors.len() > SHOWN {
+            write!(f, "\n  and {} more", self.errors.len() - SHOWN)?;
+        }
+
+        Ok(())
+    }
 }
 
-fn dump_json_file(path: impl AsRef<Path>, value: &impl Serialize) -> io::Result<()> {
-    let data = serde_json::to_vec_pretty(value)?;
-    fs::write(path, &data)?;
-    Ok(())
+impl std::error::Error for InvalidIr {}
+
+/// A file which could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub path: Option<PathBuf>,
+    /// Line and column of the error, starting from 1
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(path: Option<&Path>, location: Option<(usize, usize)>, message: String) -> Self {
+        // Both serde_json and serde_norway put the location in the message
+        let message = match location {
+            Some((line, column)) => message
+                .strip_suffix(&format!(" at line {line} column {column}"))
+                .map_or(message.clone(), str::to_string),
+            None => message,
+        };
+
+        Self {
+            path: path.map(Path::to_path_buf),
+            location,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some((line, column)) = self.location {
+            write!(f, "{line}:{column}:")?;
+        }
+        if self.path.is_some() || self.location.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Syntax of an IR file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    /// Easier to write by hand
+    Yaml,
+}
+
+impl Format {
+    /// `.yaml` and `.yml` files are YAML, everything else is JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path.as_ref().extension().and_then(OsStr::to_str);
+        match extension {
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    fn parse<T: for<'a> Deserialize<'a>>(self, data: &[u8], path: Option<&Path>) -> io::Result<T> {
+        let err = match self {
+            Format::Json => match serde_json::from_slice(data) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let location = (err.line() != 0).then(|| (err.line(), err.column()));
+                    ParseError::new(path, location, err.to_string())
+                }
+            },
+            Format::Yaml => match serde_norway::from_slice(data) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let location = err
+                        .location()
+                        .map(|location| (location.line(), location.column()));
+                    ParseError::new(path, location, err.to_string())
+                }
+            },
+        };
+
+        Err(io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn to_writer(self, writer: impl io::Write, value: &impl Serialize) -> io::Result<()> {
+        match self {
+            Format::Json => serde_json::to_writer_pretty(writer, value)?,
+            Format::Yaml => serde_norway::to_writer(writer, value)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Read a file, naming it in errors.
+pub(crate) fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))
+}
+
+/// A top-level file, tagged with the version of its layout.
+pub(crate) trait TopLevel {
+    fn format_version(&self) -> u32;
+}
+
+impl<T> TopLevel for Versioned<T> {
+    fn format_version(&self) -> u32 {
+        self.format_version
+    }
+}
+
+impl TopLevel for Index {
+    fn format_version(&self) -> u32 {
+        match self {
+            Index::Index { format_version, .. } => *format_version,
+            Index::MultiChip(ir) => ir.format_version,
+        }
+    }
 }
 
-fn dump_json_writer(writer: impl io::Write, value: &impl Serialize) -> io::Result<()> {
-    Ok(serde_json::to_writer_pretty(writer, value)?)
+pub(crate) fn load_file<T: for<'a> Deserialize<'a> + JsonSchema>(
+    path: impl AsRef<Path>,
+    format_version: u32,
+    migrate: fn(&mut Value, u32),
+) -> io::Result<T> {
+    let path = path.as_ref();
+    let data = read_file(path)?;
+    load_slice(
+        &data,
+        Format::from_path(path),
+        Some(path),
+        format_version,
+        migrate,
+    )
+}
+
+/// Load a top-level file, upgrading it if needed. Returns the version it was
+/// in, which also applies to the files it points to.
+pub(crate) fn load_versioned<T: for<'a> Deserialize<'a> + JsonSchema + TopLevel>(
+    data: &[u8],
+    format: Format,
+    path: Option<&Path>,
+    migrate: fn(&mut Value, u32),
+) -> io::Result<(u32, T)> {
+    // Files in the current version deserialize in a single pass, older ones
+    // (or broken ones) go through a `Value` to be upgraded (or diagnosed)
+    if let Ok(value) = format.parse::<T>(data, path)
+        && value.format_version() == FORMAT_VERSION
+    {
+        return Ok((FORMAT_VERSION, value));
+    }
+
+    let mut value: Value = format.parse(data, path)?;
+    let format_version = match value.get("format_version") {
+        Some(format_version) => u32::deserialize(format_version)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        None => 0,
+    };
+    format::check_version(format_version)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    if format_version != FORMAT_VERSION {
+        migrate(&mut value, format_version);
+    }
+
+    let item = T::deserialize(&value).map_err(|err| invalid::<T>(err.into(), &value, path))?;
+    Ok((format_version, item))
+}
+
+fn load_slice<T: for<'a> Deserialize<'a> + JsonSchema>(
+    data: &[u8],
+    format: Format,
+    path: Option<&Path>,
+    format_version: u32,
+    migrate: fn(&mut Value, u32),
+) -> io::Result<T> {
+    if format_version == FORMAT_VERSION {
+        let err = match format.parse(data, path) {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+
+        // Syntax errors are reported as they are
+        let value = format.parse(data, path)?;
+        return Err(invalid::<T>(err, &value, path));
+    }
+
+    let mut value = format.parse(data, path)?;
+    migrate(&mut value, format_version);
+    T::deserialize(&value).map_err(|err| invalid::<T>(err.into(), &value, path))
+}
+
+/// Validate a value which did not deserialize against the schema, to locate
+/// the problem.
+fn invalid<T: JsonSchema>(err: io::Error, value: &Value, path: Option<&Path>) -> io::Error {
+    let errors = schema::validate(&schema::schema::<T>(), value);
+    if errors.is_empty() {
+        return err;
+    }
+
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        InvalidIr {
+            path: path.map(Path::to_path_buf),
+            errors,
+        },
+    )
+}
+
+/// Upgrade the root file of an IR, the files it points to are upgraded
+/// separately.
+pub(crate) fn migrate_root(root: &mut Value, from: u32) {
+    if root.get("type").and_then(Value::as_str) != Some("index") {
+        format::migrate_multi_chip(root, from);
+    }
+}
+
+fn dump_file(path: impl AsRef<Path>, value: &impl Serialize) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut data = Vec::new();
+    Format::from_path(path).to_writer(&mut data, value)?;
+    fs::write(path, &data)?;
+    Ok(())
 }
 
 /// Load the IR from a reader.
-pub fn load_reader(reader: impl io::Read) -> io::Result<ir::MultiChip> {
-    load_json_reader(reader)
+pub fn load_reader(mut reader: impl io::Read) -> io::Result<ir::MultiChip> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let (_, ir) =
+        load_versioned::<Versioned<_>>(&data, Format::Json, None, format::migrate_multi_chip)?;
+    Ok(ir.inner)
 }
 
 /// Load the IR from a writer.
 pub fn dump_writer(writer: impl io::Write, ir: &ir::MultiChip) -> io::Result<()> {
-    dump_json_writer(writer, ir)
+    Format::Json.to_writer(writer, &Versioned::new(ir))
 }
 
-/// Load the IR from a single file.
+/// Load the IR from a single file, in the format given by its extension.
 pub fn load_single_file(path: impl AsRef<Path>) -> io::Result<ir::MultiChip> {
-    load_json_file(path)
+    let path = path.as_ref();
+    let data = read_file(path)?;
+
+    let (_, ir) = load_versioned::<Versioned<_>>(
+        &data,
+        Format::from_path(path),
+        Some(path),
+        format::migrate_multi_chip,
+    )?;
+    Ok(ir.inner)
 }
 
-/// Dump the IR to a single file.
+/// Dump the IR to a single file, in the format given by its extension.
 pub fn dump_single_file(path: impl AsRef<Path>, ir: &ir::MultiChip) -> io::Result<()> {
-    dump_json_file(path, ir)
+    dump_file(path, &Versioned::new(ir))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Root file of a multi-file IR, or a whole single-file IR.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
-enum Index {
+pub(crate) enum Index {
     #[serde(rename = "index")]
     Index {
-        chips: Vec<PathBuf>,
-        modules: Vec<PathBuf>,
+        #[serde(default)]
+        format_version: u32,
+        chips: Vec<IndexEntry>,
+        modules: Vec<IndexEntry>,
     },
     #[serde(untagged)]
-    MultiChip(MultiChip),
+    MultiChip(Versioned<MultiChip>),
 }
 
-/// Load the IR from multiple files, the path points to the index
+/// A file of a multi-file IR. Naming what the file contains lets
+/// [`IrRepository`](crate::repository::IrRepository) find it without
+/// parsing it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum IndexEntry {
+    Path(PathBuf),
+    Named {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+        path: PathBuf,
+    },
+}
+
+impl IndexEntry {
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            IndexEntry::Path(path) => path,
+            IndexEntry::Named { path, .. } => path,
+        }
+    }
+}
+
+/// Load the IR from multiple files, the path points to the index. Every file
+/// can be in any format, as given by its extension.
 pub fn load_multi_file(path: impl AsRef<Path>) -> io::Result<ir::MultiChip> {
     let path = path.as_ref();
-    let index = load_json_file::<Index>(&path)?;
+    let data = read_file(path)?;
+    let (format_version, index) =
+        load_versioned::<Index>(&data, Format::from_path(path), Some(path), migrate_root)?;
 
     // Maybe we loaded a single-file IR, just return that
     let (chips, modules) = match index {
-        Index::Index { chips, modules } => (chips, modules),
-        Index::MultiChip(ir) => return Ok(ir),
+        Index::Index { chips, modules, .. } => (chips, modules),
+        Index::MultiChip(ir) => return Ok(ir.inner),
     };
 
     let root = path
         .parent()
         .ok_or_else(|| io::Error::other("missing path parent"))?;
 
-    let (chips, modules) = utils::maybe_par_join(
+    let (chips, modules) = load_files(root, &chips, &modules, format_version);
+    let chips = chips?;
+    let modules = modules?;
+
+    Ok(ir::MultiChip { chips, modules })
+}
+
+fn load_files(
+    root: &Path,
+    chips: &[IndexEntry],
+    modules: &[IndexEntry],
+    format_version: u32,
+) -> (io::Result<Vec<ir::Chip>>, io::Result<Vec<ir::Module>>) {
+    utils::maybe_par_join(
         || {
             utils::into_maybe_par_iter(chips)
-                .map(|path| root.join(path))
-                .map(load_json_file)
+                .map(|entry| {
+                    load_file(
+                        root.join(entry.path()),
+                        format_version,
+                        format::migrate_chip,
+                    )
+                })
                 .collect::<io::Result<Vec<_>>>()
         },
         || {
             utils::into_maybe_par_iter(modules)
-                .map(|path| root.join(path))
-                .map(load_json_file)
+                .map(|entry| {
+                    load_file(
+                        root.join(entry.path()),
+                        format_version,
+                        format::migrate_module,
+                    )
+                })
                 .collect::<io::Result<Vec<_>>>()
         },
-    );
+    )
+}
 
-    let chips = chips?;
-    let modules = modules?;
+/// Rewrite an IR in the current format, in place. The path points to the
+/// index or to a single-file IR. Returns the version it was in.
+pub fn upgrade_file(path: impl AsRef<Path>) -> io::Result<u32> {
+    let path = path.as_ref();
+    let data = read_file(path)?;
+    let (format_version, index) =
+        load_versioned::<Index>(&data, Format::from_path(path), Some(path), migrate_root)?;
 
-    Ok(ir::MultiChip { chips, modules })
+    if format_version == FORMAT_VERSION {
+        return Ok(format_version);
+    }
+
+    let (chips, modules) = match index {
+        Index::Index { chips, modules, .. } => (chips, modules),
+        Index::MultiChip(ir) => {
+            dump_file(path, &Versioned::new(ir.inner))?;
+            return Ok(format_version);
+        }
+    };
+
+    let root = path
+        .parent()
+        .ok_or_else(|| io::Error::other("missing path parent"))?;
+
+    // Load everything first, to leave the files untouched on errors
+    let (chip_items, module_items) = load_files(root, &chips, &modules, format_version);
+    let chip_items = chip_items?;
+    let module_items = module_items?;
+
+    for (entry, chip) in chips.iter().zip(&chip_items) {
+        dump_file(root.join(entry.path()), chip)?;
+    }
+    for (entry, module) in modules.iter().zip(&module_items) {
+        dump_file(root.join(entry.path()), module)?;
+    }
+
+    // Name every entry while at it
+    let chips = chips
+        .iter()
+        .zip(&chip_items)
+        .map(|(entry, chip)| chip_entry(chip, entry.path().to_path_buf()))
+        .collect();
+    let modules = modules
+        .iter()
+        .zip(&module_items)
+        .map(|(entry, module)| module_entry(module, entry.path().to_path_buf()))
+        .collect();
+
+    let index = Index::Index {
+        format_version: FORMAT_VERSION,
+        chips,
+        modules,
+    };
+    dump_file(path, &index)?;
+
+    Ok(format_version)
 }
 
-/// Dump the IR to multiple files, the path points to the root directory.
+/// Dump the IR to multiple JSON files, the path points to the root directory.
 pub fn dump_multi_file(root: impl AsRef<Path>, ir: &ir::MultiChip) -> io::Result<()> {
+    dump_multi_file_as(root, ir, Format::Json)
+}
+
+/// Dump the IR to multiple files in the given format, the path points to the
+/// root directory.
+///
+/// The output only depends on the IR: files are named after the chip or the
+/// module and its version, and the index is sorted by name then version.
+pub fn dump_multi_file_as(
+    root: impl AsRef<Path>,
+    ir: &ir::MultiChip,
+    format: Format,
+) -> io::Result<()> {
     let root = root.as_ref();
-    let chips_path = root.join("chips");
-    let modules_path = root.join("modules");
+    utils::create_dir_if_not_exist(&root.join("chips"))?;
+    utils::create_dir_if_not_exist(&root.join("modules"))?;
 
-    utils::create_dir_if_not_exist(&chips_path)?;
-    utils::create_dir_if_not_exist(&modules_path)?;
+    let chips = sorted(&ir.chips, |chip| (&chip.name, None));
+    let modules = sorted(&ir.modules, |module| {
+        (&module.name, module.version.as_ref())
+    });
 
-    let (chips, modules) = utils::maybe_par_join(
-        || {
-            utils::into_maybe_par_iter(&ir.chips)
-                .map(|chip| {
-                    let path = chips_path.join(format!("{}.json", chip.name.to_snake_case()));
-                    dump_json_file(&path, chip)?;
+    let chip_paths = file_paths("chips", &chips, format, |chip| (&chip.name, None));
+    let module_paths = file_paths("modules", &modules, format, |module| {
+        (&module.name, module.version.as_ref())
+    });
 
-                    Ok(path)
-                })
-                .collect::<io::Result<Vec<_>>>()
+    let (chips_res, modules_res) = utils::maybe_par_join(
+        || {
+            utils::into_maybe_par_iter(chips.iter().zip(&chip_paths).collect::<Vec<_>>())
+                .map(|(chip, path)| dump_file(root.join(path), *chip))
+                .collect::<io::Result<()>>()
         },
         || {
-            utils::into_maybe_par_iter(&ir.modules)
-                .map(|module| {
-                    let path = modules_path.join(format!("{}.json", module.name.to_snake_case()));
-                    dump_json_file(&path, module)?;
-
-                    Ok(path)
-                })
-                .collect::<io::Result<Vec<_>>>()
+            utils::into_maybe_par_iter(modules.iter().zip(&module_paths).collect::<Vec<_>>())
+                .map(|(module, path)| dump_file(root.join(path), *module))
+                .collect::<io::Result<()>>()
         },
     );
 
-    let chips = chips?;
-    let modules = modules?;
+    chips_res?;
+    modules_res?;
 
-    dump_json_file(root.join("index.json"), &Index::Index { chips, modules })?;
+    let chips = chips
+        .iter()
+        .zip(chip_paths)
+        .map(|(chip, path)| chip_entry(chip, path))
+        .collect();
+    let modules = modules
+        .iter()
+        .zip(module_paths)
+        .map(|(module, path)| module_entry(module, path))
+        .collect();
+
+    let index = Index::Index {
+        format_version: FORMAT_VERSION,
+        chips,
+        modules,
+    };
+    dump_file(root.join(format!("index.{}", format.extension())), &index)?;
 
     Ok(())
 }
+
+/// Items sorted by name then version, keeping the original order for equal
+/// keys.
+fn sorted<'a, T>(
+    items: &'a [T],
+    key: impl Fn(&'a T) -> (&'a String, Option<&'a String>),
+) -> Vec<&'a T> {
+    let mut items = items.iter().collect::<Vec<_>>();
+    items.sort_by_key(|item| key(item));
+    items
+}
+
+/// Paths relative to the root for every item, unique even when names only
+/// differ by case or punctuation.
+fn file_paths<T>(
+    dir: &str,
+    items: &[&T],
+    format: Format,
+    key: impl Fn(&T) -> (&String, Option<&String>),
+) -> Vec<PathBuf> {
+    let mut used = HashSet::new();
+
+    items
+        .iter()
+        .map(|item| {
+            let stem = match key(item) {
+                (name, Some(version)) => format!("{name}_{version}").to_snake_case(),
+                (name, None) => name.to_snake_case(),
+            };
+
+            let mut unique = stem.clone();
+            for n in 2.. {
+                if used.insert(unique.clone()) {
+                    break;
+                }
+                unique = format!("{stem}_{n}");
+            }
+
+            Path::new(dir).join(format!("{unique}.{}", format.extension()))
+        })
+        .collect()
+}
+
+fn chip_entry(chip: &ir::Chip, path: PathBuf) -> IndexEntry {
+    IndexEntry::Named {
+        name: chip.name.clone(),
+        version: None,
+        path,
+    }
+}
+
+fn module_entry(module: &ir::Module, path: PathBuf) -> IndexEntry {
+    IndexEntry::Named {
+        name: module.name.clone(),
+        version: module.version.clone(),
+        path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("halogen-load-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn chip() -> Value {
+        json!({
+            "name": "F103",
+            "peripherals": [{ "name": "USART1", "module": "usart", "address": 0x4001_3800u32, "block_name": "Usart" }],
+            "imports": [{ "name": "usart" }],
+        })
+    }
+
+    fn module() -> Value {
+        json!({
+            "name": "usart",
+            "blocks": [{ "name": "Usart", "fields": [] }],
+            "bitfields": [],
+            "enums": [],
+        })
+    }
+
+    fn write(path: &Path, value: &Value) {
+        fs::write(path, serde_json::to_vec_pretty(value).unwrap()).unwrap();
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn single_file_v0() {
+        let root = temp_dir("single-v0");
+        let path = root.join("ir.json");
+        write(&path, &json!({ "chips": [chip()], "modules": [module()] }));
+
+        let ir = load_single_file(&path).unwrap();
+        assert_eq!(ir.chips[0].name, "F103");
+        assert_eq!(ir.modules[0].name, "usart");
+
+        assert_eq!(upgrade_file(&path).unwrap(), 0);
+        assert_eq!(read(&path)["format_version"], FORMAT_VERSION);
+        assert_eq!(load_single_file(&path).unwrap(), ir);
+    }
+
+    #[test]
+    fn multi_file_v0() {
+        let root = temp_dir("multi-v0");
+        fs::create_dir_all(root.join("chips")).unwrap();
+        fs::create_dir_all(root.join("modules")).unwrap();
+        write(&root.join("chips/f103.json"), &chip());
+        write(&root.join("modules/usart.json"), &module());
+        write(
+            &root.join("index.json"),
+            &json!({
+                "type": "index",
+                "chips": ["chips/f103.json"],
+                "modules": ["modules/usart.json"],
+            }),
+        );
+
+        let ir = load_multi_file(root.join("index.json")).unwrap();
+        assert_eq!(ir.chips[0].name, "F103");
+        assert_eq!(ir.modules[0].name, "usart");
+
+        assert_eq!(upgrade_file(root.join("index.json")).unwrap(), 0);
+        assert_eq!(
+            read(&root.join("index.json"))["format_version"],
+            FORMAT_VERSION
+        );
+        assert_eq!(load_multi_file(root.join("index.json")).unwrap(), ir);
+        assert_eq!(
+            upgrade_file(root.join("index.json")).unwrap(),
+            FORMAT_VERSION
+        );
+    }
+
+    #[test]
+    fn current_version_round_trip() {
+        let root = temp_dir("round-trip");
+        let path = root.join("ir.json");
+        let ir: MultiChip =
+            serde_json::from_value(json!({ "chips": [chip()], "modules": [module()] })).unwrap();
+
+        dump_single_file(&path, &ir).unwrap();
+        assert_eq!(read(&path)["format_version"], FORMAT_VERSION);
+        assert_eq!(load_single_file(&path).unwrap(), ir);
+
+        // A single-file IR is accepted in place of an index
+        assert_eq!(load_multi_file(&path).unwrap(), ir);
+    }
+
+    #[test]
+    fn newer_version() {
+        let data = json!({ "format_version": FORMAT_VERSION + 1, "chips": [], "modules": [] });
+        let err = load_reader(data.to_string().as_bytes()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "IR format version {} is newer than the supported {FORMAT_VERSION}",
+                FORMAT_VERSION + 1
+            )
+        );
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let root = temp_dir("yaml");
+        let ir: MultiChip =
+            serde_json::from_value(json!({ "chips": [chip()], "modules": [module()] })).unwrap();
+
+        let path = root.join("ir.yaml");
+        dump_single_file(&path, &ir).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("name: F103"));
+        assert_eq!(load_single_file(&path).unwrap(), ir);
+
+        dump_multi_file_as(&root, &ir, Format::Yaml).unwrap();
+        assert!(root.join("modules/usart.yaml").exists());
+        assert_eq!(load_multi_file(root.join("index.yaml")).unwrap(), ir);
+    }
+
+    #[test]
+    fn mixed_formats() {
+        let root = temp_dir("mixed");
+        fs::create_dir_all(root.join("chips")).unwrap();
+        fs::create_dir_all(root.join("modules")).unwrap();
+        write(&root.join("chips/f103.json"), &chip());
+        fs::write(
+            root.join("modules/usart.yml"),
+            serde_norway::to_string(&module()).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            root.join("index.yaml"),
+            "type: index\nchips: [chips/f103.json]\nmodules: [modules/usart.yml]\n",
+        )
+        .unwrap();
+
+        let ir = load_multi_file(root.join("index.yaml")).unwrap();
+        assert_eq!(ir.chips[0].name, "F103");
+        assert_eq!(ir.modules[0].name, "usart");
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let root = temp_dir("parse-error");
+
+        let path = root.join("ir.json");
+        fs::write(&path, "{\n  \"chips\": [,]\n}").unwrap();
+        let err = load_single_file(&path).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<ParseError>().unwrap();
+        assert_eq!(err.path.as_deref(), Some(path.as_path()));
+        assert_eq!(err.location, Some((2, 13)));
+        assert!(!err.message.contains("line"));
+
+        let path = root.join("ir.yaml");
+        fs::write(&path, "chips: []\nmodules: [\n").unwrap();
+        let err = load_single_file(&path).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<ParseError>().unwrap();
+        assert_eq!(err.path.as_deref(), Some(path.as_path()));
+        assert_eq!(err.location.map(|(line, _)| line), Some(3));
+        assert!(
+            err.to_string()
+                .starts_with(&format!("{}:3:", path.display()))
+        );
+    }
+
+    fn files(root: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        for dir in ["", "chips", "modules"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_file() {
+                    let data = fs::read(&path).unwrap();
+                    files.push((path.strip_prefix(root).unwrap().to_path_buf(), data));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn deterministic_dump() {
+        let root = temp_dir("deterministic");
+        let module = |name: &str, version: Option<&str>| {
+            let mut module = module();
+            module["name"] = json!(name);
+            module["version"] = json!(version);
+            module
+        };
+        let mut ir: MultiChip = serde_json::from_value(json!({
+            "chips": [chip()],
+            "modules": [
+                module("usart", Some("v4")),
+                module("usart", Some("v3")),
+                module("Usart", Some("v3")),
+                module("usart_v3", None),
+            ],
+        }))
+        .unwrap();
+
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        dump_multi_file(root.join("a"), &ir).unwrap();
+        ir.modules.reverse();
+        dump_multi_file(root.join("b"), &ir).unwrap();
+
+        let files = files(&root.join("a"));
+        assert_eq!(files, self::files(&root.join("b")));
+
+        let names = files
+            .iter()
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "chips/f103.json",
+                "index.json",
+                "modules/usart_v3.json",
+                "modules/usart_v3_2.json",
+                "modules/usart_v3_3.json",
+                "modules/usart_v4.json",
+            ]
+        );
+
+        let loaded = load_multi_file(root.join("a/index.json")).unwrap();
+        assert_eq!(loaded.modules.len(), 4);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::diff::{self, Change};
+use crate::ir::*;
+use crate::visit::{self, Path, Segment, VisitorMut};
+
+/// What to do when two IRs define the same chip or module differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    /// Fail the merge
+    #[default]
+    Error,
+    /// Keep the definition coming first
+    PreferLeft,
+    /// Keep the definition coming last
+    PreferRight,
+}
+
+/// A chip or module defined differently by two IRs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: Path,
+    /// What the right definition changes compared to the left one, empty if
+    /// they only differ in descriptions
+    pub changes: Vec<Change>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} has conflicting definitions", self.path)?;
+        if self.changes.is_empty() {
+            write!(f, " (descriptions only)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} conflicting definitions", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, ", {}", conflict.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    pub ir: MultiChip,
+    /// Identical definitions found more than once, only one copy is kept
+    pub duplicates: Vec<Path>,
+    /// Conflicts resolved according to the policy
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Union the chips and modules of several IRs, in order. Chips are matched
+/// by name and modules by name and version, each compared as a whole but for
+/// the sources of their items.
+pub fn merge(irs: impl IntoIterator<Item = MultiChip>, policy: Policy) -> Result<Merged, Error> {
+    let mut chips = Table::default();
+    let mut modules = Table::default();
+    let mut duplicates = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for ir in irs {
+        for chip in ir.chips {
+            let path = Path::new().join(Segment::Chip(chip.name.clone()));
+
+            match chips.insert(chip.name.clone(), chip, chip_layout) {
+                Insert::New => {}
+                Insert::Duplicate => duplicates.push(path),
+                Insert::Conflict(existing, chip) => {
+                    let changes = diff::diff(&chips_ir(existing), &chips_ir(&chip));
+                    conflicts.push(Conflict { path, changes });
+                    if policy == Policy::PreferRight {
+                        *existing = chip;
+                    }
+                }
+            }
+        }
+
+        for module in ir.modules {
+            let key = (module.name.clone(), module.version.clone());
+            let path = Path::new().join(Segment::Module {
+                name: module.name.clone(),
+                version: module.version.clone(),
+            });
+
+            match modules.insert(key, module, module_layout) {
+                Insert::New => {}
+                Insert::Duplicate => duplicates.push(path),
+                Insert::Conflict(existing, module) => {
+                    let changes = diff::diff(&modules_ir(existing), &modules_ir(&module));
+                    conflicts.push(Conflict { path, changes });
+                    if policy == Policy::PreferRight {
+                        *existing = module;
+                    }
+                }
+            }
+        }
+    }
+
+    if policy == Policy::Error && !conflicts.is_empty() {
+        return Err(Error { conflicts });
+    }
+
+    Ok(Merged {
+        ir: MultiChip {
+            chips: chips.items,
+            modules: modules.items,
+        },
+        duplicates,
+        conflicts,
+    })
+}
+
+/// Items unique by key, in insertion order.
+struct Table<K, T> {
+    indices: HashMap<K, usize>,
+    items: Vec<T>,
+}
+
+impl<K, T> Default for Table<K, T> {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+enum Insert<'a, T> {
+    New,
+    /// An identical item was already present
+    Duplicate,
+    /// A different item was already present, the new one is given back
+    Conflict(&'a mut T, T),
+}
+
+impl<K: Eq + Hash, T: PartialEq> Table<K, T> {
+    /// Items are compared by the part of them given by `layout`.
+    fn insert(&mut self, key: K, item: T, layout: fn(&T) -> T) -> Insert<'_, T> {
+        match self.indices.get(&key) {
+            Some(&index) if layout(&self.items[index]) == layout(&item) => Insert::Duplicate,
+            Some(&index) => Insert::Conflict(&mut self.items[index], item),
+            None => {
+                self.indices.insert(key, self.items.len());
+                self.items.push(item);
+                Insert::New
+            }
+        }
+    }
+}
+
+/// Everything but what is allowed to differ between duplicate definitions,
+/// the same item loaded from different files has different sources.
+fn chip_layout(chip: &Chip) -> Chip {
+    let mut chip = chip.clone();
+    StripSources.visit_chip_mut(&Path::new(), &mut chip);
+    chip
+}
+
+fn module_layout(module: &Module) -> Module {
+    let mut module = module.clone();
+    StripSources.visit_module_mut(&Path::new(), &mut module);
+    module
+}
+
+struct StripSources;
+
+impl VisitorMut for StripSources {
+    fn visit_chip_mut(&mut self, path: &Path, chip: &mut Chip) {
+        chip.source = None;
+        visit::walk_chip_mut(self, path, chip);
+    }
+
+    fn visit_peripheral_mut(&mut self, _path: &Path, peripheral: &mut chip::Peripheral) {
+        peripheral.source = None;
+    }
+
+    fn visit_module_mut(&mut self, path: &Path, module: &mut Module) {
+        module.source = None;
+        visit::walk_module_mut(self, path, module);
+    }
+
+    fn visit_block_mut(&mut self, path: &Path, block: &mut Block) {
+        block.source = None;
+        visit::walk_block_mut(self, path, block);
+    }
+
+    fn visit_block_field_mut(&mut self, _path: &Path, field: &mut block::Field) {
+        field.source = None;
+    }
+
+    fn visit_bitfield_mut(&mut self, path: &Path, bitfield: &mut Bitfield) {
+        bitfield.source = None;
+        visit::walk_bitfield_mut(self, path, bitfield);
+    }
+
+    fn visit_bitfield_field_mut(&mut self, _path: &Path, field: &mut bitfield::Field) {
+        field.source = None;
+    }
+
+    fn visit_enum_mut(&mut self, _path: &Path, enum_name: &mut Enum) {
+        enum_name.source = None;
+    }
+}
+
+fn chips_ir(chip: &Chip) -> MultiChip {
+    MultiChip {
+        chips: vec![chip.clone()],
+        modules: Vec::new(),
+    }
+}
+
+fn modules_ir(module: &Module) -> MultiChip {
+    MultiChip {
+        chips: Vec::new(),
+        modules: vec![module.clone()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn multi(chip: &str, dr_size: u32) -> MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": chip,
+                "peripherals": [{ "name": "USART1", "module": "uart", "address": 0x4001_3800u32, "block_name": "Uart" }],
+                "imports": [{ "name": "uart" }],
+            }],
+            "modules": [{
+                "name": "uart",
+                "blocks": [{
+                    "name": "Uart",
+                    "fields": [{ "name": "DR", "byte_offset": 4, "access": "rw", "bit_size": dr_size }],
+                }],
+                "bitfields": [],
+                "enums": [],
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn dr(multi: &MultiChip) -> &block::FieldInner {
+        &multi.modules[0].blocks[0].fields[0].inner
+    }
+
+    #[test]
+    fn union_and_duplicates() {
+        let merged = merge([multi("F103", 32), multi("F030", 32)], Policy::Error).unwrap();
+
+        let names = merged.ir.chips.iter().map(|chip| chip.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["F103", "F030"]);
+        assert_eq!(merged.ir.modules.len(), 1);
+        assert_eq!(merged.duplicates.len(), 1);
+        assert_eq!(merged.duplicates[0].to_string(), "module uart");
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn error_policy() {
+        let error = merge([multi("F103", 32), multi("F030", 16)], Policy::Error).unwrap_err();
+
+        assert_eq!(error.conflicts.len(), 1);
+        let conflict = &error.conflicts[0];
+        assert_eq!(conflict.path.to_string(), "module uart");
+        assert_eq!(conflict.changes.len(), 1);
+        assert_eq!(
+            conflict.changes[0].path.to_string(),
+            "module uart > block Uart > field DR"
+        );
+    }
+
+    #[test]
+    fn prefer_policies() {
+        let irs = || [multi("F103", 32), multi("F030", 16)];
+        let left = merge(irs(), Policy::PreferLeft).unwrap();
+        let right = merge(irs(), Policy::PreferRight).unwrap();
+
+        assert_eq!(dr(&left.ir), dr(&multi("F103", 32)));
+        assert_eq!(dr(&right.ir), dr(&multi("F030", 16)));
+        assert_eq!(left.conflicts.len(), 1);
+        assert_eq!(right.conflicts, left.conflicts);
+    }
+
+    #[test]
+    fn sources_are_ignored() {
+        let from = |file: &str| {
+            let mut ir = multi("F103", 32);
+            let source = Some(Source {
+                file: Some(file.into()),
+                ..Default::default()
+            });
+            ir.chips[0].source = source.clone();
+            ir.chips[0].peripherals[0].source = source.clone();
+            ir.modules[0].source = source.clone();
+            ir.modules[0].blocks[0].fields[0].source = source;
+            ir
+        };
+
+        let merged = merge([from("a.json"), from("b.json")], Policy::Error).unwrap();
+        assert_eq!(merged.duplicates.len(), 2);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.ir, from("a.json"));
+
+        let mut described = from("b.json");
+        described.modules[0].description = Some("UART".into());
+        let err = merge([from("a.json"), described], Policy::Error).unwrap_err();
+        assert_eq!(err.to_string(), "1 conflicting definitions, module uart");
+    }
+}
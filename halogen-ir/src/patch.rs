@@ -0,0 +1,1190 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::{fmt, fs, io};
+
+use serde::Deserialize;
+
+use crate::arrays::{self, Element};
+use crate::ir::*;
+use crate::visit::{Path, Segment};
+
+pub use crate::glob::Glob;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Location of the item the patch was being applied to
+    pub path: Path,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    NoMatch { glob: Glob },
+    Duplicate,
+    UnknownEnum { enum_name: String },
+    InvalidProperty { property: &'static str },
+    Array { reason: &'static str },
+}
+
+impl Error {
+    fn new(path: &Path, kind: ErrorKind) -> Self {
+        Self {
+            path: path.clone(),
+            kind,
+        }
+    }
+
+    fn no_match(path: &Path, glob: &Glob) -> Self {
+        Self::new(path, ErrorKind::NoMatch { glob: glob.clone() })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.segments().is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+
+        match &self.kind {
+            ErrorKind::NoMatch { glob } => write!(f, "selector {glob} does not match anything"),
+            ErrorKind::Duplicate => write!(f, "defined multiple times"),
+            ErrorKind::UnknownEnum { enum_name } => write!(f, "unknown enum {enum_name}"),
+            ErrorKind::InvalidProperty { property } => {
+                write!(f, "cannot set {property} on this kind of field")
+            }
+            ErrorKind::Array { reason } => write!(f, "cannot merge into an array, {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A set of fixes to apply to an IR, usually loaded from a YAML file:
+///
+/// ```yaml
+/// modules:
+///   - select: usart
+///     bitfields:
+///       - select: Cr1
+///         fields:
+///           - select: "M?"
+///             set: { description: Word length }
+///         arrays:
+///           - { select: "M?", name: M }
+/// chips:
+///   - select: "STM32F4*"
+///     delete_peripherals: [USART6]
+/// ```
+///
+/// Every selector must match at least one item, except for the top level
+/// chip and module ones, so the same patch can be applied to partial IRs.
+/// Modules are patched before chips, and renaming a block, bitfield, enum
+/// or module also updates everything referencing it.
+///
+/// When the patch has a name, it is recorded in the source of every item it
+/// selects or adds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Patch {
+    /// Set to the path of the file by [`load_file`]
+    #[serde(skip)]
+    pub name: Option<String>,
+    pub chips: Vec<ChipPatch>,
+    pub modules: Vec<ModulePatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChipPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: ChipSet,
+    #[serde(default)]
+    pub peripherals: Vec<PeripheralPatch>,
+    #[serde(default)]
+    pub add_peripherals: Vec<chip::Peripheral>,
+    #[serde(default)]
+    pub delete_peripherals: Vec<Glob>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChipSet {
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeripheralPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: PeripheralSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeripheralSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub address: Option<u64>,
+    pub block_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModulePatch {
+    pub select: Glob,
+    /// Only patch these versions of the module, a missing version matches
+    /// the empty string
+    #[serde(default)]
+    pub version: Option<Glob>,
+    #[serde(default)]
+    pub set: ModuleSet,
+    #[serde(default)]
+    pub blocks: Vec<BlockPatch>,
+    #[serde(default)]
+    pub bitfields: Vec<BitfieldPatch>,
+    #[serde(default)]
+    pub enums: Vec<EnumPatch>,
+    #[serde(default)]
+    pub add_blocks: Vec<Block>,
+    #[serde(default)]
+    pub add_bitfields: Vec<Bitfield>,
+    #[serde(default)]
+    pub add_enums: Vec<Enum>,
+    #[serde(default)]
+    pub delete_blocks: Vec<Glob>,
+    #[serde(default)]
+    pub delete_bitfields: Vec<Glob>,
+    #[serde(default)]
+    pub delete_enums: Vec<Glob>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModuleSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: BlockSet,
+    #[serde(default)]
+    pub fields: Vec<BlockFieldPatch>,
+    #[serde(default)]
+    pub add_fields: Vec<block::Field>,
+    #[serde(default)]
+    pub delete_fields: Vec<Glob>,
+    #[serde(default)]
+    pub arrays: Vec<ArrayPatch>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockFieldPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: BlockFieldSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockFieldSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub byte_offset: Option<u64>,
+    /// Only for register fields
+    pub access: Option<Access>,
+    /// Only for simple register fields
+    pub bit_size: Option<u32>,
+    /// Only for simple register fields
+    pub enum_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitfieldPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: BitfieldSet,
+    #[serde(default)]
+    pub fields: Vec<BitfieldFieldPatch>,
+    #[serde(default)]
+    pub add_fields: Vec<bitfield::Field>,
+    #[serde(default)]
+    pub delete_fields: Vec<Glob>,
+    #[serde(default)]
+    pub arrays: Vec<ArrayPatch>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitfieldSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub bit_size: Option<u32>,
+    pub default: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitfieldFieldPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: BitfieldFieldSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitfieldFieldSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub bit_offset: Option<u32>,
+    pub bit_size: Option<u32>,
+    pub enum_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: EnumSet,
+    #[serde(default)]
+    pub variants: Vec<VariantPatch>,
+    #[serde(default)]
+    pub add_variants: Vec<enum_name::Variant>,
+    #[serde(default)]
+    pub delete_variants: Vec<Glob>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnumSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub bit_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantPatch {
+    pub select: Glob,
+    #[serde(default)]
+    pub set: VariantSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariantSet {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub value: Option<u64>,
+}
+
+/// Merge evenly spaced fields with the same layout into a single array.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArrayPatch {
+    pub select: Glob,
+    pub name: String,
+    /// Defaults to the description of the first field
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Load a patch file, `.json` files are parsed as JSON, everything else as YAML.
+pub fn load_file(path: impl AsRef<std::path::Path>) -> io::Result<Patch> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path)?;
+
+    let mut patch: Patch = if path.extension() == Some(OsStr::new("json")) {
+        serde_json::from_str(&data)?
+    } else {
+        serde_norway::from_str(&data).map_err(io::Error::other)?
+    };
+    patch.name = Some(path.display().to_string());
+
+    Ok(patch)
+}
+
+impl Patch {
+    pub fn apply(&self, multi: &mut MultiChip) -> Result<()> {
+        let root = Path::new();
+        let name = self.name.as_deref();
+
+        for patch in &self.modules {
+            for module in &mut multi.modules {
+                if patch.matches(module) {
+                    patch.apply(&root, name, module, &mut multi.chips)?;
+                }
+            }
+        }
+
+        let mut modules = HashSet::new();
+        for module in &multi.modules {
+            if !modules.insert((&module.name, &module.version)) {
+                let path = root.join(Segment::Module {
+                    name: module.name.clone(),
+                    version: module.version.clone(),
+                });
+                return Err(Error::new(&path, ErrorKind::Duplicate));
+            }
+        }
+
+        for patch in &self.chips {
+            for chip in &mut multi.chips {
+                if patch.select.matches(&chip.name) {
+                    patch.apply(&root.join(chip.segment()), name, chip)?;
+                }
+            }
+        }
+
+        check_unique(&root, &multi.chips)
+    }
+}
+
+impl ChipPatch {
+    fn apply(&self, path: &Path, name: Option<&str>, chip: &mut Chip) -> Result<()> {
+        delete(path, &mut chip.peripherals, &self.delete_peripherals)?;
+        chip.peripherals
+            .extend(added(&self.add_peripherals, name, |peripheral| {
+                &mut peripheral.source
+            }));
+
+        for patch in &self.peripherals {
+            for peripheral in select(path, &mut chip.peripherals, &patch.select)? {
+                mark(&mut peripheral.source, name);
+                let set = &patch.set;
+                set_opt(&mut peripheral.name, &set.name);
+                set_desc(&mut peripheral.description, &set.description);
+                set_opt(&mut peripheral.address, &set.address);
+                set_opt(&mut peripheral.block_name, &set.block_name);
+            }
+        }
+
+        check_unique(path, &chip.peripherals)?;
+        set_desc(&mut chip.description, &self.set.description);
+        mark(&mut chip.source, name);
+
+        Ok(())
+    }
+}
+
+/// Items of a module renamed by a patch, from their name before the patch
+/// to the final one.
+#[derive(Default)]
+struct Renames {
+    blocks: HashMap<String, String>,
+    bitfields: HashMap<String, String>,
+    enums: HashMap<String, String>,
+}
+
+impl Renames {
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.bitfields.is_empty() && self.enums.is_empty()
+    }
+}
+
+impl ModulePatch {
+    fn matches(&self, module: &Module) -> bool {
+        self.select.matches(&module.name)
+            && self.version.as_ref().is_none_or(|version| {
+                version.matches(module.version.as_deref().unwrap_or_default())
+            })
+    }
+
+    fn apply(
+        &self,
+        path: &Path,
+        name: Option<&str>,
+        module: &mut Module,
+        chips: &mut [Chip],
+    ) -> Result<()> {
+        let path = &path.join(module.segment());
+
+        delete(path, &mut module.blocks, &self.delete_blocks)?;
+        delete(path, &mut module.bitfields, &self.delete_bitfields)?;
+        delete(path, &mut module.enums, &self.delete_enums)?;
+        module
+            .blocks
+            .extend(added(&self.add_blocks, name, |block| &mut block.source));
+        module
+            .bitfields
+            .extend(added(&self.add_bitfields, name, |bitfield| {
+                &mut bitfield.source
+            }));
+        module
+            .enums
+            .extend(added(&self.add_enums, name, |enum_name| {
+                &mut enum_name.source
+            }));
+
+        // Patches never reorder items, so names can be matched by position
+        // once every patch is applied. References are then updated in one
+        // pass, keyed on the original names, which keeps swaps (A -> B and
+        // B -> A) and chains (A -> B and B -> C) correct.
+        let old_enums = names(&module.enums);
+        let old_bitfields = names(&module.bitfields);
+        let old_blocks = names(&module.blocks);
+
+        for patch in &self.enums {
+            for enum_name in select(path, &mut module.enums, &patch.select)? {
+                patch.apply(&path.join(enum_name.segment()), name, enum_name)?;
+            }
+        }
+        check_unique(path, &module.enums)?;
+
+        for patch in &self.bitfields {
+            for bitfield in select(path, &mut module.bitfields, &patch.select)? {
+                patch.apply(
+                    &path.join(bitfield.segment()),
+                    name,
+                    bitfield,
+                    &module.enums,
+                )?;
+            }
+        }
+        check_unique(path, &module.bitfields)?;
+
+        for patch in &self.blocks {
+            for block in select(path, &mut module.blocks, &patch.select)? {
+                patch.apply(&path.join(block.segment()), name, block, &module.enums)?;
+            }
+        }
+        check_unique(path, &module.blocks)?;
+
+        let renames = Renames {
+            blocks: renamed(old_blocks, &module.blocks),
+            bitfields: renamed(old_bitfields, &module.bitfields),
+            enums: renamed(old_enums, &module.enums),
+        };
+
+        if !renames.is_empty() {
+            rename_in_module(module, &renames);
+            for chip in chips.iter_mut().filter(|chip| imports(chip, module)) {
+                rename_in_chip(chip, &module.name, &renames);
+            }
+        }
+
+        if let Some(new_name) = &self.set.name {
+            for chip in chips.iter_mut().filter(|chip| imports(chip, module)) {
+                rename_module_in_chip(chip, &module.name, new_name);
+            }
+            module.name = new_name.clone();
+        }
+        set_desc(&mut module.description, &self.set.description);
+        mark(&mut module.source, name);
+
+        Ok(())
+    }
+}
+
+impl BlockPatch {
+    fn apply(
+        &self,
+        path: &Path,
+        name: Option<&str>,
+        block: &mut Block,
+        enums: &[Enum],
+    ) -> Result<()> {
+        delete(path, &mut block.fields, &self.delete_fields)?;
+        block
+            .fields
+            .extend(added(&self.add_fields, name, |field| &mut field.source));
+
+        for patch in &self.fields {
+            for field in select(path, &mut block.fields, &patch.select)? {
+                mark(&mut field.source, name);
+                patch.apply(&path.join(field.segment()), field, enums)?;
+            }
+        }
+
+        for patch in &self.arrays {
+            merge_array(path, name, &mut block.fields, patch)?;
+        }
+
+        check_unique(path, &block.fields)?;
+        set_opt(&mut block.name, &self.set.name);
+        set_desc(&mut block.description, &self.set.description);
+        mark(&mut block.source, name);
+
+        Ok(())
+    }
+}
+
+impl BlockFieldPatch {
+    fn apply(&self, path: &Path, field: &mut block::Field, enums: &[Enum]) -> Result<()> {
+        let set = &self.set;
+        let invalid = |property| Error::new(path, ErrorKind::InvalidProperty { property });
+
+        set_opt(&mut field.name, &set.name);
+        set_desc(&mut field.description, &set.description);
+        set_opt(&mut field.byte_offset, &set.byte_offset);
+
+        if let Some(access) = &set.access {
+            match &mut field.inner {
+                block::FieldInner::Bitfield(inner) => inner.access = access.clone(),
+                block::FieldInner::Simple(inner) => inner.access = access.clone(),
+                block::FieldInner::Block(_) => return Err(invalid("access")),
+            }
+        }
+
+        if set.bit_size.is_some() || set.enum_name.is_some() {
+            let block::FieldInner::Simple(inner) = &mut field.inner else {
+                let property = match set.bit_size {
+                    Some(_) => "bit_size",
+                    None => "enum_name",
+                };
+                return Err(invalid(property));
+            };
+
+            set_opt(&mut inner.bit_size, &set.bit_size);
+            if let Some(name) = &set.enum_name {
+                check_enum(path, enums, name)?;
+                inner.enum_name = Some(name.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BitfieldPatch {
+    fn apply(
+        &self,
+        path: &Path,
+        name: Option<&str>,
+        bitfield: &mut Bitfield,
+        enums: &[Enum],
+    ) -> Result<()> {
+        delete(path, &mut bitfield.fields, &self.delete_fields)?;
+        bitfield
+            .fields
+            .extend(added(&self.add_fields, name, |field| &mut field.source));
+
+        for patch in &self.fields {
+            for field in select(path, &mut bitfield.fields, &patch.select)? {
+                mark(&mut field.source, name);
+                let set = &patch.set;
+                set_opt(&mut field.name, &set.name);
+                set_desc(&mut field.description, &set.description);
+                set_opt(&mut field.bit_offset, &set.bit_offset);
+                set_opt(&mut field.bit_size, &set.bit_size);
+
+                if let Some(name) = &set.enum_name {
+                    check_enum(&path.join(field.segment()), enums, name)?;
+                    field.enum_name = Some(name.clone());
+                }
+            }
+        }
+
+        for patch in &self.arrays {
+            merge_array(path, name, &mut bitfield.fields, patch)?;
+        }
+
+        check_unique(path, &bitfield.fields)?;
+        set_opt(&mut bitfield.name, &self.set.name);
+        set_desc(&mut bitfield.description, &self.set.description);
+        set_opt(&mut bitfield.bit_size, &self.set.bit_size);
+        set_opt(&mut bitfield.default, &self.set.default);
+        mark(&mut bitfield.source, name);
+
+        Ok(())
+    }
+}
+
+impl EnumPatch {
+    fn apply(&self, path: &Path, name: Option<&str>, enum_name: &mut Enum) -> Result<()> {
+        delete(path, &mut enum_name.variants, &self.delete_variants)?;
+        enum_name.variants.extend(self.add_variants.iter().cloned());
+
+        for patch in &self.variants {
+            for variant in select(path, &mut enum_name.variants, &patch.select)? {
+                let set = &patch.set;
+                set_opt(&mut variant.name, &set.name);
+                set_desc(&mut variant.description, &set.description);
+                set_opt(&mut variant.value, &set.value);
+            }
+        }
+
+        check_unique(path, &enum_name.variants)?;
+        set_opt(&mut enum_name.name, &self.set.name);
+        set_desc(&mut enum_name.description, &self.set.description);
+        set_opt(&mut enum_name.bit_size, &self.set.bit_size);
+        mark(&mut enum_name.source, name);
+
+        Ok(())
+    }
+}
+
+fn set_opt<T: Clone>(dst: &mut T, src: &Option<T>) {
+    if let Some(src) = src {
+        *dst = src.clone();
+    }
+}
+
+fn set_desc(dst: &mut Option<String>, src: &Option<String>) {
+    if src.is_some() {
+        dst.clone_from(src);
+    }
+}
+
+/// Record a patch in the source of an item it selects.
+fn mark(source: &mut Option<Source>, patch: Option<&str>) {
+    if let Some(patch) = patch {
+        source
+            .get_or_insert_default()
+            .patches
+            .push(patch.to_string());
+    }
+}
+
+/// Items added by a patch, with the patch as their source unless they
+/// already have one.
+fn added<'a, T: Clone>(
+    items: &'a [T],
+    patch: Option<&'a str>,
+    source: fn(&mut T) -> &mut Option<Source>,
+) -> impl Iterator<Item = T> + 'a {
+    items.iter().map(move |item| {
+        let mut item = item.clone();
+        let source = source(&mut item);
+        if source.is_none() {
+            *source = patch.map(|patch| Source {
+                file: Some(patch.to_string()),
+                ..Default::default()
+            });
+        }
+        item
+    })
+}
+
+fn check_enum(path: &Path, enums: &[Enum], name: &str) -> Result<()> {
+    if enums.iter().any(|enum_name| enum_name.name == name) {
+        Ok(())
+    } else {
+        let enum_name = name.to_string();
+        Err(Error::new(path, ErrorKind::UnknownEnum { enum_name }))
+    }
+}
+
+fn imports(chip: &Chip, module: &Module) -> bool {
+    chip.imports
+        .iter()
+        .any(|import| import.name == module.name && import.version == module.version)
+}
+
+fn rename_in_module(module: &mut Module, renames: &Renames) {
+    for field in module.blocks.iter_mut().flat_map(|block| &mut block.fields) {
+        match &mut field.inner {
+            block::FieldInner::Block(inner) => rename(&mut inner.block_name, &renames.blocks),
+            block::FieldInner::Bitfield(inner) => {
+                rename(&mut inner.bitfield_name, &renames.bitfields)
+            }
+            block::FieldInner::Simple(inner) => {
+                if let Some(enum_name) = &mut inner.enum_name {
+                    rename(enum_name, &renames.enums);
+                }
+            }
+        }
+    }
+
+    let fields = module
+        .bitfields
+        .iter_mut()
+        .flat_map(|bitfield| &mut bitfield.fields);
+    for field in fields {
+        if let Some(enum_name) = &mut field.enum_name {
+            rename(enum_name, &renames.enums);
+        }
+    }
+}
+
+fn rename_in_chip(chip: &mut Chip, module: &str, renames: &Renames) {
+    for peripheral in &mut chip.peripherals {
+        if peripheral.module == module {
+            rename(&mut peripheral.block_name, &renames.blocks);
+        }
+    }
+
+    let cm_regs = chip
+        .cm_ext
+        .iter_mut()
+        .flat_map(|cm_ext| &mut cm_ext.cm_regs);
+    for cm_reg in cm_regs {
+        if cm_reg.module == module {
+            rename(&mut cm_reg.bitfield_name, &renames.bitfields);
+        }
+    }
+
+    let csrs = chip
+        .riscv_ext
+        .iter_mut()
+        .flat_map(|riscv_ext| &mut riscv_ext.csrs);
+    for csr in csrs {
+        if csr.module == module {
+            rename(&mut csr.bitfield_name, &renames.bitfields);
+        }
+    }
+}
+
+fn rename(name: &mut String, renames: &HashMap<String, String>) {
+    if let Some(new) = renames.get(name) {
+        *name = new.clone();
+    }
+}
+
+fn rename_module_in_chip(chip: &mut Chip, old: &str, new: &str) {
+    for import in &mut chip.imports {
+        if import.name == old {
+            import.name = new.to_string();
+        }
+    }
+
+    for peripheral in &mut chip.peripherals {
+        if peripheral.module == old {
+            peripheral.module = new.to_string();
+        }
+    }
+
+    let cm_regs = chip
+        .cm_ext
+        .iter_mut()
+        .flat_map(|cm_ext| &mut cm_ext.cm_regs);
+    for cm_reg in cm_regs {
+        if cm_reg.module == old {
+            cm_reg.module = new.to_string();
+        }
+    }
+
+    let csrs = chip
+        .riscv_ext
+        .iter_mut()
+        .flat_map(|riscv_ext| &mut riscv_ext.csrs);
+    for csr in csrs {
+        if csr.module == old {
+            csr.module = new.to_string();
+        }
+    }
+}
+
+/// An IR item which can be selected by name.
+trait Named {
+    fn name(&self) -> &str;
+
+    fn segment(&self) -> Segment;
+}
+
+macro_rules! impl_named {
+    ($($ty:ty => $segment:ident),* $(,)?) => {
+        $(impl Named for $ty {
+            fn name(&self) -> &str {
+                &self.name
+            }
+
+            fn segment(&self) -> Segment {
+                Segment::$segment(self.name.clone())
+            }
+        })*
+    };
+}
+
+impl_named! {
+    Chip => Chip,
+    chip::Peripheral => Peripheral,
+    Block => Block,
+    block::Field => Field,
+    Bitfield => Bitfield,
+    bitfield::Field => Field,
+    Enum => Enum,
+    enum_name::Variant => Variant,
+}
+
+impl Named for Module {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn segment(&self) -> Segment {
+        Segment::Module {
+            name: self.name.clone(),
+            version: self.version.clone(),
+        }
+    }
+}
+
+fn names<T: Named>(items: &[T]) -> Vec<String> {
+    items.iter().map(|item| item.name().to_string()).collect()
+}
+
+/// Map the old names of the renamed items to their new ones.
+fn renamed<T: Named>(old: Vec<String>, items: &[T]) -> HashMap<String, String> {
+    old.into_iter()
+        .zip(items)
+        .filter(|(old, item)| old != item.name())
+        .map(|(old, item)| (old, item.name().to_string()))
+        .collect()
+}
+
+fn select<'a, T: Named>(path: &Path, items: &'a mut [T], glob: &Glob) -> Result<Vec<&'a mut T>> {
+    let selected = items
+        .iter_mut()
+        .filter(|item| glob.matches(item.name()))
+        .collect::<Vec<_>>();
+
+    if selected.is_empty() {
+        return Err(Error::no_match(path, glob));
+    }
+
+    Ok(selected)
+}
+
+fn delete<T: Named>(path: &Path, items: &mut Vec<T>, globs: &[Glob]) -> Result<()> {
+    for glob in globs {
+        let len = items.len();
+        items.retain(|item| !glob.matches(item.name()));
+
+        if items.len() == len {
+            return Err(Error::no_match(path, glob));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_unique<T: Named>(path: &Path, items: &[T]) -> Result<()> {
+    let mut names = HashSet::new();
+    for item in items {
+        if !names.insert(item.name()) {
+            return Err(Error::new(&path.join(item.segment()), ErrorKind::Duplicate));
+        }
+    }
+
+    Ok(())
+}
+
+fn merge_array<T: Element>(
+    path: &Path,
+    name: Option<&str>,
+    items: &mut Vec<T>,
+    patch: &ArrayPatch,
+) -> Result<()> {
+    let position = items
+        .iter()
+        .position(|item| patch.select.matches(item.name()))
+        .ok_or_else(|| Error::no_match(path, &patch.select))?;
+
+    let (selected, rest): (Vec<_>, Vec<_>) = std::mem::take(items)
+        .into_iter()
+        .partition(|item| patch.select.matches(item.name()));
+    *items = rest;
+
+    let mut array = arrays::collapse(selected, &patch.name).map_err(|reason| {
+        let path = path.join(Segment::Field(patch.name.clone()));
+        Error::new(&path, ErrorKind::Array { reason })
+    })?;
+    if patch.description.is_some() {
+        *array.description_mut() = patch.description.clone();
+    }
+    mark(array.source_mut(), name);
+
+    items.insert(position, array);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn multi() -> MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "STM32F103",
+                "peripherals": [
+                    { "name": "USART1", "module": "usart", "address": 0x4001_3800u32, "block_name": "Usart" },
+                    { "name": "USART2", "module": "usart", "address": 0x4000_4400u32, "block_name": "Usart" },
+                ],
+                "imports": [{ "name": "usart", "version": "v1" }],
+                "riscv_ext": {
+                    "csrs": [{ "name": "UCR", "module": "usart", "number": 0x800, "access": "rw", "bitfield_name": "Cr1" }],
+                },
+            }],
+            "modules": [{
+                "name": "usart",
+                "version": "v1",
+                "blocks": [{
+                    "name": "Usart",
+                    "fields": [
+                        { "name": "CR1", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr1" },
+                        { "name": "DR", "byte_offset": 4, "access": "rw", "bit_size": 32 },
+                    ],
+                }],
+                "bitfields": [{
+                    "name": "Cr1",
+                    "bit_size": 32,
+                    "fields": [
+                        { "name": "M0", "bit_offset": 12, "bit_size": 1 },
+                        { "name": "M1", "bit_offset": 13, "bit_size": 1 },
+                    ],
+                }],
+                "enums": [{ "name": "Parity", "bit_size": 1, "variants": [{ "name": "EVEN", "value": 0 }] }],
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn apply(patch: &str, multi: &mut MultiChip) -> Result<()> {
+        let patch: Patch = serde_norway::from_str(patch).unwrap();
+        patch.apply(multi)
+    }
+
+    fn bitfield_name(field: &block::Field) -> &str {
+        match &field.inner {
+            block::FieldInner::Bitfield(inner) => &inner.bitfield_name,
+            _ => panic!("{} is not a bitfield register", field.name),
+        }
+    }
+
+    #[test]
+    fn set_and_delete() {
+        let mut multi = multi();
+        apply(
+            "
+            chips:
+              - select: STM32*
+                delete_peripherals: [USART2]
+                peripherals:
+                  - { select: USART1, set: { address: 0x40013c00 } }
+            modules:
+              - select: usart
+                blocks:
+                  - select: Usart
+                    delete_fields: [DR]
+                    fields:
+                      - { select: CR1, set: { access: ro, description: Control } }
+            ",
+            &mut multi,
+        )
+        .unwrap();
+
+        let peripherals = &multi.chips[0].peripherals;
+        assert_eq!(peripherals.len(), 1);
+        assert_eq!(peripherals[0].address, 0x4001_3c00);
+
+        let fields = &multi.modules[0].blocks[0].fields;
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].description.as_deref(), Some("Control"));
+        assert!(matches!(
+            &fields[0].inner,
+            block::FieldInner::Bitfield(inner) if inner.access == Access::Read
+        ));
+    }
+
+    #[test]
+    fn renames_follow_references() {
+        let mut multi = multi();
+        apply(
+            "
+            modules:
+              - select: usart
+                set: { name: uart }
+                blocks:
+                  - { select: Usart, set: { name: Uart } }
+                bitfields:
+                  - { select: Cr1, set: { name: Ctrl } }
+            ",
+            &mut multi,
+        )
+        .unwrap();
+
+        let module = &multi.modules[0];
+        assert_eq!(module.name, "uart");
+        assert_eq!(module.blocks[0].name, "Uart");
+        assert_eq!(bitfield_name(&module.blocks[0].fields[0]), "Ctrl");
+
+        let chip = &multi.chips[0];
+        assert_eq!(chip.imports[0].name, "uart");
+        assert_eq!(chip.peripherals[0].module, "uart");
+        assert_eq!(chip.peripherals[0].block_name, "Uart");
+        let csr = &chip.riscv_ext.as_ref().unwrap().csrs[0];
+        assert_eq!(csr.module, "uart");
+        assert_eq!(csr.bitfield_name, "Ctrl");
+    }
+
+    #[test]
+    fn renames_swap_and_chain() {
+        let mut multi = multi();
+        let module = &mut multi.modules[0];
+        let cr1 = module.bitfields[0].clone();
+        let reg = module.blocks[0].fields[0].clone();
+        for name in ["Cr2", "Cr3"] {
+            module.bitfields.push(Bitfield {
+                name: name.into(),
+                ..cr1.clone()
+            });
+        }
+
+        module.blocks[0].fields = ["Cr1", "Cr2", "Cr3"]
+            .into_iter()
+            .map(|name| block::Field {
+                name: name.to_uppercase(),
+                inner: block::FieldInner::Bitfield(block::field::Bitfield {
+                    access: Access::ReadWrite,
+                    bitfield_name: name.into(),
+                }),
+                ..reg.clone()
+            })
+            .collect();
+
+        // Cr1 and Cr2 swap names, Cr3 takes the old name of Cr2 through
+        // a temporary one
+        apply(
+            "
+            modules:
+              - select: usart
+                bitfields:
+                  - { select: Cr1, set: { name: Tmp } }
+                  - { select: Cr2, set: { name: Cr1 } }
+                  - { select: Tmp, set: { name: Cr2 } }
+                  - { select: Cr3, set: { name: Cr4 } }
+                  - { select: Cr4, set: { name: Cr5 } }
+            ",
+            &mut multi,
+        )
+        .unwrap();
+
+        let module = &multi.modules[0];
+        let names = module
+            .bitfields
+            .iter()
+            .map(|bitfield| bitfield.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["Cr2", "Cr1", "Cr5"]);
+
+        let fields = &module.blocks[0].fields;
+        let refs = fields.iter().map(bitfield_name);
+        assert_eq!(refs.collect::<Vec<_>>(), ["Cr2", "Cr1", "Cr5"]);
+    }
+
+    #[test]
+    fn arrays() {
+        let mut multi = multi();
+        apply(
+            "
+            modules:
+              - select: usart
+                bitfields:
+                  - select: Cr1
+                    arrays:
+                      - { select: \"M?\", name: M, description: Word length }
+            ",
+            &mut multi,
+        )
+        .unwrap();
+
+        let fields = &multi.modules[0].bitfields[0].fields;
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "M");
+        assert_eq!(fields[0].bit_offset, 12);
+        assert_eq!(fields[0].array, Some(Array { len: 2, stride: 1 }));
+        assert_eq!(fields[0].description.as_deref(), Some("Word length"));
+    }
+
+    #[test]
+    fn sources() {
+        let mut multi = multi();
+        let mut patch: Patch = serde_norway::from_str(
+            "
+modules:
+  - select: usart
+    add_enums:
+      - { name: Stop, bit_size: 2, variants: [] }
+    blocks:
+      - select: Usart
+        set: { description: USART }
+",
+        )
+        .unwrap();
+        patch.name = Some("fixes.yaml".into());
+        patch.apply(&mut multi).unwrap();
+
+        let module = &multi.modules[0];
+        let patches = module.blocks[0]
+            .source
+            .as_ref()
+            .map(|source| &source.patches);
+        assert_eq!(patches, Some(&vec!["fixes.yaml".to_string()]));
+        assert_eq!(module.bitfields[0].source, None);
+
+        let source = module.enums[1].source.as_ref().unwrap();
+        assert_eq!(source.file.as_deref(), Some("fixes.yaml"));
+        assert!(source.patches.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let error = apply(
+            "
+            modules:
+              - select: usart
+                blocks:
+                  - { select: Usart, delete_fields: [SR] }
+            ",
+            &mut multi(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "module usart (v1) > block Usart: selector SR does not match anything"
+        );
+
+        let error = apply(
+            "
+            modules:
+              - select: usart
+                blocks:
+                  - select: Usart
+                    fields: [{ select: DR, set: { enum_name: Stop } }]
+            ",
+            &mut multi(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.kind,
+            ErrorKind::UnknownEnum {
+                enum_name: "Stop".into()
+            }
+        );
+
+        let error = apply(
+            "
+            chips:
+              - select: STM32F103
+                peripherals: [{ select: USART2, set: { name: USART1 } }]
+            ",
+            &mut multi(),
+        )
+        .unwrap_err();
+        assert_eq!(error.path.to_string(), "chip STM32F103 > peripheral USART1");
+        assert_eq!(error.kind, ErrorKind::Duplicate);
+
+        // Top level selectors may match nothing
+        apply("chips: [{ select: GD32* }]", &mut multi()).unwrap();
+    }
+}
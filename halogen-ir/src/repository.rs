@@ -0,0 +1,373 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::format;
+use crate::ir;
+use crate::load::{self, Format, Index, IndexEntry};
+
+/// A multi-file IR, loaded on demand. Only the index is read upfront, chips
+/// and modules are parsed on first use and then cached.
+///
+/// Chips and modules are found through the names recorded in the index,
+/// files from older indices without names are parsed in order until the
+/// item is found.
+pub struct IrRepository {
+    root: PathBuf,
+    format_version: u32,
+    chips: Vec<Entry<ir::Chip>>,
+    modules: Vec<Entry<ir::Module>>,
+}
+
+struct Entry<T> {
+    /// Name and version recorded in the index
+    name: Option<(String, Option<String>)>,
+    path: PathBuf,
+    item: OnceLock<T>,
+}
+
+/// Something held by a repository.
+trait Item: for<'a> Deserialize<'a> + JsonSchema {
+    const MIGRATE: fn(&mut Value, u32);
+
+    fn name(&self) -> &str;
+
+    fn version(&self) -> Option<&str>;
+}
+
+impl Item for ir::Chip {
+    const MIGRATE: fn(&mut Value, u32) = format::migrate_chip;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Item for ir::Module {
+    const MIGRATE: fn(&mut Value, u32) = format::migrate_module;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+}
+
+impl<T: Item> Entry<T> {
+    fn new(entry: IndexEntry) -> Self {
+        match entry {
+            IndexEntry::Path(path) => Self {
+                name: None,
+                path,
+                item: OnceLock::new(),
+            },
+            IndexEntry::Named {
+                name,
+                version,
+                path,
+            } => Self {
+                name: Some((name, version)),
+                path,
+                item: OnceLock::new(),
+            },
+        }
+    }
+
+    fn loaded(item: T) -> Self {
+        Self {
+            name: Some((item.name().to_string(), item.version().map(str::to_string))),
+            path: PathBuf::new(),
+            item: OnceLock::from(item),
+        }
+    }
+
+    fn get(&self, root: &Path, format_version: u32) -> io::Result<&T> {
+        if let Some(item) = self.item.get() {
+            return Ok(item);
+        }
+
+        let item = load::load_file(root.join(&self.path), format_version, T::MIGRATE)?;
+
+        // Another thread may have won the race, both loaded the same thing
+        let _ = self.item.set(item);
+        Ok(self.item.get().unwrap())
+    }
+}
+
+impl IrRepository {
+    /// Open an IR from its index, or from a single-file IR which is then
+    /// loaded at once.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let data = load::read_file(path)?;
+        let (format_version, index) = load::load_versioned::<Index>(
+            &data,
+            Format::from_path(path),
+            Some(path),
+            load::migrate_root,
+        )?;
+
+        let (chips, modules) = match index {
+            Index::Index { chips, modules, .. } => (chips, modules),
+            Index::MultiChip(ir) => return Ok(Self::from_ir(ir.inner)),
+        };
+
+        let root = path
+            .parent()
+            .ok_or_else(|| io::Error::other("missing path parent"))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            format_version,
+            chips: chips.into_iter().map(Entry::new).collect(),
+            modules: modules.into_iter().map(Entry::new).collect(),
+        })
+    }
+
+    /// Wrap an IR which is already loaded.
+    pub fn from_ir(ir: ir::MultiChip) -> Self {
+        Self {
+            root: PathBuf::new(),
+            format_version: format::FORMAT_VERSION,
+            chips: ir.chips.into_iter().map(Entry::loaded).collect(),
+            modules: ir.modules.into_iter().map(Entry::loaded).collect(),
+        }
+    }
+
+    /// Names of every chip, which loads the chips missing a name in the
+    /// index.
+    pub fn chip_names(&self) -> io::Result<Vec<&str>> {
+        self.chips
+            .iter()
+            .map(|entry| match &entry.name {
+                Some((name, _)) => Ok(name.as_str()),
+                None => Ok(entry.get(&self.root, self.format_version)?.name()),
+            })
+            .collect()
+    }
+
+    pub fn chip(&self, name: &str) -> io::Result<&ir::Chip> {
+        self.find(&self.chips, name, None)?
+            .ok_or_else(|| not_found(format!("unknown chip {name}")))
+    }
+
+    pub fn module(&self, name: &str, version: Option<&str>) -> io::Result<&ir::Module> {
+        self.find(&self.modules, name, Some(version))?
+            .ok_or_else(|| match version {
+                Some(version) => not_found(format!("unknown module {name} {version}")),
+                None => not_found(format!("unknown module {name}")),
+            })
+    }
+
+    /// A chip along with the modules it imports, enough to generate it or
+    /// to resolve its peripherals.
+    pub fn chip_ir(&self, name: &str) -> io::Result<ir::MultiChip> {
+        let chip = self.chip(name)?;
+
+        let modules = chip
+            .imports
+            .iter()
+            .map(|import| {
+                self.module(&import.name, import.version.as_deref())
+                    .cloned()
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(ir::MultiChip {
+            chips: vec![chip.clone()],
+            modules,
+        })
+    }
+
+    /// Load everything.
+    pub fn to_ir(&self) -> io::Result<ir::MultiChip> {
+        let chips = self
+            .chips
+            .iter()
+            .map(|entry| entry.get(&self.root, self.format_version).cloned())
+            .collect::<io::Result<_>>()?;
+        let modules = self
+            .modules
+            .iter()
+            .map(|entry| entry.get(&self.root, self.format_version).cloned())
+            .collect::<io::Result<_>>()?;
+
+        Ok(ir::MultiChip { chips, modules })
+    }
+
+    /// Find an item by name, and by version if given. Entries named in the
+    /// index are tried first, then the others are loaded in order.
+    fn find<'a, T: Item>(
+        &'a self,
+        entries: &'a [Entry<T>],
+        name: &str,
+        version: Option<Option<&str>>,
+    ) -> io::Result<Option<&'a T>> {
+        let matches = |item_name: &str, item_version: Option<&str>| {
+            item_name == name && version.is_none_or(|version| version == item_version)
+        };
+
+        for entry in entries {
+            if let Some((entry_name, entry_version)) = &entry.name
+                && matches(entry_name, entry_version.as_deref())
+            {
+                return entry.get(&self.root, self.format_version).map(Some);
+            }
+        }
+
+        for entry in entries.iter().filter(|entry| entry.name.is_none()) {
+            let item = entry.get(&self.root, self.format_version)?;
+            if matches(item.name(), item.version()) {
+                return Ok(Some(item));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn not_found(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("halogen-repository-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("chips")).unwrap();
+        fs::create_dir_all(dir.join("modules")).unwrap();
+        dir
+    }
+
+    fn write(path: PathBuf, value: Value) {
+        fs::write(path, serde_json::to_vec(&value).unwrap()).unwrap();
+    }
+
+    fn module(name: &str, version: &str) -> Value {
+        json!({
+            "name": name,
+            "version": version,
+            "blocks": [{ "name": "Regs", "fields": [] }],
+            "bitfields": [],
+            "enums": [],
+        })
+    }
+
+    #[test]
+    fn lazy_lookup() {
+        let root = temp_dir("lazy");
+        write(
+            root.join("chips/f103.json"),
+            json!({
+                "name": "F103",
+                "peripherals": [],
+                "imports": [{ "name": "usart", "version": "v2" }],
+            }),
+        );
+        write(root.join("modules/usart_v1.json"), module("usart", "v1"));
+        write(root.join("modules/usart_v2.json"), module("usart", "v2"));
+        // Files named in the index are never read unless asked for
+        write(
+            root.join("index.json"),
+            json!({
+                "type": "index",
+                "format_version": format::FORMAT_VERSION,
+                "chips": [
+                    { "name": "F103", "path": "chips/f103.json" },
+                    { "name": "F407", "path": "chips/missing.json" },
+                ],
+                "modules": [
+                    { "name": "usart", "version": "v1", "path": "modules/usart_v1.json" },
+                    { "name": "usart", "version": "v2", "path": "modules/usart_v2.json" },
+                    { "name": "gpio", "path": "modules/missing.json" },
+                ],
+            }),
+        );
+
+        let repository = IrRepository::open(root.join("index.json")).unwrap();
+        assert_eq!(repository.chip_names().unwrap(), ["F103", "F407"]);
+
+        let ir = repository.chip_ir("F103").unwrap();
+        assert_eq!(ir.chips[0].name, "F103");
+        assert_eq!(ir.modules.len(), 1);
+        assert_eq!(ir.modules[0].version.as_deref(), Some("v2"));
+
+        assert_eq!(
+            repository.chip("F407").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(repository.to_ir().is_err());
+    }
+
+    #[test]
+    fn unnamed_fallback() {
+        let root = temp_dir("unnamed");
+        write(
+            root.join("chips/f103.json"),
+            json!({ "name": "F103", "peripherals": [], "imports": [] }),
+        );
+        write(root.join("modules/usart.json"), module("usart", "v1"));
+        write(root.join("modules/gpio.json"), module("gpio", "v2"));
+        // An index written before entries were named
+        write(
+            root.join("index.json"),
+            json!({
+                "type": "index",
+                "format_version": 1,
+                "chips": ["chips/f103.json"],
+                "modules": ["modules/usart.json", "modules/gpio.json"],
+            }),
+        );
+
+        let repository = IrRepository::open(root.join("index.json")).unwrap();
+        assert_eq!(repository.chip_names().unwrap(), ["F103"]);
+        assert_eq!(
+            repository
+                .module("gpio", Some("v2"))
+                .unwrap()
+                .version
+                .as_deref(),
+            Some("v2")
+        );
+
+        let err = repository.module("usart", Some("v2")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "unknown module usart v2");
+
+        assert_eq!(repository.to_ir().unwrap().modules.len(), 2);
+    }
+
+    #[test]
+    fn single_file() {
+        let root = temp_dir("single");
+        let path = root.join("ir.json");
+        let ir: ir::MultiChip = serde_json::from_value(json!({
+            "chips": [{ "name": "F103", "peripherals": [], "imports": [{ "name": "usart", "version": "v1" }] }],
+            "modules": [module("usart", "v1")],
+        }))
+        .unwrap();
+        load::dump_single_file(&path, &ir).unwrap();
+
+        let repository = IrRepository::open(&path).unwrap();
+        assert_eq!(repository.chip_ir("F103").unwrap(), ir);
+        assert_eq!(repository.to_ir().unwrap(), ir);
+    }
+}
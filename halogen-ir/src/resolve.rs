@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+use crate::ir::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    UnknownChip {
+        chip: String,
+    },
+    DuplicateChip {
+        chip: String,
+    },
+    UnknownPeripheral {
+        chip: String,
+        peripheral: String,
+    },
+    MissingImport {
+        chip: String,
+        module: String,
+    },
+    UnknownModule {
+        module: String,
+        version: Option<String>,
+    },
+    DuplicateModule {
+        module: String,
+        version: Option<String>,
+    },
+    UnknownBlock {
+        module: String,
+        block: String,
+    },
+    UnknownBitfield {
+        module: String,
+        bitfield: String,
+    },
+    UnknownEnum {
+        module: String,
+        enum_name: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownChip { chip } => write!(f, "unknown chip {chip}"),
+            Error::DuplicateChip { chip } => write!(f, "chip {chip} defined multiple times"),
+            Error::UnknownPeripheral { chip, peripheral } => {
+                write!(f, "unknown peripheral {peripheral} in chip {chip}")
+            }
+            Error::MissingImport { chip, module } => {
+                write!(f, "chip {chip} uses module {module} without importing it")
+            }
+            Error::UnknownModule { module, version } => match version {
+                Some(version) => write!(f, "unknown module {module} ({version})"),
+                None => write!(f, "unknown module {module}"),
+            },
+            Error::DuplicateModule { module, version } => match version {
+                Some(version) => write!(f, "module {module} ({version}) defined multiple times"),
+                None => write!(f, "module {module} defined multiple times"),
+            },
+            Error::UnknownBlock { module, block } => {
+                write!(f, "unknown block {block} in module {module}")
+            }
+            Error::UnknownBitfield { module, bitfield } => {
+                write!(f, "unknown bitfield {bitfield} in module {module}")
+            }
+            Error::UnknownEnum { module, enum_name } => {
+                write!(f, "unknown enum {enum_name} in module {module}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An error found by validation, along with the source of the item it was
+/// found in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub error: Error,
+    pub source: Option<Source>,
+}
+
+impl Diagnostic {
+    fn new(error: Error, source: Option<&Source>) -> Self {
+        Self {
+            error,
+            source: source.cloned(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(source) = &self.source {
+            write!(f, " (from {source})")?;
+        }
+        Ok(())
+    }
+}
+
+/// A block field referencing another item of the module.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockUse<'a> {
+    pub block: &'a Block,
+    pub field: &'a block::Field,
+}
+
+/// A field using an enum, either a simple register or a bitfield field.
+#[derive(Debug, Clone, Copy)]
+pub enum EnumUse<'a> {
+    Block(BlockUse<'a>),
+    Bitfield {
+        bitfield: &'a Bitfield,
+        field: &'a bitfield::Field,
+    },
+}
+
+/// A peripheral resolved to the block it instantiates.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedPeripheral<'a> {
+    pub peripheral: &'a chip::Peripheral,
+    pub module: &'a ModuleIndex<'a>,
+    pub block: &'a Block,
+}
+
+/// Name lookup over a whole [`MultiChip`].
+#[derive(Debug)]
+pub struct Index<'a> {
+    chips: HashMap<&'a str, ChipIndex<'a>>,
+    /// Modules by name, each entry holds all of its versions
+    modules: HashMap<&'a str, Vec<ModuleIndex<'a>>>,
+    duplicates: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+pub struct ChipIndex<'a> {
+    pub chip: &'a Chip,
+    peripherals: HashMap<&'a str, &'a chip::Peripheral>,
+    imports: HashMap<&'a str, Option<&'a str>>,
+}
+
+#[derive(Debug)]
+pub struct ModuleIndex<'a> {
+    pub module: &'a Module,
+    blocks: HashMap<&'a str, &'a Block>,
+    bitfields: HashMap<&'a str, &'a Bitfield>,
+    enums: HashMap<&'a str, &'a Enum>,
+    block_users: HashMap<&'a str, Vec<BlockUse<'a>>>,
+    bitfield_users: HashMap<&'a str, Vec<BlockUse<'a>>>,
+    enum_users: HashMap<&'a str, Vec<EnumUse<'a>>>,
+}
+
+impl<'a> Index<'a> {
+    pub fn new(multi: &'a MultiChip) -> Self {
+        let mut duplicates = Vec::new();
+
+        let mut chips = HashMap::new();
+        for chip in &multi.chips {
+            match chips.entry(chip.name.as_str()) {
+                Entry::Occupied(_) => duplicates.push(Diagnostic::new(
+                    Error::DuplicateChip {
+                        chip: chip.name.clone(),
+                    },
+                    chip.source.as_ref(),
+                )),
+                Entry::Vacant(entry) => {
+                    entry.insert(ChipIndex::new(chip));
+                }
+            }
+        }
+
+        let mut modules = HashMap::<_, Vec<ModuleIndex>>::new();
+        for module in &multi.modules {
+            let versions = modules.entry(module.name.as_str()).or_default();
+            if versions
+                .iter()
+                .any(|other| other.module.version == module.version)
+            {
+                duplicates.push(Diagnostic::new(
+                    Error::DuplicateModule {
+                        module: module.name.clone(),
+                        version: module.version.clone(),
+                    },
+                    module.source.as_ref(),
+                ));
+            } else {
+                versions.push(ModuleIndex::new(module));
+            }
+        }
+
+        Self {
+            chips,
+            modules,
+            duplicates,
+        }
+    }
+
+    pub fn chip(&self, name: &str) -> Result<&ChipIndex<'a>> {
+        self.chips.get(name).ok_or_else(|| Error::UnknownChip {
+            chip: name.to_string(),
+        })
+    }
+
+    pub fn module(&self, name: &str, version: Option<&str>) -> Result<&ModuleIndex<'a>> {
+        self.modules
+            .get(name)
+            .and_then(|versions| {
+                versions
+                    .iter()
+                    .find(|module| module.module.version.as_deref() == version)
+            })
+            .ok_or_else(|| Error::UnknownModule {
+                module: name.to_string(),
+                version: version.map(str::to_string),
+            })
+    }
+
+    /// Resolve a module through the imports of a chip.
+    pub fn import(&self, chip: &ChipIndex<'a>, name: &str) -> Result<&ModuleIndex<'a>> {
+        let version = chip.imports.get(name).ok_or_else(|| Error::MissingImport {
+            chip: chip.chip.name.clone(),
+            module: name.to_string(),
+        })?;
+
+        self.module(name, *version)
+    }
+
+    pub fn peripheral(&self, chip: &str, peripheral: &str) -> Result<ResolvedPeripheral<'_>> {
+        let chip = self.chip(chip)?;
+        let peripheral = chip.peripheral(peripheral)?;
+        let module = self.import(chip, &peripheral.module)?;
+        let block = module.block(&peripheral.block_name)?;
+
+        Ok(ResolvedPeripheral {
+            peripheral,
+            module,
+            block,
+        })
+    }
+
+    pub fn chips(&self) -> impl Iterator<Item = &ChipIndex<'a>> {
+        self.chips.values()
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &ModuleIndex<'a>> {
+        self.modules.values().flatten()
+    }
+
+    /// Check every reference in the IR, returning all the dangling ones.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut errors = self.duplicates.clone();
+
+        let mut chips = self.chips.values().collect::<Vec<_>>();
+        chips.sort_by_key(|chip| &chip.chip.name);
+
+        for chip in chips {
+            for import in &chip.chip.imports {
+                if let Err(err) = self.module(&import.name, import.version.as_deref()) {
+                    errors.push(Diagnostic::new(err, chip.chip.source.as_ref()));
+                }
+            }
+
+            for peripheral in &chip.chip.peripherals {
+                if let Err(err) = self.peripheral(&chip.chip.name, &peripheral.name) {
+                    // Already reported above
+                    if !matches!(err, Error::UnknownModule { .. }) {
+                        errors.push(Diagnostic::new(err, peripheral.source.as_ref()));
+                    }
+                }
+            }
+
+            if let Some(cm_ext) = &chip.chip.cm_ext {
+                for cm_reg in &cm_ext.cm_regs {
+                    let res = self
+                        .import(chip, &cm_reg.module)
+                        .and_then(|module| module.bitfield(&cm_reg.bitfield_name));
+
+                    match res {
+                        Ok(_) | Err(Error::UnknownModule { .. }) => {}
+                        Err(err) => errors.push(Diagnostic::new(err, chip.chip.source.as_ref())),
+                    }
+                }
+            }
+
+            if let Some(riscv_ext) = &chip.chip.riscv_ext {
+                for csr in &riscv_ext.csrs {
+                    let res = self
+                        .import(chip, &csr.module)
+                        .and_then(|module| module.bitfield(&csr.bitfield_name));
+
+                    match res {
+                        Ok(_) | Err(Error::UnknownModule { .. }) => {}
+                        Err(err) => errors.push(Diagnostic::new(err, chip.chip.source.as_ref())),
+                    }
+                }
+            }
+        }
+
+        let mut modules = self.modules().collect::<Vec<_>>();
+        modules.sort_by_key(|module| (&module.module.name, &module.module.version));
+
+        for module in modules {
+            errors.extend(module.validate());
+        }
+
+        errors
+    }
+}
+
+impl<'a> ChipIndex<'a> {
+    fn new(chip: &'a Chip) -> Self {
+        Self {
+            chip,
+            peripherals: chip
+                .peripherals
+                .iter()
+                .map(|peripheral| (peripheral.name.as_str(), peripheral))
+                .collect(),
+            imports: chip
+                .imports
+                .iter()
+                .map(|import| (import.name.as_str(), import.version.as_deref()))
+                .collect(),
+        }
+    }
+
+    pub fn peripheral(&self, name: &str) -> Result<&'a chip::Peripheral> {
+        self.peripherals
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::UnknownPeripheral {
+                chip: self.chip.name.clone(),
+                peripheral: name.to_string(),
+            })
+    }
+}
+
+impl<'a> ModuleIndex<'a> {
+    fn new(module: &'a Module) -> Self {
+        let mut block_users = HashMap::<_, Vec<_>>::new();
+        let mut bitfield_users = HashMap::<_, Vec<_>>::new();
+        let mut enum_users = HashMap::<_, Vec<_>>::new();
+
+        for block in &module.blocks {
+            for field in &block.fields {
+                let usage = BlockUse { block, field };
+                match &field.inner {
+                    block::FieldInner::Block(inner) => {
+                        block_users
+                            .entry(inner.block_name.as_str())
+                            .or_default()
+                            .push(usage);
+                    }
+                    block::FieldInner::Bitfield(inner) => {
+                        bitfield_users
+                            .entry(inner.bitfield_name.as_str())
+                            .or_default()
+                            .push(usage);
+                    }
+                    block::FieldInner::Simple(inner) => {
+                        if let Some(enum_name) = &inner.enum_name {
+                            enum_users
+                                .entry(enum_name.as_str())
+                                .or_default()
+                                .push(EnumUse::Block(usage));
+                        }
+                    }
+                }
+            }
+        }
+
+        for bitfield in &module.bitfields {
+            for field in &bitfield.fields {
+                if let Some(enum_name) = &field.enum_name {
+                    enum_users
+                        .entry(enum_name.as_str())
+                        .or_default()
+                        .push(EnumUse::Bitfield { bitfield, field });
+                }
+            }
+        }
+
+        Self {
+            module,
+            blocks: module
+                .blocks
+                .iter()
+                .map(|block| (block.name.as_str(), block))
+                .collect(),
+            bitfields: module
+                .bitfields
+                .iter()
+                .map(|bitfield| (bitfield.name.as_str(), bitfield))
+                .collect(),
+            enums: module
+                .enums
+                .iter()
+                .map(|enum_name| (enum_name.name.as_str(), enum_name))
+                .collect(),
+            block_users,
+            bitfield_users,
+            enum_users,
+        }
+    }
+
+    pub fn block(&self, name: &str) -> Result<&'a Block> {
+        self.blocks
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::UnknownBlock {
+                module: self.module.name.clone(),
+                block: name.to_string(),
+            })
+    }
+
+    pub fn bitfield(&self, name: &str) -> Result<&'a Bitfield> {
+        self.bitfields
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::UnknownBitfield {
+                module: self.module.name.clone(),
+                bitfield: name.to_string(),
+            })
+    }
+
+    pub fn enum_name(&self, name: &str) -> Result<&'a Enum> {
+        self.enums
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::UnknownEnum {
+                module: self.module.name.clone(),
+                enum_name: name.to_string(),
+            })
+    }
+
+    /// Resolve the sub-block of a block field, if it is one.
+    pub fn field_block(&self, field: &block::Field) -> Result<Option<&'a Block>> {
+        match &field.inner {
+            block::FieldInner::Block(inner) => self.block(&inner.block_name).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolve the bitfield of a block field, if it has one.
+    pub fn field_bitfield(&self, field: &block::Field) -> Result<Option<&'a Bitfield>> {
+        match &field.inner {
+            block::FieldInner::Bitfield(inner) => self.bitfield(&inner.bitfield_name).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolve the enum of a simple block field, if it has one.
+    pub fn field_enum(&self, field: &block::Field) -> Result<Option<&'a Enum>> {
+        match &field.inner {
+            block::FieldInner::Simple(block::field::Simple {
+                enum_name: Some(enum_name),
+                ..
+            }) => self.enum_name(enum_name).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolve the enum of a bitfield field, if it has one.
+    pub fn bitfield_field_enum(&self, field: &bitfield::Field) -> Result<Option<&'a Enum>> {
+        field
+            .enum_name
+            .as_deref()
+            .map(|enum_name| self.enum_name(enum_name))
+            .transpose()
+    }
+
+    /// Block fields containing the given block.
+    pub fn block_users(&self, name: &str) -> &[BlockUse<'a>] {
+        self.block_users.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Block fields using the given bitfield.
+    pub fn bitfield_users(&self, name: &str) -> &[BlockUse<'a>] {
+        self.bitfield_users.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Block and bitfield fields using the given enum.
+    pub fn enum_users(&self, name: &str) -> &[EnumUse<'a>] {
+        self.enum_users.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    fn validate(&self) -> Vec<Diagnostic> {
+        let mut errors = Vec::new();
+
+        for block in &self.module.blocks {
+            for field in &block.fields {
+                let res = self
+                    .field_block(field)
+                    .and(self.field_bitfield(field))
+                    .and(self.field_enum(field));
+
+                if let Err(err) = res {
+                    let source = field.source.as_ref().or(block.source.as_ref());
+                    errors.push(Diagnostic::new(err, source));
+                }
+            }
+        }
+
+        for bitfield in &self.module.bitfields {
+            for field in &bitfield.fields {
+                if let Err(err) = self.bitfield_field_enum(field) {
+                    let source = field.source.as_ref().or(bitfield.source.as_ref());
+                    errors.push(Diagnostic::new(err, source));
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn multi() -> MultiChip {
+        let usart = |version: &str| {
+            json!({
+                "name": "usart",
+                "version": version,
+                "blocks": [{
+                    "name": "Usart",
+                    "fields": [
+                        { "name": "CR1", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr1" },
+                        { "name": "CR2", "byte_offset": 4, "access": "rw", "bitfield_name": "Cr1" },
+                    ],
+                }],
+                "bitfields": [{
+                    "name": "Cr1",
+                    "bit_size": 32,
+                    "fields": [{ "name": "PS", "bit_offset": 9, "bit_size": 1, "enum_name": "Parity" }],
+                }],
+                "enums": [{ "name": "Parity", "bit_size": 1, "variants": [] }],
+            })
+        };
+
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "F103",
+                "peripherals": [{ "name": "USART1", "module": "usart", "address": 0x4001_3800u32, "block_name": "Usart" }],
+                "imports": [{ "name": "usart", "version": "v2" }],
+            }],
+            "modules": [usart("v1"), usart("v2")],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn lookups() {
+        let multi = multi();
+        let index = Index::new(&multi);
+
+        let resolved = index.peripheral("F103", "USART1").unwrap();
+        assert_eq!(resolved.block.name, "Usart");
+        assert_eq!(resolved.module.module.version.as_deref(), Some("v2"));
+
+        assert_eq!(
+            index.peripheral("F103", "USART9").unwrap_err(),
+            Error::UnknownPeripheral {
+                chip: "F103".into(),
+                peripheral: "USART9".into()
+            }
+        );
+        assert_eq!(
+            index.module("usart", Some("v3")).unwrap_err().to_string(),
+            "unknown module usart (v3)"
+        );
+
+        let module = index.module("usart", Some("v1")).unwrap();
+        let users = module.bitfield_users("Cr1");
+        let names = users.iter().map(|user| user.field.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["CR1", "CR2"]);
+        assert_eq!(module.enum_users("Parity").len(), 1);
+        assert!(module.block_users("Usart").is_empty());
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Index::new(&multi()).validate().is_empty());
+
+        let mut multi = multi();
+        let chip = multi.chips[0].clone();
+        multi.chips.push(chip);
+
+        let peripherals = &mut multi.chips[0].peripherals;
+        peripherals[0].block_name = "Uart".into();
+        peripherals.push(chip::Peripheral {
+            name: "GPIOA".into(),
+            module: "gpio".into(),
+            ..peripherals[0].clone()
+        });
+        multi.modules[0].bitfields[0].fields[0].enum_name = Some("Stop".into());
+
+        let errors = Index::new(&multi)
+            .validate()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "chip F103 defined multiple times",
+                "unknown block Uart in module usart",
+                "chip F103 uses module gpio without importing it",
+                "unknown enum Stop in module usart",
+            ]
+        );
+    }
+}
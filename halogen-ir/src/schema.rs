@@ -0,0 +1,180 @@
+use std::fmt;
+
+pub use schemars::{JsonSchema, Schema};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::ir;
+
+/// Schema of a single-file IR.
+pub fn multi_chip() -> Schema {
+    schema::<crate::format::Versioned<ir::MultiChip>>()
+}
+
+/// Schema of a chip file of a multi-file IR.
+pub fn chip() -> Schema {
+    schema::<ir::Chip>()
+}
+
+/// Schema of a module file of a multi-file IR.
+pub fn module() -> Schema {
+    schema::<ir::Module>()
+}
+
+/// Schema of the files accepted by
+/// [`load_multi_file`](crate::load::load_multi_file), either the index of a
+/// multi-file IR or a single-file IR.
+#[cfg(feature = "load")]
+pub fn index() -> Schema {
+    schema::<crate::load::Index>()
+}
+
+pub fn schema<T: JsonSchema>() -> Schema {
+    schemars::generate::SchemaSettings::draft2020_12()
+        .into_generator()
+        .into_root_schema_for::<T>()
+}
+
+/// A value not matching the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// JSON pointer to the offending value
+    pub path: String,
+    pub message: String,
+    /// Source of the closest item around the value which records one
+    pub source: Option<ir::Source>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{path}: {}", self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, " (from {source})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Check a value against a schema, returning every mismatch found.
+pub fn validate(schema: &Schema, value: &Value) -> Vec<Error> {
+    let validator =
+        jsonschema::validator_for(schema.as_value()).expect("generated schemas are valid");
+
+    let mut errors = validator
+        .iter_errors(value)
+        .flat_map(|error| errors(&error))
+        .collect::<Vec<_>>();
+
+    for error in &mut errors {
+        error.source = source(value, &error.path);
+    }
+
+    errors
+}
+
+/// Find the innermost source recorded along a JSON pointer, skipping the
+/// ones which are themselves invalid.
+fn source(value: &Value, pointer: &str) -> Option<ir::Source> {
+    // Every prefix of the pointer, from the root to the value itself
+    let prefixes = std::iter::once(0)
+        .chain(pointer.match_indices('/').skip(1).map(|(end, _)| end))
+        .chain(std::iter::once(pointer.len()));
+
+    prefixes
+        .filter_map(|end| value.pointer(&pointer[..end])?.get("source"))
+        .filter_map(|source| ir::Source::deserialize(source).ok())
+        .last()
+}
+
+/// Flatten an error, replacing a mismatch against every alternative (as
+/// untagged enums produce) by the errors of the closest alternative.
+fn errors(error: &jsonschema::ValidationError) -> Vec<Error> {
+    use jsonschema::error::ValidationErrorKind;
+
+    let alternatives = match error.kind() {
+        ValidationErrorKind::AnyOf { context } | ValidationErrorKind::OneOfNotValid { context } => {
+            context
+        }
+        _ => &Vec::new(),
+    };
+
+    // The alternative which got the furthest, then with the fewest errors
+    let closest = alternatives
+        .iter()
+        .map(|alternative| alternative.iter().flat_map(errors).collect::<Vec<_>>())
+        .filter(|errors| !errors.is_empty())
+        .max_by_key(|errors| {
+            let depth = errors.iter().map(Error::depth).max();
+            (depth, std::cmp::Reverse(errors.len()))
+        });
+
+    closest.unwrap_or_else(|| {
+        vec![Error {
+            path: error.instance_path().to_string(),
+            message: error.masked().to_string(),
+            source: None,
+        }]
+    })
+}
+
+impl Error {
+    fn depth(&self) -> usize {
+        self.path.matches('/').count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn module_value() -> Value {
+        json!({
+            "name": "tim",
+            "blocks": [{
+                "name": "Tim",
+                "fields": [
+                    { "name": "CR1", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr1" },
+                    { "name": "CNT", "byte_offset": 36, "access": "rw", "bit_size": 16 },
+                ],
+            }],
+            "bitfields": [],
+            "enums": [],
+        })
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(validate(&module(), &module_value()), []);
+    }
+
+    #[test]
+    fn invalid_field() {
+        let mut value = module_value();
+        value["blocks"][0]["fields"][1]["access"] = json!("rx");
+        value["blocks"][0]["fields"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("byte_offset");
+
+        let errors = validate(&module(), &value);
+        let paths = errors.iter().map(|error| error.path.as_str());
+        assert_eq!(
+            paths.collect::<Vec<_>>(),
+            ["/blocks/0/fields/0", "/blocks/0/fields/1/access"]
+        );
+        assert!(
+            errors[0].to_string().contains("byte_offset"),
+            "{}",
+            errors[0]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::ir::*;
+
+/// Size and coverage figures of an IR.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub chips: usize,
+    /// Peripherals of all chips
+    pub peripherals: usize,
+    /// Distinct module names
+    pub modules: usize,
+    /// Distinct module name and version pairs
+    pub module_versions: usize,
+    pub blocks: usize,
+    /// Block fields which are not sub-blocks
+    pub registers: usize,
+    /// Bitfield fields
+    pub fields: usize,
+    /// Bitfield fields wider than one bit
+    pub multi_bit_fields: usize,
+    pub enums: usize,
+    pub variants: usize,
+    pub registers_without_description: usize,
+    pub fields_without_description: usize,
+    /// Multi-bit fields without an enum, single bits rarely need one
+    pub fields_without_enum: usize,
+    /// Items left out by the frontend, by reason
+    pub skipped: BTreeMap<String, usize>,
+    /// Module versions, most imported first
+    pub module_usage: Vec<ModuleUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModuleUsage {
+    pub name: String,
+    pub version: Option<String>,
+    /// Number of chips importing the module
+    pub chips: usize,
+}
+
+pub fn stats(multi: &MultiChip) -> Stats {
+    let mut stats = Stats {
+        chips: multi.chips.len(),
+        module_versions: multi.modules.len(),
+        ..Default::default()
+    };
+
+    let mut users = HashMap::<_, usize>::new();
+    for chip in &multi.chips {
+        stats.peripherals += chip.peripherals.len();
+
+        let imports = chip
+            .imports
+            .iter()
+            .map(|import| (import.name.as_str(), import.version.as_deref()))
+            .collect::<HashSet<_>>();
+        for import in imports {
+            *users.entry(import).or_default() += 1;
+        }
+    }
+
+    stats.modules = multi
+        .modules
+        .iter()
+        .map(|module| module.name.as_str())
+        .collect::<HashSet<_>>()
+        .len();
+
+    for module in &multi.modules {
+        stats.blocks += module.blocks.len();
+        stats.enums += module.enums.len();
+
+        for field in module.blocks.iter().flat_map(|block| &block.fields) {
+            if matches!(field.inner, block::FieldInner::Block(_)) {
+                continue;
+            }
+
+            stats.registers += 1;
+            if field.description.is_none() {
+                stats.registers_without_description += 1;
+            }
+        }
+
+        for field in module
+            .bitfields
+            .iter()
+            .flat_map(|bitfield| &bitfield.fields)
+        {
+            stats.fields += 1;
+            if field.description.is_none() {
+                stats.fields_without_description += 1;
+            }
+            if field.bit_size > 1 {
+                stats.multi_bit_fields += 1;
+                if field.enum_name.is_none() {
+                    stats.fields_without_enum += 1;
+                }
+            }
+        }
+
+        for enum_name in &module.enums {
+            stats.variants += enum_name.variants.len();
+        }
+
+        for skipped in &module.skipped {
+            *stats.skipped.entry(skipped.reason.clone()).or_default() += 1;
+        }
+
+        stats.module_usage.push(ModuleUsage {
+            name: module.name.clone(),
+            version: module.version.clone(),
+            chips: users
+                .get(&(module.name.as_str(), module.version.as_deref()))
+                .copied()
+                .unwrap_or(0),
+        });
+    }
+
+    stats.module_usage.sort_by(|a, b| {
+        b.chips
+            .cmp(&a.chips)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.version.cmp(&b.version))
+    });
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let multi = serde_json::from_value(json!({
+            "chips": [],
+            "modules": [{
+                "name": "tim",
+                "blocks": [],
+                "bitfields": [{
+                    "name": "Cr1",
+                    "bit_size": 32,
+                    "fields": [
+                        { "name": "CEN", "bit_offset": 0, "bit_size": 1 },
+                        { "name": "UDIS", "description": "Update", "bit_offset": 1, "bit_size": 1 },
+                        { "name": "CMS", "bit_offset": 5, "bit_size": 2, "enum_name": "Cms" },
+                        { "name": "CKD", "bit_offset": 8, "bit_size": 2 },
+                    ],
+                }],
+                "enums": [{ "name": "Cms", "bit_size": 2, "variants": [] }],
+            }],
+        }))
+        .unwrap();
+
+        let stats = stats(&multi);
+        assert_eq!(stats.fields, 4);
+        assert_eq!(stats.fields_without_description, 3);
+        assert_eq!(stats.multi_bit_fields, 2);
+        assert_eq!(stats.fields_without_enum, 1);
+    }
+}
@@ -0,0 +1,534 @@
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::ir::*;
+
+/// A single step of a [`Path`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Chip(String),
+    Peripheral(String),
+    Module {
+        name: String,
+        version: Option<String>,
+    },
+    Block(String),
+    Field(String),
+    Bitfield(String),
+    Enum(String),
+    Variant(String),
+    CmReg(String),
+    Csr(String),
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Chip(name) => write!(f, "chip {name}"),
+            Segment::Peripheral(name) => write!(f, "peripheral {name}"),
+            Segment::Module {
+                name,
+                version: Some(version),
+            } => write!(f, "module {name} ({version})"),
+            Segment::Module {
+                name,
+                version: None,
+            } => write!(f, "module {name}"),
+            Segment::Block(name) => write!(f, "block {name}"),
+            Segment::Field(name) => write!(f, "field {name}"),
+            Segment::Bitfield(name) => write!(f, "bitfield {name}"),
+            Segment::Enum(name) => write!(f, "enum {name}"),
+            Segment::Variant(name) => write!(f, "variant {name}"),
+            Segment::CmReg(name) => write!(f, "cm_reg {name}"),
+            Segment::Csr(name) => write!(f, "csr {name}"),
+        }
+    }
+}
+
+/// Location of the node being visited, the last segment is the node itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn last(&self) -> Option<&Segment> {
+        self.segments.last()
+    }
+
+    /// Name of the module containing the node, if any.
+    pub fn module(&self) -> Option<(&str, Option<&str>)> {
+        self.segments.iter().find_map(|segment| match segment {
+            Segment::Module { name, version } => Some((name.as_str(), version.as_deref())),
+            _ => None,
+        })
+    }
+
+    /// Name of the chip containing the node, if any.
+    pub fn chip(&self) -> Option<&str> {
+        self.segments.iter().find_map(|segment| match segment {
+            Segment::Chip(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn join(&self, segment: Segment) -> Self {
+        let mut segments = Vec::with_capacity(self.segments.len() + 1);
+        segments.extend_from_slice(&self.segments);
+        segments.push(segment);
+        Self { segments }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i != 0 {
+                write!(f, " > ")?;
+            }
+            write!(f, "{segment}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Path {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+fn module_segment(module: &Module) -> Segment {
+    Segment::Module {
+        name: module.name.clone(),
+        version: module.version.clone(),
+    }
+}
+
+/// Read-only traversal of the IR. Every method defaults to visiting the
+/// children of the node, override the ones you are interested in and call
+/// the matching `walk_*` function to keep descending.
+pub trait Visitor {
+    fn visit_multi_chip(&mut self, multi: &MultiChip) {
+        walk_multi_chip(self, multi)
+    }
+
+    fn visit_chip(&mut self, path: &Path, chip: &Chip) {
+        walk_chip(self, path, chip)
+    }
+
+    fn visit_peripheral(&mut self, _path: &Path, _peripheral: &chip::Peripheral) {}
+
+    fn visit_cm_reg(&mut self, _path: &Path, _cm_reg: &chip::cm_ext::CmReg) {}
+
+    fn visit_csr(&mut self, _path: &Path, _csr: &chip::riscv_ext::Csr) {}
+
+    fn visit_module(&mut self, path: &Path, module: &Module) {
+        walk_module(self, path, module)
+    }
+
+    fn visit_block(&mut self, path: &Path, block: &Block) {
+        walk_block(self, path, block)
+    }
+
+    fn visit_block_field(&mut self, _path: &Path, _field: &block::Field) {}
+
+    fn visit_bitfield(&mut self, path: &Path, bitfield: &Bitfield) {
+        walk_bitfield(self, path, bitfield)
+    }
+
+    fn visit_bitfield_field(&mut self, _path: &Path, _field: &bitfield::Field) {}
+
+    fn visit_enum(&mut self, path: &Path, enum_name: &Enum) {
+        walk_enum(self, path, enum_name)
+    }
+
+    fn visit_variant(&mut self, _path: &Path, _variant: &enum_name::Variant) {}
+}
+
+pub fn walk_multi_chip<V: Visitor + ?Sized>(visitor: &mut V, multi: &MultiChip) {
+    let path = Path::new();
+    for chip in &multi.chips {
+        visitor.visit_chip(&path.join(Segment::Chip(chip.name.clone())), chip);
+    }
+    for module in &multi.modules {
+        visitor.visit_module(&path.join(module_segment(module)), module);
+    }
+}
+
+pub fn walk_chip<V: Visitor + ?Sized>(visitor: &mut V, path: &Path, chip: &Chip) {
+    for peripheral in &chip.peripherals {
+        let path = path.join(Segment::Peripheral(peripheral.name.clone()));
+        visitor.visit_peripheral(&path, peripheral);
+    }
+    for cm_reg in chip.cm_ext.iter().flat_map(|cm_ext| &cm_ext.cm_regs) {
+        visitor.visit_cm_reg(&path.join(Segment::CmReg(cm_reg.name.clone())), cm_reg);
+    }
+    for csr in chip.riscv_ext.iter().flat_map(|riscv_ext| &riscv_ext.csrs) {
+        visitor.visit_csr(&path.join(Segment::Csr(csr.name.clone())), csr);
+    }
+}
+
+pub fn walk_module<V: Visitor + ?Sized>(visitor: &mut V, path: &Path, module: &Module) {
+    for block in &module.blocks {
+        visitor.visit_block(&path.join(Segment::Block(block.name.clone())), block);
+    }
+    for bitfield in &module.bitfields {
+        let path = path.join(Segment::Bitfield(bitfield.name.clone()));
+        visitor.visit_bitfield(&path, bitfield);
+    }
+    for enum_name in &module.enums {
+        visitor.visit_enum(&path.join(Segment::Enum(enum_name.name.clone())), enum_name);
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, path: &Path, block: &Block) {
+    for field in &block.fields {
+        visitor.visit_block_field(&path.join(Segment::Field(field.name.clone())), field);
+    }
+}
+
+pub fn walk_bitfield<V: Visitor + ?Sized>(visitor: &mut V, path: &Path, bitfield: &Bitfield) {
+    for field in &bitfield.fields {
+        visitor.visit_bitfield_field(&path.join(Segment::Field(field.name.clone())), field);
+    }
+}
+
+pub fn walk_enum<V: Visitor + ?Sized>(visitor: &mut V, path: &Path, enum_name: &Enum) {
+    for variant in &enum_name.variants {
+        visitor.visit_variant(&path.join(Segment::Variant(variant.name.clone())), variant);
+    }
+}
+
+/// Mutable traversal of the IR, see [`Visitor`].
+pub trait VisitorMut {
+    fn visit_multi_chip_mut(&mut self, multi: &mut MultiChip) {
+        walk_multi_chip_mut(self, multi)
+    }
+
+    fn visit_chip_mut(&mut self, path: &Path, chip: &mut Chip) {
+        walk_chip_mut(self, path, chip)
+    }
+
+    fn visit_peripheral_mut(&mut self, _path: &Path, _peripheral: &mut chip::Peripheral) {}
+
+    fn visit_cm_reg_mut(&mut self, _path: &Path, _cm_reg: &mut chip::cm_ext::CmReg) {}
+
+    fn visit_csr_mut(&mut self, _path: &Path, _csr: &mut chip::riscv_ext::Csr) {}
+
+    fn visit_module_mut(&mut self, path: &Path, module: &mut Module) {
+        walk_module_mut(self, path, module)
+    }
+
+    fn visit_block_mut(&mut self, path: &Path, block: &mut Block) {
+        walk_block_mut(self, path, block)
+    }
+
+    fn visit_block_field_mut(&mut self, _path: &Path, _field: &mut block::Field) {}
+
+    fn visit_bitfield_mut(&mut self, path: &Path, bitfield: &mut Bitfield) {
+        walk_bitfield_mut(self, path, bitfield)
+    }
+
+    fn visit_bitfield_field_mut(&mut self, _path: &Path, _field: &mut bitfield::Field) {}
+
+    fn visit_enum_mut(&mut self, path: &Path, enum_name: &mut Enum) {
+        walk_enum_mut(self, path, enum_name)
+    }
+
+    fn visit_variant_mut(&mut self, _path: &Path, _variant: &mut enum_name::Variant) {}
+}
+
+pub fn walk_multi_chip_mut<V: VisitorMut + ?Sized>(visitor: &mut V, multi: &mut MultiChip) {
+    let path = Path::new();
+    for chip in &mut multi.chips {
+        visitor.visit_chip_mut(&path.join(Segment::Chip(chip.name.clone())), chip);
+    }
+    for module in &mut multi.modules {
+        visitor.visit_module_mut(&path.join(module_segment(module)), module);
+    }
+}
+
+pub fn walk_chip_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &Path, chip: &mut Chip) {
+    for peripheral in &mut chip.peripherals {
+        let path = path.join(Segment::Peripheral(peripheral.name.clone()));
+        visitor.visit_peripheral_mut(&path, peripheral);
+    }
+    for cm_reg in chip
+        .cm_ext
+        .iter_mut()
+        .flat_map(|cm_ext| &mut cm_ext.cm_regs)
+    {
+        let path = path.join(Segment::CmReg(cm_reg.name.clone()));
+        visitor.visit_cm_reg_mut(&path, cm_reg);
+    }
+    for csr in chip
+        .riscv_ext
+        .iter_mut()
+        .flat_map(|riscv_ext| &mut riscv_ext.csrs)
+    {
+        let path = path.join(Segment::Csr(csr.name.clone()));
+        visitor.visit_csr_mut(&path, csr);
+    }
+}
+
+pub fn walk_module_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &Path, module: &mut Module) {
+    for block in &mut module.blocks {
+        visitor.visit_block_mut(&path.join(Segment::Block(block.name.clone())), block);
+    }
+    for bitfield in &mut module.bitfields {
+        let path = path.join(Segment::Bitfield(bitfield.name.clone()));
+        visitor.visit_bitfield_mut(&path, bitfield);
+    }
+    for enum_name in &mut module.enums {
+        let path = path.join(Segment::Enum(enum_name.name.clone()));
+        visitor.visit_enum_mut(&path, enum_name);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &Path, block: &mut Block) {
+    for field in &mut block.fields {
+        visitor.visit_block_field_mut(&path.join(Segment::Field(field.name.clone())), field);
+    }
+}
+
+pub fn walk_bitfield_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    path: &Path,
+    bitfield: &mut Bitfield,
+) {
+    for field in &mut bitfield.fields {
+        let path = path.join(Segment::Field(field.name.clone()));
+        visitor.visit_bitfield_field_mut(&path, field);
+    }
+}
+
+pub fn walk_enum_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &Path, enum_name: &mut Enum) {
+    for variant in &mut enum_name.variants {
+        let path = path.join(Segment::Variant(variant.name.clone()));
+        visitor.visit_variant_mut(&path, variant);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn multi() -> MultiChip {
+        serde_json::from_value(json!({
+            "chips": [{
+                "name": "F103",
+                "peripherals": [{ "name": "TIM2", "module": "tim", "address": 0, "block_name": "Tim" }],
+                "imports": [{ "name": "tim" }],
+                "cm_ext": {
+                    "cm_regs": [{
+                        "name": "CONTROL",
+                        "module": "tim",
+                        "reg_name": "control",
+                        "access": "rw",
+                        "bitfield_name": "Cr1",
+                    }],
+                },
+                "riscv_ext": {
+                    "csrs": [{
+                        "name": "MSTATUS",
+                        "module": "tim",
+                        "number": 0x300,
+                        "access": "rw",
+                        "bitfield_name": "Cr1",
+                    }],
+                },
+            }],
+            "modules": [{
+                "name": "tim",
+                "version": "v1",
+                "blocks": [{
+                    "name": "Tim",
+                    "fields": [{ "name": "CR1", "byte_offset": 0, "access": "rw", "bitfield_name": "Cr1" }],
+                }],
+                "bitfields": [{
+                    "name": "Cr1",
+                    "bit_size": 32,
+                    "fields": [{ "name": "CEN", "bit_offset": 0, "bit_size": 1 }],
+                }],
+                "enums": [{
+                    "name": "Dir",
+                    "bit_size": 1,
+                    "variants": [{ "name": "UP", "value": 0 }],
+                }],
+            }],
+        }))
+        .unwrap()
+    }
+
+    const EXPECTED: &[&str] = &[
+        "chip F103",
+        "chip F103 > peripheral TIM2",
+        "chip F103 > cm_reg CONTROL",
+        "chip F103 > csr MSTATUS",
+        "module tim (v1)",
+        "module tim (v1) > block Tim",
+        "module tim (v1) > block Tim > field CR1",
+        "module tim (v1) > bitfield Cr1",
+        "module tim (v1) > bitfield Cr1 > field CEN",
+        "module tim (v1) > enum Dir",
+        "module tim (v1) > enum Dir > variant UP",
+    ];
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Recorder {
+        fn record(&mut self, path: &Path) {
+            self.0.push(path.to_string());
+        }
+    }
+
+    impl Visitor for Recorder {
+        fn visit_chip(&mut self, path: &Path, chip: &Chip) {
+            self.record(path);
+            walk_chip(self, path, chip)
+        }
+
+        fn visit_peripheral(&mut self, path: &Path, _peripheral: &chip::Peripheral) {
+            self.record(path);
+        }
+
+        fn visit_cm_reg(&mut self, path: &Path, _cm_reg: &chip::cm_ext::CmReg) {
+            self.record(path);
+        }
+
+        fn visit_csr(&mut self, path: &Path, _csr: &chip::riscv_ext::Csr) {
+            self.record(path);
+        }
+
+        fn visit_module(&mut self, path: &Path, module: &Module) {
+            self.record(path);
+            walk_module(self, path, module)
+        }
+
+        fn visit_block(&mut self, path: &Path, block: &Block) {
+            self.record(path);
+            walk_block(self, path, block)
+        }
+
+        fn visit_block_field(&mut self, path: &Path, _field: &block::Field) {
+            self.record(path);
+        }
+
+        fn visit_bitfield(&mut self, path: &Path, bitfield: &Bitfield) {
+            self.record(path);
+            walk_bitfield(self, path, bitfield)
+        }
+
+        fn visit_bitfield_field(&mut self, path: &Path, _field: &bitfield::Field) {
+            self.record(path);
+        }
+
+        fn visit_enum(&mut self, path: &Path, enum_name: &Enum) {
+            self.record(path);
+            walk_enum(self, path, enum_name)
+        }
+
+        fn visit_variant(&mut self, path: &Path, _variant: &enum_name::Variant) {
+            self.record(path);
+        }
+    }
+
+    impl VisitorMut for Recorder {
+        fn visit_chip_mut(&mut self, path: &Path, chip: &mut Chip) {
+            self.record(path);
+            walk_chip_mut(self, path, chip)
+        }
+
+        fn visit_peripheral_mut(&mut self, path: &Path, _peripheral: &mut chip::Peripheral) {
+            self.record(path);
+        }
+
+        fn visit_cm_reg_mut(&mut self, path: &Path, _cm_reg: &mut chip::cm_ext::CmReg) {
+            self.record(path);
+        }
+
+        fn visit_csr_mut(&mut self, path: &Path, _csr: &mut chip::riscv_ext::Csr) {
+            self.record(path);
+        }
+
+        fn visit_module_mut(&mut self, path: &Path, module: &mut Module) {
+            self.record(path);
+            walk_module_mut(self, path, module)
+        }
+
+        fn visit_block_mut(&mut self, path: &Path, block: &mut Block) {
+            self.record(path);
+            walk_block_mut(self, path, block)
+        }
+
+        fn visit_block_field_mut(&mut self, path: &Path, _field: &mut block::Field) {
+            self.record(path);
+        }
+
+        fn visit_bitfield_mut(&mut self, path: &Path, bitfield: &mut Bitfield) {
+            self.record(path);
+            walk_bitfield_mut(self, path, bitfield)
+        }
+
+        fn visit_bitfield_field_mut(&mut self, path: &Path, _field: &mut bitfield::Field) {
+            self.record(path);
+        }
+
+        fn visit_enum_mut(&mut self, path: &Path, enum_name: &mut Enum) {
+            self.record(path);
+            walk_enum_mut(self, path, enum_name)
+        }
+
+        fn visit_variant_mut(&mut self, path: &Path, _variant: &mut enum_name::Variant) {
+            self.record(path);
+        }
+    }
+
+    #[test]
+    fn visit_every_node() {
+        let mut recorder = Recorder::default();
+        recorder.visit_multi_chip(&multi());
+        assert_eq!(recorder.0, EXPECTED);
+    }
+
+    #[test]
+    fn visit_every_node_mut() {
+        let mut recorder = Recorder::default();
+        recorder.visit_multi_chip_mut(&mut multi());
+        assert_eq!(recorder.0, EXPECTED);
+    }
+
+    #[test]
+    fn path_accessors() {
+        let path = Path::new()
+            .join(Segment::Module {
+                name: "tim".into(),
+                version: Some("v1".into()),
+            })
+            .join(Segment::Block("Tim".into()));
+
+        assert_eq!(path.module(), Some(("tim", Some("v1"))));
+        assert_eq!(path.chip(), None);
+        assert_eq!(path.last(), Some(&Segment::Block("Tim".into())));
+
+        let path = Path::new()
+            .join(Segment::Chip("F103".into()))
+            .join(Segment::Peripheral("TIM2".into()));
+
+        assert_eq!(path.chip(), Some("F103"));
+        assert_eq!(path.module(), None);
+    }
+}